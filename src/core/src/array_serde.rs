@@ -0,0 +1,29 @@
+//! Serde for byte arrays longer than the 32 elements serde handles by
+//! itself, as `#[serde(with = "nesemu_core::array_serde")]`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<S>(array: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    array.serialize(serializer)
+}
+
+pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let vec: Vec<u8> = Vec::deserialize(deserializer)?;
+    if vec.len() == N {
+        let mut array = [0u8; N];
+        array.copy_from_slice(&vec);
+        Ok(array)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "Expected array of length {}, found {}",
+            N,
+            vec.len()
+        )))
+    }
+}
@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+pub mod array_serde;
+
 pub trait Read {
     fn read(&self, addr: u16, _read_only: bool) -> u8;
 }
@@ -5,3 +9,107 @@ pub trait Read {
 pub trait Write {
     fn write(&mut self, addr: u16, data: u8) -> ();
 }
+
+/// How the two physical nametables are laid out over the four logical
+/// ones the PPU can address. Decided by the cartridge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+
+/// Which console the timing follows. Famiclones like the Dendy run PAL
+/// speed video with NTSC style CPU and APU timing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    /// The CPU clock in Hz.
+    pub fn cpu_clock(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+
+    /// PPU dots per CPU cycle, as a fraction: 3 everywhere but PAL, which
+    /// has 3.2.
+    pub fn dots_per_cpu_cycle(&self) -> (u32, u32) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The line vblank starts on. The Dendy keeps NTSC's 20 lines of vblank
+    /// and pads the extra lines out before it instead.
+    pub fn vblank_line(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Only the NTSC PPU drops a dot on odd frames.
+    pub fn skips_odd_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    /// The CPU cycles on which the APU frame counter steps, in 4 step and
+    /// 5 step mode. The last entry is when the sequence starts over.
+    pub fn frame_counter_steps(&self, five_step: bool) -> [u32; 6] {
+        match (self, five_step) {
+            (Region::Pal, false) => [8313, 16627, 24939, 33252, 33253, 33254],
+            (Region::Pal, true) => [8313, 16627, 24939, 33253, 41565, 41566],
+            (_, false) => [7457, 14913, 22371, 29828, 29829, 29830],
+            (_, true) => [7457, 14913, 22371, 29829, 37281, 37282],
+        }
+    }
+
+    /// Noise channel timer periods, in CPU cycles.
+    pub fn noise_periods(&self) -> [u16; 16] {
+        match self {
+            Region::Pal => [
+                4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+            ],
+            _ => [
+                4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+            ],
+        }
+    }
+
+    /// DMC timer periods, in CPU cycles.
+    pub fn dmc_periods(&self) -> [u16; 16] {
+        match self {
+            Region::Pal => [
+                398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+            ],
+            _ => [
+                428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+            ],
+        }
+    }
+}
@@ -186,6 +186,7 @@ impl<Bus: Read + Write> CPU<Bus> {
         let mut file = OpenOptions::new()
             .write(true)
             .append(true)
+            .create(true)
             .open("log.txt")
             .unwrap();
         if let Err(e) = writeln!(file, "{}", m) {
@@ -226,6 +227,40 @@ impl<Bus: Read + Write> CPU<Bus> {
 
         self.cycles = 0;
     }
+
+    /// Maskable interrupt request, ignored while the I flag is set.
+    pub fn irq(&mut self) {
+        if self.get_flag(I) == 0 {
+            self.interrupt(0xFFFE);
+            self.cycles = 7;
+        }
+    }
+
+    /// Non-maskable interrupt, e.g. the PPU entering vblank.
+    pub fn nmi(&mut self) {
+        self.interrupt(0xFFFA);
+        self.cycles = 8;
+    }
+
+    fn interrupt(&mut self, vector: u16) {
+        self.write(
+            0x0100 + self.stk_ptr as u16,
+            ((self.pgrm_ctr >> 8) & 0xFF) as u8,
+        );
+        self.stk_ptr = self.stk_ptr.wrapping_sub(1);
+        self.write(0x0100 + self.stk_ptr as u16, (self.pgrm_ctr & 0xFF) as u8);
+        self.stk_ptr = self.stk_ptr.wrapping_sub(1);
+
+        self.set_flag(B, false);
+        self.set_flag(U, true);
+        self.write(0x0100 + self.stk_ptr as u16, self.status);
+        self.stk_ptr = self.stk_ptr.wrapping_sub(1);
+        self.set_flag(I, true);
+
+        let low = self.read(vector, false) as u16;
+        let hi = self.read(vector + 1, false) as u16;
+        self.pgrm_ctr = (hi << 8) | low;
+    }
 }
 
 impl<Bus: Read + Write> CPU<Bus> {
@@ -246,6 +281,7 @@ impl<Bus: Read + Write> CPU<Bus> {
             logger: OpenOptions::new()
                 .write(true)
                 .append(true)
+                .create(true)
                 .open("log.txt")
                 .unwrap(),
         }
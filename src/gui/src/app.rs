@@ -1,17 +1,36 @@
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
 
-use egui::{CentralPanel, Grid, ScrollArea, Ui};
+use std::time::Duration;
 
+use egui::{CentralPanel, Grid, ProgressBar, ScrollArea, Ui};
+
+use nesemu::movie::COMMAND_FDS_INSERT;
+use nesemu::nsf::NsfPlayer;
 use nesemu::Nes;
+use nesemu_core::Region;
 use nesemu_cpu::cpu::{CpuDebugInfo, FlagData};
 
+use crate::audio_panel::AudioPanel;
+use crate::controls::Controls;
+use crate::display::GameDisplay;
+use crate::event_viewer::EventViewer;
+use crate::movie_panel::MoviePanel;
+use crate::palette_settings::PaletteSettings;
+use crate::ppu_viewer::PpuViewer;
 use crate::GuiMessage;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 pub struct NesemuGui {
     sender: Sender<GuiMessage>,
     nes_ref: Arc<RwLock<Nes>>,
+    palette: PaletteSettings,
+    display: GameDisplay,
+    ppu_viewer: PpuViewer,
+    event_viewer: EventViewer,
+    audio_panel: AudioPanel,
+    movie_panel: MoviePanel,
+    controls: Controls,
 }
 
 //impl Default for NesemuGui {
@@ -23,7 +42,7 @@ pub struct NesemuGui {
 impl NesemuGui {
     /// Called once before the first frame.
     pub fn new(
-        _cc: &eframe::CreationContext<'_>,
+        cc: &eframe::CreationContext<'_>,
         gui_tx: Sender<GuiMessage>,
         nes_ref: Arc<RwLock<Nes>>,
     ) -> Self {
@@ -39,12 +58,36 @@ impl NesemuGui {
         NesemuGui {
             sender: gui_tx,
             nes_ref,
+            palette: PaletteSettings::default(),
+            display: GameDisplay::default(),
+            ppu_viewer: PpuViewer::default(),
+            event_viewer: EventViewer::default(),
+            audio_panel: AudioPanel::default(),
+            movie_panel: MoviePanel::default(),
+            controls: Controls::load(cc.storage),
         }
     }
 }
 
 impl eframe::App for NesemuGui {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.display.handle_keys(ctx);
+        self.controls.show(ctx);
+        if let Ok(mut emu) = self.nes_ref.try_write() {
+            self.controls.update(ctx, &mut emu, self.display.pointer());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if _frame.info().window_info.fullscreen != self.display.fullscreen {
+            _frame.set_fullscreen(self.display.fullscreen);
+        }
+        if self.display.fullscreen {
+            // nothing but the game
+            CentralPanel::default()
+                .frame(egui::Frame::none().fill(egui::Color32::BLACK))
+                .show(ctx, |ui| self.show_game(ui));
+            return;
+        }
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
 
@@ -59,6 +102,24 @@ impl eframe::App for NesemuGui {
                     ui.add_space(16.0);
                 }
 
+                ui.menu_button("Region", |ui| {
+                    if let Ok(mut emu) = self.nes_ref.try_write() {
+                        create_region_menu(ui, &mut emu);
+                    }
+                });
+                ui.add_space(16.0);
+
+                ui.menu_button("Display", |ui| self.display.show_settings(ui));
+                ui.add_space(16.0);
+
+                ui.menu_button("Input", |ui| {
+                    if ui.button("Controls...").clicked() {
+                        self.controls.open = true;
+                        ui.close_menu();
+                    }
+                });
+                ui.add_space(16.0);
+
                 egui::widgets::global_dark_light_mode_buttons(ui);
             });
         });
@@ -78,11 +139,87 @@ impl eframe::App for NesemuGui {
                 }
             }
         });
+
+        egui::Window::new("Game")
+            .resizable(true)
+            .default_size([512., 448.])
+            .show(ctx, |ui| self.show_game(ui));
+        egui::Window::new("Palette").show(ctx, |ui| self.palette.show(ui));
+        if let Ok(emu) = self.nes_ref.try_read() {
+            let ppu = emu.ppu.read().unwrap();
+            self.ppu_viewer.show(ctx, &ppu, &self.palette.palette);
+            let bus = emu.bus.read().unwrap();
+            let mut events = bus.events.lock().unwrap();
+            self.event_viewer
+                .show(ctx, &mut events, emu.region().scanlines());
+        }
+
+        if let Ok(mut emu) = self.nes_ref.try_write() {
+            self.audio_panel.show(ctx, &mut emu);
+            self.movie_panel.show(ctx, &mut emu);
+        }
+
+        let mut nsf_track = None;
+        if let Ok(emu) = self.nes_ref.try_read() {
+            if let Some(player) = &emu.nsf {
+                egui::Window::new("NSF Player").show(ctx, |ui| {
+                    nsf_track = create_nsf_player_panel(ui, player);
+                });
+            }
+        }
+        if let Some(track) = nsf_track {
+            if let Ok(mut emu) = self.nes_ref.try_write() {
+                emu.select_nsf_track(track);
+            }
+        }
+
+        let mut disk_action = None;
+        if let Ok(emu) = self.nes_ref.try_read() {
+            if let Some(sides) = emu.fds_side_count() {
+                let current = emu.fds_current_side();
+                egui::Window::new("Disk System").show(ctx, |ui| {
+                    disk_action = create_disk_system_panel(ui, sides, current);
+                });
+            }
+        }
+        if let Some(action) = disk_action {
+            if let Ok(mut emu) = self.nes_ref.try_write() {
+                match action {
+                    // through commands, so movies record the disk changes
+                    DiskAction::Insert(side) => emu.fds_change_side(side),
+                    DiskAction::Eject => {
+                        if emu.fds_current_side().is_some() {
+                            emu.queue_commands(COMMAND_FDS_INSERT);
+                        }
+                    }
+                    DiskAction::Save => {
+                        if let Err(e) = emu.save_fds_disk() {
+                            log::error!("saving the disk failed: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }
     }
     // Called by the frame work to save state before shutdown.
-    //fn save(&mut self, storage: &mut dyn eframe::Storage) {
-    //    eframe::set_value(storage, eframe::APP_KEY, self);
-    //}
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.controls.save(storage);
+    }
+}
+
+impl NesemuGui {
+    fn show_game(&mut self, ui: &mut Ui) {
+        if let Ok(emu) = self.nes_ref.try_read() {
+            let ppu = emu.ppu.read().unwrap();
+            self.display.show(
+                ui,
+                &ppu.frame,
+                ppu.frame_count,
+                &self.palette.palette,
+                self.palette.ntsc.as_ref(),
+            );
+        }
+    }
 }
 
 fn create_ram_panel<T: std::fmt::Debug>(ui: &mut Ui, title: &str, array: &[T]) {
@@ -210,3 +347,122 @@ fn create_cpu_debug_panel(ui: &mut Ui, info: &CpuDebugInfo) {
     });
     ui.add_space(16.);
 }
+
+fn create_region_menu(ui: &mut Ui, emu: &mut Nes) {
+    let current = emu.region();
+    let mut setting = emu.region_setting();
+    ui.radio_value(&mut setting, None, format!("Auto ({:?})", current));
+    ui.radio_value(&mut setting, Some(Region::Ntsc), "NTSC");
+    ui.radio_value(&mut setting, Some(Region::Pal), "PAL");
+    ui.radio_value(&mut setting, Some(Region::Dendy), "Dendy");
+    if setting != emu.region_setting() {
+        emu.set_region(setting);
+    }
+}
+
+/// Returns the track the user picked, if any.
+fn create_nsf_player_panel(ui: &mut Ui, player: &NsfPlayer) -> Option<u8> {
+    let nsf = &player.nsf;
+    let mut selected = None;
+
+    ui.heading(&nsf.title);
+    ui.label(&nsf.artist);
+    ui.label(&nsf.copyright);
+    if let Some(ripper) = &nsf.ripper {
+        ui.label(format!("Ripped by {}", ripper));
+    }
+    let chips = nsf.expansion.names();
+    if !chips.is_empty() {
+        ui.label(format!("Expansion audio: {}", chips.join(", ")));
+    }
+    let unsupported = nsf.expansion.unsupported_names();
+    if !unsupported.is_empty() {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!("Not emulated, so silent: {}", unsupported.join(", ")),
+        );
+    }
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        if ui.button("⏮").clicked() && player.track > 0 {
+            selected = Some(player.track - 1);
+        }
+        ui.label(format!("Track {} / {}", player.track + 1, nsf.total_songs));
+        if ui.button("⏭").clicked() {
+            selected = Some(player.track + 1);
+        }
+    });
+    if let Some(name) = nsf.track_name(player.track) {
+        ui.label(name);
+    }
+
+    let length = nsf.track_length(player.track);
+    let elapsed = player.elapsed();
+    let progress = (elapsed.as_secs_f32() / length.as_secs_f32()).min(1.);
+    let text = format!("{} / {}", format_time(elapsed), format_time(length));
+    ui.add(ProgressBar::new(progress).text(text));
+
+    ui.push_id("nsf-tracks", |ui| {
+        ScrollArea::vertical().max_height(120.).show(ui, |ui| {
+            for track in 0..nsf.total_songs {
+                let name = nsf
+                    .track_name(track)
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("Track {}", track + 1));
+                if ui.selectable_label(track == player.track, name).clicked() {
+                    selected = Some(track);
+                }
+            }
+        })
+    });
+
+    // move on by itself once the fade out is done
+    if selected.is_none() && player.finished() && player.track + 1 < nsf.total_songs {
+        selected = Some(player.track + 1);
+    }
+    selected
+}
+
+enum DiskAction {
+    Insert(usize),
+    Eject,
+    Save,
+}
+
+fn create_disk_system_panel(ui: &mut Ui, sides: usize, current: Option<usize>) -> Option<DiskAction> {
+    let mut action = None;
+    match current {
+        Some(side) => ui.label(format!("Disk {} side {}", side / 2 + 1, side_name(side))),
+        None => ui.label("No disk inserted"),
+    };
+    ui.horizontal_wrapped(|ui| {
+        for side in 0..sides {
+            let name = format!("{}{}", side / 2 + 1, side_name(side));
+            if ui.selectable_label(current == Some(side), name).clicked() {
+                action = Some(DiskAction::Insert(side));
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Eject").clicked() {
+            action = Some(DiskAction::Eject);
+        }
+        if ui.button("Save").clicked() {
+            action = Some(DiskAction::Save);
+        }
+    });
+    action
+}
+
+fn side_name(side: usize) -> &'static str {
+    if side % 2 == 0 {
+        "A"
+    } else {
+        "B"
+    }
+}
+
+fn format_time(time: Duration) -> String {
+    format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+}
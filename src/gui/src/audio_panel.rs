@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use egui::{Color32, Context, Pos2, Sense, Slider, Stroke, Ui, Vec2};
+
+use nesemu::audio::wav::WavFormat;
+use nesemu::audio::{note_name, ChannelState};
+use nesemu::Nes;
+
+const SCOPE_SIZE: Vec2 = Vec2::new(256., 40.);
+/// Points drawn across the scope, about 11ms.
+const SCOPE_POINTS: usize = 512;
+const DUTIES: [&str; 4] = ["12.5%", "25%", "50%", "75%"];
+
+/// Volume, mute and an oscilloscope for every sound channel.
+pub struct AudioPanel {
+    scopes: bool,
+    recording: RecordingSettings,
+}
+
+/// Where and how the Record button writes.
+struct RecordingSettings {
+    path: String,
+    format: WavFormat,
+    per_channel: bool,
+    error: Option<String>,
+}
+
+impl Default for AudioPanel {
+    fn default() -> Self {
+        AudioPanel {
+            scopes: true,
+            recording: RecordingSettings {
+                path: "recording.wav".to_string(),
+                format: WavFormat::Pcm16,
+                per_channel: false,
+                error: None,
+            },
+        }
+    }
+}
+
+impl AudioPanel {
+    pub fn show(&mut self, ctx: &Context, emu: &mut Nes) {
+        let channels = emu.audio_channels();
+        egui::Window::new("Audio")
+            .default_open(false)
+            .show(ctx, |ui| {
+                self.recording.show(ui, emu);
+                ui.separator();
+                ui.checkbox(&mut self.scopes, "Oscilloscopes");
+                ui.separator();
+                for (n, channel) in channels.iter().enumerate() {
+                    ui.push_id(n, |ui| show_channel(ui, emu, n, channel, self.scopes));
+                    ui.separator();
+                }
+            });
+    }
+}
+
+impl RecordingSettings {
+    fn show(&mut self, ui: &mut Ui, emu: &mut Nes) {
+        let recording = emu.mixer.is_recording();
+        ui.add_enabled_ui(!recording, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("WAV file");
+                ui.text_edit_singleline(&mut self.path);
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.format, WavFormat::Pcm16, "16-bit");
+                ui.radio_value(&mut self.format, WavFormat::Float32, "32-bit float");
+                ui.checkbox(&mut self.per_channel, "A file per channel");
+            });
+        });
+        ui.horizontal(|ui| {
+            if recording {
+                if ui.button("Stop").clicked() {
+                    self.error = emu.mixer.stop_recording().err().map(|e| e.to_string());
+                }
+                ui.colored_label(Color32::RED, "Recording");
+            } else if ui.button("Record").clicked() {
+                self.error = emu
+                    .start_recording(Path::new(&self.path), self.format, self.per_channel)
+                    .err()
+                    .map(|e| e.to_string());
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+}
+
+fn show_channel(ui: &mut Ui, emu: &mut Nes, n: usize, channel: &ChannelState, scope: bool) {
+    ui.horizontal(|ui| {
+        ui.strong(channel.name);
+        if let Some(volume) = emu.mixer.volume_mut(n) {
+            ui.checkbox(&mut volume.muted, "Mute");
+            ui.add(Slider::new(&mut volume.volume, 0.0..=1.0).text("Volume"));
+        }
+    });
+    ui.monospace(describe_channel(channel));
+    match emu.mixer.scope(n) {
+        Some(history) if scope => show_scope(ui, history),
+        _ => {}
+    }
+}
+
+fn describe_channel(channel: &ChannelState) -> String {
+    let mut text = format!("Period {:4}  Volume {:3}", channel.period, channel.volume);
+    if let Some(duty) = channel.duty {
+        text += &format!("  Duty {:>5}", DUTIES[duty as usize & 0x03]);
+    }
+    if let Some(frequency) = channel.frequency {
+        text += &format!("  {:.1}Hz {}", frequency, note_name(frequency));
+    }
+    text
+}
+
+/// Draws the latest stretch of a channel, lined up on a rising edge so
+/// steady notes stand still.
+fn show_scope(ui: &mut Ui, scope: &VecDeque<f32>) {
+    let (response, painter) = ui.allocate_painter(SCOPE_SIZE, Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0., Color32::from_gray(20));
+    if scope.len() < SCOPE_POINTS {
+        return;
+    }
+
+    let latest = scope.len() - SCOPE_POINTS;
+    let (min, max) = scope
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), level| {
+            (min.min(*level), max.max(*level))
+        });
+    let middle = (min + max) / 2.;
+    let start = (1..=latest)
+        .rev()
+        .take(SCOPE_POINTS)
+        .find(|&i| scope[i - 1] < middle && scope[i] >= middle)
+        .unwrap_or(latest);
+
+    let points = (0..SCOPE_POINTS)
+        .map(|i| {
+            let x = rect.left() + i as f32 * rect.width() / (SCOPE_POINTS - 1) as f32;
+            let y = rect.bottom() - scope[start + i] * rect.height();
+            Pos2::new(x, y)
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        Stroke::new(1., Color32::from_rgb(90, 230, 120)),
+    ));
+}
@@ -0,0 +1,517 @@
+use egui::{Context, Grid, Key, Slider, Ui};
+use serde::{Deserialize, Serialize};
+
+use nesemu::input::arkanoid::{Arkanoid, FamicomArkanoid};
+use nesemu::input::controller::StandardController;
+use nesemu::input::four_score::{FourScore, HoriAdapter};
+use nesemu::input::keyboard::{FamilyKeyboard, KEYBOARD_KEYS};
+use nesemu::input::power_pad::{FamilyTrainer, PowerPad, PAD_BUTTONS};
+use nesemu::input::zapper::Zapper;
+use nesemu::input::{default_ports, Button, DefaultInput, ExpansionDevice, InputDevice, Pointer};
+use nesemu::Nes;
+
+/// Where the bindings are kept between runs.
+const CONTROLS_KEY: &str = "controls";
+
+/// Something a key can be bound to.
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Button(Button),
+    TurboA,
+    TurboB,
+}
+
+impl Action {
+    const ALL: [Action; 10] = [
+        Action::Button(Button::A),
+        Action::Button(Button::B),
+        Action::Button(Button::Select),
+        Action::Button(Button::Start),
+        Action::Button(Button::Up),
+        Action::Button(Button::Down),
+        Action::Button(Button::Left),
+        Action::Button(Button::Right),
+        Action::TurboA,
+        Action::TurboB,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Button(button) => button.name(),
+            Action::TurboA => "Turbo A",
+            Action::TurboB => "Turbo B",
+        }
+    }
+}
+
+/// A key binding being changed.
+#[derive(Clone, Copy, PartialEq)]
+enum Binding {
+    Player(usize, Action),
+    Pad(usize),
+}
+
+/// What is plugged into the second port.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Port2Device {
+    Controller,
+    /// Aimed and fired with the mouse over the game.
+    Zapper,
+    /// The Vaus paddle, turned by moving the mouse across the game and
+    /// fired with its button.
+    Arkanoid,
+    /// Played on the pad keys.
+    PowerPad,
+}
+
+/// What is in the Famicom's expansion port.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Expansion {
+    None,
+    /// The Famicom version of the Vaus paddle.
+    Arkanoid,
+    /// The Famicom Power Pad, played on the pad keys.
+    FamilyTrainer,
+    /// The Family BASIC keyboard, typed on the real one.
+    Keyboard,
+}
+
+/// A 4-player adapter, in place of whatever the settings say is in the
+/// ports, or for Hori's, in the expansion port.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FourPlayer {
+    Off,
+    FourScore,
+    Hori,
+}
+
+/// One player's keys. Buttons are in `Button::ALL` order.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PlayerBindings {
+    pub buttons: [Option<Key>; 8],
+    pub turbo_a: Option<Key>,
+    pub turbo_b: Option<Key>,
+}
+
+impl PlayerBindings {
+    fn key_mut(&mut self, action: Action) -> &mut Option<Key> {
+        match action {
+            Action::Button(button) => &mut self.buttons[button as usize],
+            Action::TurboA => &mut self.turbo_a,
+            Action::TurboB => &mut self.turbo_b,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// Players 1 and 2 are on ports 1 and 2; 3 and 4 need a 4-player
+    /// adapter.
+    pub players: [PlayerBindings; 4],
+    /// Drops both of up and down, or left and right, when held together,
+    /// which a real pad can't do and some games trip over.
+    pub no_opposing: bool,
+    /// Frames a turbo button stays pressed, then released, for.
+    pub turbo_period: u8,
+    pub port2: Port2Device,
+    /// How far from the mouse, in pixels, the Zapper picks up light.
+    pub zapper_radius: usize,
+    pub four_player: FourPlayer,
+    pub expansion: Expansion,
+    /// Power Pad buttons 1-12, for either version of the mat.
+    pub pad_keys: [Option<Key>; PAD_BUTTONS],
+    /// Plugs in what the rom's header asks for, when it says, in place of
+    /// the devices chosen here.
+    pub auto_input: bool,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        ControlSettings {
+            players: [
+                PlayerBindings {
+                    buttons: [
+                        Some(Key::F),
+                        Some(Key::D),
+                        Some(Key::S),
+                        Some(Key::Enter),
+                        Some(Key::ArrowUp),
+                        Some(Key::ArrowDown),
+                        Some(Key::ArrowLeft),
+                        Some(Key::ArrowRight),
+                    ],
+                    turbo_a: Some(Key::R),
+                    turbo_b: Some(Key::E),
+                },
+                PlayerBindings::default(),
+                PlayerBindings::default(),
+                PlayerBindings::default(),
+            ],
+            no_opposing: true,
+            turbo_period: 2,
+            port2: Port2Device::Controller,
+            zapper_radius: 3,
+            four_player: FourPlayer::Off,
+            expansion: Expansion::None,
+            pad_keys: [
+                Key::U,
+                Key::I,
+                Key::O,
+                Key::P,
+                Key::H,
+                Key::J,
+                Key::K,
+                Key::L,
+                Key::V,
+                Key::B,
+                Key::N,
+                Key::M,
+            ]
+            .map(Some),
+            auto_input: true,
+        }
+    }
+}
+
+impl ControlSettings {
+    fn key_mut(&mut self, binding: Binding) -> &mut Option<Key> {
+        match binding {
+            Binding::Player(player, action) => self.players[player].key_mut(action),
+            Binding::Pad(button) => &mut self.pad_keys[button],
+        }
+    }
+
+    /// The devices chosen here, for when the rom's own aren't used.
+    fn setup(&self) -> Setup {
+        Setup {
+            four_player: self.four_player,
+            port2: self.port2,
+            expansion: self.expansion,
+        }
+    }
+}
+
+/// The devices chosen in the settings.
+#[derive(Clone, Copy, PartialEq)]
+struct Setup {
+    four_player: FourPlayer,
+    port2: Port2Device,
+    expansion: Expansion,
+}
+
+/// Keyboard and mouse controls for the two ports, and the window to set
+/// them up in.
+#[derive(Default)]
+pub struct Controls {
+    pub settings: ControlSettings,
+    pub open: bool,
+    player: usize,
+    /// Waiting for the next key press to bind to this.
+    rebinding: Option<Binding>,
+    /// What was plugged in: the devices chosen, or those the rom asked
+    /// for instead, and the Zapper radius.
+    plugged: Option<(Setup, Option<DefaultInput>, usize)>,
+}
+
+impl Controls {
+    /// Picks up the bindings saved last time, if there are any.
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        Controls {
+            settings: storage
+                .and_then(|storage| eframe::get_value(storage, CONTROLS_KEY))
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, CONTROLS_KEY, &self.settings);
+    }
+
+    /// Plugs in the devices the settings ask for, if they changed, and
+    /// passes the keyboard and the mouse over the game on to them.
+    pub fn update(&mut self, ctx: &Context, emu: &mut Nes, pointer: Pointer) {
+        let settings = &self.settings;
+        let setup = settings.setup();
+        let rom_input = emu.default_input.filter(|_| settings.auto_input);
+        let wanted = (setup, rom_input, settings.zapper_radius);
+        if self.plugged != Some(wanted) {
+            emu.zapper_radius = settings.zapper_radius;
+            match rom_input {
+                Some(input) => emu.plug_input(input),
+                None => plug_setup(emu, setup),
+            }
+            self.plugged = Some(wanted);
+        }
+
+        let frame_count = emu.ppu.read().unwrap().frame_count;
+        for (player, buttons) in self.buttons(ctx, frame_count).into_iter().enumerate() {
+            emu.set_buttons(player, buttons);
+        }
+        emu.set_pointer(1, pointer);
+        emu.set_expansion_pointer(pointer);
+        // only a Power Pad takes these
+        emu.set_keys(1, &self.pad_keys(ctx));
+        let expansion = match rom_input {
+            Some(DefaultInput::FamilyTrainer) => Expansion::FamilyTrainer,
+            Some(DefaultInput::FamilyKeyboard) => Expansion::Keyboard,
+            Some(_) => Expansion::None,
+            None => setup.expansion,
+        };
+        match expansion {
+            Expansion::FamilyTrainer => emu.set_expansion_keys(&self.pad_keys(ctx)),
+            Expansion::Keyboard => emu.set_expansion_keys(&self.keyboard_keys(ctx)),
+            _ => {}
+        }
+    }
+
+    fn accepts_keys(&self, ctx: &Context) -> bool {
+        !ctx.wants_keyboard_input() && self.rebinding.is_none()
+    }
+
+    /// The Power Pad buttons held, from 1 to 12.
+    fn pad_keys(&self, ctx: &Context) -> [bool; PAD_BUTTONS] {
+        if !self.accepts_keys(ctx) {
+            return [false; PAD_BUTTONS];
+        }
+        ctx.input(|input| {
+            self.settings
+                .pad_keys
+                .map(|key| key.is_some_and(|key| input.key_down(key)))
+        })
+    }
+
+    /// The Family BASIC keys held, in `KEYBOARD_KEYS` order. Keys are
+    /// matched by name, with the modifiers standing in for the shifts,
+    /// Ctrl and Grph. egui has no keys for most punctuation, so only - and
+    /// ^ (on the = key) of those can be typed.
+    fn keyboard_keys(&self, ctx: &Context) -> Vec<bool> {
+        let mut held = vec![false; KEYBOARD_KEYS.len()];
+        if !self.accepts_keys(ctx) {
+            return held;
+        }
+        let mut press = |name: &str| {
+            if let Some(index) = KEYBOARD_KEYS.iter().position(|key| *key == name) {
+                held[index] = true;
+            }
+        };
+        ctx.input(|input| {
+            for key in &input.keys_down {
+                press(family_key_name(*key));
+            }
+            let modifiers = input.modifiers;
+            if modifiers.shift {
+                press("Left Shift");
+            }
+            if modifiers.ctrl {
+                press("Ctrl");
+            }
+            if modifiers.alt {
+                press("Grph");
+            }
+        });
+        held
+    }
+
+    /// The buttons held by each player, as `Button` bits. Turbo
+    /// buttons follow the emulated frame count so they keep time with the
+    /// game rather than the window.
+    fn buttons(&self, ctx: &Context, frame_count: u64) -> [u8; 4] {
+        if !self.accepts_keys(ctx) {
+            return [0; 4];
+        }
+        let settings = &self.settings;
+        let period = settings.turbo_period.max(1) as u64;
+        let turbo = (frame_count / period) & 1 == 0;
+        ctx.input(|input| {
+            let held = |key: Option<Key>| key.is_some_and(|key| input.key_down(key));
+            settings.players.clone().map(|player| {
+                let mut buttons = 0;
+                for (button, key) in Button::ALL.iter().zip(player.buttons) {
+                    if held(key) {
+                        buttons |= button.bit();
+                    }
+                }
+                if turbo && held(player.turbo_a) {
+                    buttons |= Button::A.bit();
+                }
+                if turbo && held(player.turbo_b) {
+                    buttons |= Button::B.bit();
+                }
+                if settings.no_opposing {
+                    for pair in [
+                        Button::Up.bit() | Button::Down.bit(),
+                        Button::Left.bit() | Button::Right.bit(),
+                    ] {
+                        if buttons & pair == pair {
+                            buttons &= !pair;
+                        }
+                    }
+                }
+                buttons
+            })
+        })
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        if let Some(binding) = self.rebinding {
+            let pressed = ctx.input(|input| {
+                input.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            if let Some(key) = pressed {
+                // Escape backs out without changing anything
+                if key != Key::Escape {
+                    *self.settings.key_mut(binding) = Some(key);
+                }
+                self.rebinding = None;
+            }
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Controls")
+            .open(&mut open)
+            .show(ctx, |ui| self.show_bindings(ui));
+        self.open = open;
+        if !self.open {
+            self.rebinding = None;
+        }
+    }
+
+    fn show_bindings(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            for player in 0..self.settings.players.len() {
+                let name = format!("Player {}", player + 1);
+                ui.selectable_value(&mut self.player, player, name);
+            }
+        });
+        ui.separator();
+
+        let player = self.player;
+        Grid::new("bindings").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                self.show_binding(ui, action.name(), Binding::Player(player, action));
+            }
+        });
+        ui.separator();
+
+        let settings = &mut self.settings;
+        ui.checkbox(&mut settings.no_opposing, "No opposing directions");
+        ui.add(Slider::new(&mut settings.turbo_period, 1..=8).text("Turbo frames"));
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("4 players:");
+            ui.selectable_value(&mut settings.four_player, FourPlayer::Off, "Off");
+            ui.selectable_value(
+                &mut settings.four_player,
+                FourPlayer::FourScore,
+                "Four Score",
+            );
+            ui.selectable_value(&mut settings.four_player, FourPlayer::Hori, "Hori");
+        });
+        ui.add_enabled_ui(settings.four_player == FourPlayer::Off, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Port 2:");
+                ui.selectable_value(&mut settings.port2, Port2Device::Controller, "Controller");
+                ui.selectable_value(&mut settings.port2, Port2Device::Zapper, "Zapper");
+                ui.selectable_value(&mut settings.port2, Port2Device::Arkanoid, "Arkanoid");
+                ui.selectable_value(&mut settings.port2, Port2Device::PowerPad, "Power Pad");
+            });
+        });
+        ui.add_enabled_ui(settings.four_player != FourPlayer::Hori, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Expansion:");
+                ui.selectable_value(&mut settings.expansion, Expansion::None, "None");
+                ui.selectable_value(&mut settings.expansion, Expansion::Arkanoid, "Arkanoid");
+                ui.selectable_value(
+                    &mut settings.expansion,
+                    Expansion::FamilyTrainer,
+                    "Family Trainer",
+                );
+                ui.selectable_value(&mut settings.expansion, Expansion::Keyboard, "Keyboard");
+            });
+        });
+        ui.checkbox(&mut settings.auto_input, "Devices from the rom header");
+        ui.add(Slider::new(&mut settings.zapper_radius, 0..=10).text("Zapper radius"));
+
+        ui.collapsing("Power Pad keys", |ui| {
+            Grid::new("pad_bindings").striped(true).show(ui, |ui| {
+                for button in 0..PAD_BUTTONS {
+                    let name = format!("Button {}", button + 1);
+                    self.show_binding(ui, &name, Binding::Pad(button));
+                }
+            });
+        });
+        if ui.button("Reset to defaults").clicked() {
+            self.settings = ControlSettings::default();
+        }
+    }
+
+    fn show_binding(&mut self, ui: &mut Ui, name: &str, binding: Binding) {
+        ui.label(name);
+        let key = self.settings.key_mut(binding);
+        let text = if self.rebinding == Some(binding) {
+            "Press a key..."
+        } else {
+            key.map_or("-", |key| key.name())
+        };
+        if ui.button(text).clicked() {
+            self.rebinding = Some(binding);
+        }
+        if ui.button("Clear").clicked() {
+            *key = None;
+        }
+        ui.end_row();
+    }
+}
+
+/// Plugs in the devices chosen in the settings.
+fn plug_setup(emu: &mut Nes, setup: Setup) {
+    let devices: [Box<dyn InputDevice>; 2] = match setup.four_player {
+        FourPlayer::FourScore => FourScore::pair().map(|side| Box::new(side) as _),
+        FourPlayer::Hori => default_ports(),
+        FourPlayer::Off => [
+            Box::<StandardController>::default(),
+            match setup.port2 {
+                Port2Device::Controller => Box::<StandardController>::default(),
+                Port2Device::Zapper => Box::new(Zapper::new(emu.ppu.clone(), emu.zapper_radius)),
+                Port2Device::Arkanoid => Box::<Arkanoid>::default(),
+                Port2Device::PowerPad => Box::<PowerPad>::default(),
+            },
+        ],
+    };
+    for (port, device) in devices.into_iter().enumerate() {
+        emu.plug(port, device);
+    }
+    // Hori's adapter takes the expansion port
+    let expansion: Option<Box<dyn ExpansionDevice>> = match setup.expansion {
+        _ if setup.four_player == FourPlayer::Hori => Some(Box::<HoriAdapter>::default()),
+        Expansion::None => None,
+        Expansion::Arkanoid => Some(Box::<FamicomArkanoid>::default()),
+        Expansion::FamilyTrainer => Some(Box::<FamilyTrainer>::default()),
+        Expansion::Keyboard => Some(Box::new(FamilyKeyboard::new())),
+    };
+    emu.plug_expansion(expansion);
+}
+
+/// The Family BASIC key `key` stands for, where the names differ.
+fn family_key_name(key: Key) -> &'static str {
+    match key {
+        Key::Enter => "Return",
+        Key::Escape => "Esc",
+        Key::Backspace | Key::Delete => "Del",
+        Key::Insert => "Ins",
+        Key::Home => "Clr Home",
+        Key::End => "Stop",
+        Key::Tab => "Kana",
+        Key::Minus => "-",
+        Key::PlusEquals => "^",
+        key => key.name(),
+    }
+}
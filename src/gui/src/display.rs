@@ -0,0 +1,212 @@
+use egui::{
+    Color32, ColorImage, Context, Image, Sense, Slider, TextureHandle, TextureOptions, Ui, Vec2,
+};
+
+use nesemu::input::Pointer;
+
+use nesemu_ppu::ntsc::{NtscFilter, NTSC_WIDTH};
+use nesemu_ppu::palette::Palette;
+use nesemu_ppu::ppu::{HEIGHT, WIDTH};
+
+/// NES pixels are a little wider than they are tall.
+const PIXEL_ASPECT: f32 = 8. / 7.;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scale {
+    /// The biggest whole multiple that fits.
+    Fit,
+    Fixed(u32),
+}
+
+/// Pixels cut off each edge, which TVs hid behind the bezel.
+#[derive(Clone, Copy)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+pub struct DisplaySettings {
+    pub scale: Scale,
+    pub aspect_correction: bool,
+    pub overscan: Overscan,
+    /// How much every other line gets darkened, 0 for not at all.
+    pub scanlines: f32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            scale: Scale::Fit,
+            aspect_correction: true,
+            overscan: Overscan {
+                top: 8,
+                bottom: 8,
+                left: 0,
+                right: 0,
+            },
+            scanlines: 0.,
+        }
+    }
+}
+
+/// The game screen.
+#[derive(Default)]
+pub struct GameDisplay {
+    pub settings: DisplaySettings,
+    pub fullscreen: bool,
+    texture: Option<TextureHandle>,
+    /// The mouse over the picture, as of the last time it was drawn.
+    pointer: Pointer,
+}
+
+impl GameDisplay {
+    /// Draws the frame as big as the settings allow in the space left in
+    /// `ui`, centred.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        frame: &[u16],
+        frame_count: u64,
+        palette: &Palette,
+        ntsc: Option<&NtscFilter>,
+    ) {
+        let image = render(frame, frame_count, palette, ntsc, &self.settings);
+        let size = self.display_size(ui.available_size());
+        // the NTSC picture doesn't line up with screen pixels anyway
+        let filter = if ntsc.is_some() {
+            TextureOptions::LINEAR
+        } else {
+            TextureOptions::NEAREST
+        };
+        let texture = self
+            .texture
+            .get_or_insert_with(|| ui.ctx().load_texture("game", image.clone(), filter));
+        texture.set(image, filter);
+        let response = ui
+            .centered_and_justified(|ui| {
+                ui.add(Image::new((texture.id(), size)).sense(Sense::click()))
+            })
+            .inner;
+
+        let overscan = self.settings.overscan;
+        let shown = Vec2::new(
+            (WIDTH - overscan.left - overscan.right) as f32,
+            (HEIGHT - overscan.top - overscan.bottom) as f32,
+        );
+        self.pointer = Pointer {
+            position: response.hover_pos().map(|pos| {
+                let offset = (pos - response.rect.min) / response.rect.size() * shown;
+                (
+                    overscan.left as f32 + offset.x,
+                    overscan.top as f32 + offset.y,
+                )
+            }),
+            pressed: response.hovered() && ui.input(|input| input.pointer.primary_down()),
+        };
+    }
+
+    /// Where the mouse is over the game, for light guns and the like.
+    pub fn pointer(&self) -> Pointer {
+        self.pointer
+    }
+
+    /// The picture size in NES pixels, after cropping and aspect correction.
+    fn base_size(&self) -> Vec2 {
+        let overscan = self.settings.overscan;
+        let width = (WIDTH - overscan.left - overscan.right) as f32;
+        let height = (HEIGHT - overscan.top - overscan.bottom) as f32;
+        if self.settings.aspect_correction {
+            Vec2::new(width * PIXEL_ASPECT, height)
+        } else {
+            Vec2::new(width, height)
+        }
+    }
+
+    fn display_size(&self, available: Vec2) -> Vec2 {
+        let base = self.base_size();
+        let scale = match self.settings.scale {
+            Scale::Fit => (available.x / base.x)
+                .min(available.y / base.y)
+                .floor()
+                .max(1.),
+            Scale::Fixed(scale) => scale as f32,
+        };
+        base * scale
+    }
+
+    pub fn show_settings(&mut self, ui: &mut Ui) {
+        let settings = &mut self.settings;
+        ui.horizontal(|ui| {
+            ui.label("Scale:");
+            ui.selectable_value(&mut settings.scale, Scale::Fit, "Fit");
+            for scale in 1..=4 {
+                ui.selectable_value(
+                    &mut settings.scale,
+                    Scale::Fixed(scale),
+                    format!("{}x", scale),
+                );
+            }
+        });
+        ui.checkbox(&mut settings.aspect_correction, "8:7 pixel aspect");
+        ui.label("Overscan:");
+        let overscan = &mut settings.overscan;
+        ui.add(Slider::new(&mut overscan.top, 0..=16).text("Top"));
+        ui.add(Slider::new(&mut overscan.bottom, 0..=16).text("Bottom"));
+        ui.add(Slider::new(&mut overscan.left, 0..=16).text("Left"));
+        ui.add(Slider::new(&mut overscan.right, 0..=16).text("Right"));
+        ui.add(Slider::new(&mut settings.scanlines, 0.0..=1.0).text("Scanlines"));
+        ui.checkbox(&mut self.fullscreen, "Fullscreen (F11)");
+    }
+
+    /// F11 toggles fullscreen, Escape leaves it.
+    pub fn handle_keys(&mut self, ctx: &Context) {
+        ctx.input(|input| {
+            if input.key_pressed(egui::Key::F11) {
+                self.fullscreen = !self.fullscreen;
+            }
+            if input.key_pressed(egui::Key::Escape) {
+                self.fullscreen = false;
+            }
+        });
+    }
+}
+
+/// Colours the frame, crops the overscan and adds the scanlines, which
+/// double the height so each line gets a darker twin underneath.
+fn render(
+    frame: &[u16],
+    frame_count: u64,
+    palette: &Palette,
+    ntsc: Option<&NtscFilter>,
+    settings: &DisplaySettings,
+) -> ColorImage {
+    let (width, rgba) = match ntsc {
+        Some(filter) => (NTSC_WIDTH, filter.apply(frame, frame_count)),
+        None => (WIDTH, palette.to_rgba(frame)),
+    };
+    let overscan = settings.overscan;
+    let left = overscan.left * width / WIDTH;
+    let right = width - overscan.right * width / WIDTH;
+    let (top, bottom) = (overscan.top, HEIGHT - overscan.bottom);
+
+    let dim = 1. - settings.scanlines;
+    let doubled = settings.scanlines > 0.;
+    let mut pixels = Vec::with_capacity((right - left) * (bottom - top) * 2);
+    for y in top..bottom {
+        let row = &rgba[(y * width + left) * 4..(y * width + right) * 4];
+        pixels.extend(row.chunks(4).map(|p| Color32::from_rgb(p[0], p[1], p[2])));
+        if doubled {
+            pixels.extend(row.chunks(4).map(|p| {
+                let darken = |value: u8| (value as f32 * dim) as u8;
+                Color32::from_rgb(darken(p[0]), darken(p[1]), darken(p[2]))
+            }));
+        }
+    }
+    let height = (bottom - top) * if doubled { 2 } else { 1 };
+    ColorImage {
+        size: [right - left, height],
+        pixels,
+    }
+}
@@ -0,0 +1,147 @@
+use egui::{Color32, Context, Pos2, Rect, Sense, Ui, Vec2};
+
+use nesemu::events::{register_name, Event, EventKind, EventLog};
+use nesemu_ppu::ppu::{DOTS, HEIGHT, WIDTH};
+
+const SCALE: f32 = 2.;
+
+const KINDS: [(EventKind, &str, Color32); 6] = [
+    (
+        EventKind::PpuRead,
+        "PPU reads",
+        Color32::from_rgb(80, 160, 255),
+    ),
+    (
+        EventKind::PpuWrite,
+        "PPU writes",
+        Color32::from_rgb(255, 90, 90),
+    ),
+    (
+        EventKind::MapperWrite,
+        "Mapper writes",
+        Color32::from_rgb(255, 200, 60),
+    ),
+    (EventKind::Nmi, "NMI", Color32::from_rgb(200, 90, 255)),
+    (EventKind::Irq, "IRQ", Color32::from_rgb(90, 230, 120)),
+    (EventKind::SpriteZeroHit, "Sprite 0 hit", Color32::WHITE),
+];
+
+/// Plots the last frame's events at the dot they happened on.
+pub struct EventViewer {
+    /// Which of `KINDS` are shown.
+    shown: [bool; KINDS.len()],
+}
+
+impl Default for EventViewer {
+    fn default() -> Self {
+        EventViewer {
+            shown: [true; KINDS.len()],
+        }
+    }
+}
+
+impl EventViewer {
+    pub fn show(&mut self, ctx: &Context, log: &mut EventLog, scanlines: u16) {
+        egui::Window::new("Event Viewer")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut log.enabled, "Record events");
+                ui.horizontal_wrapped(|ui| {
+                    for (shown, (_, name, colour)) in self.shown.iter_mut().zip(KINDS) {
+                        ui.checkbox(shown, egui::RichText::new(name).color(colour));
+                    }
+                });
+                self.show_grid(ui, log.last_frame(), scanlines);
+            });
+    }
+
+    fn show_grid(&self, ui: &mut Ui, events: &[Event], scanlines: u16) {
+        let size = Vec2::new(DOTS as f32, scanlines as f32) * SCALE;
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let origin = response.rect.min;
+        let area = |dot: f32, line: f32, width: f32, height: f32| {
+            Rect::from_min_size(
+                origin + Vec2::new(dot, line) * SCALE,
+                Vec2::new(width, height) * SCALE,
+            )
+        };
+
+        painter.rect_filled(response.rect, 0., Color32::from_gray(20));
+        painter.rect_filled(
+            area(1., 0., WIDTH as f32, HEIGHT as f32),
+            0.,
+            Color32::from_gray(45),
+        );
+
+        let shown: Vec<&Event> = events
+            .iter()
+            .filter(|event| self.kind_shown(event.kind))
+            .collect();
+        for event in &shown {
+            let rect = area(event.dot as f32, event.scanline as f32, 1., 1.).expand(1.);
+            painter.rect_filled(rect, 0., kind_colour(event.kind));
+        }
+
+        let Some(pos) = response.hover_pos() else {
+            return;
+        };
+        let dot = (pos.x - origin.x) / SCALE;
+        let line = (pos.y - origin.y) / SCALE;
+        let nearest = shown.iter().min_by_key(|event| {
+            let distance =
+                Pos2::new(event.dot as f32, event.scanline as f32).distance(Pos2::new(dot, line));
+            (distance * 16.) as u32
+        });
+        let text = match nearest {
+            Some(event)
+                if (event.dot as f32 - dot).abs() <= 3.
+                    && (event.scanline as f32 - line).abs() <= 3. =>
+            {
+                describe_event(event)
+            }
+            _ => format!("Scanline {}, dot {}", line as u16, dot as u16),
+        };
+        response.on_hover_text(text);
+    }
+
+    fn kind_shown(&self, kind: EventKind) -> bool {
+        KINDS
+            .iter()
+            .zip(self.shown)
+            .any(|((k, _, _), shown)| *k == kind && shown)
+    }
+}
+
+fn kind_colour(kind: EventKind) -> Color32 {
+    KINDS
+        .iter()
+        .find(|(k, _, _)| *k == kind)
+        .map(|(_, _, colour)| *colour)
+        .unwrap_or(Color32::GRAY)
+}
+
+fn describe_event(event: &Event) -> String {
+    let what = match event.kind {
+        EventKind::PpuRead | EventKind::PpuWrite | EventKind::MapperWrite => {
+            let access = if event.kind == EventKind::PpuRead {
+                "read"
+            } else {
+                "write"
+            };
+            match register_name(event.addr) {
+                Some(name) => format!(
+                    "{} (${:04X}) {} ${:02X}",
+                    name, event.addr, access, event.value
+                ),
+                None => format!("${:04X} {} ${:02X}", event.addr, access, event.value),
+            }
+        }
+        EventKind::Nmi => "NMI".to_string(),
+        EventKind::Irq => "IRQ".to_string(),
+        EventKind::SpriteZeroHit => "Sprite 0 hit".to_string(),
+    };
+    format!(
+        "{}\nScanline {}, dot {}\nPC ${:04X}",
+        what, event.scanline, event.dot, event.pc
+    )
+}
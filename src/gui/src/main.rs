@@ -5,7 +5,14 @@ use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 
 mod app;
+mod audio_panel;
+mod controls;
+mod display;
+mod event_viewer;
+mod movie_panel;
 mod native;
+mod palette_settings;
+mod ppu_viewer;
 mod web;
 
 fn main() {
@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use egui::{Color32, Context, Ui};
+
+use nesemu::movie::{Movie, MovieMode, COMMAND_POWER, COMMAND_RESET};
+use nesemu::Nes;
+
+/// Records the controllers to a movie file and plays movies back, with a
+/// save state slot for going back while recording.
+pub struct MoviePanel {
+    /// `.fm2` files are written for FCEUX, anything else in our own format.
+    path: String,
+    state: Option<Vec<u8>>,
+    error: Option<String>,
+}
+
+impl Default for MoviePanel {
+    fn default() -> Self {
+        MoviePanel {
+            path: "movie.fm2".to_string(),
+            state: None,
+            error: None,
+        }
+    }
+}
+
+impl MoviePanel {
+    pub fn show(&mut self, ctx: &Context, emu: &mut Nes) {
+        egui::Window::new("Movie")
+            .default_open(false)
+            .show(ctx, |ui| self.show_controls(ui, emu));
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui, emu: &mut Nes) {
+        ui.add_enabled_ui(emu.movie.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Movie file");
+                ui.text_edit_singleline(&mut self.path);
+            });
+        });
+        ui.horizontal(|ui| {
+            if emu.movie.is_some() {
+                if ui.button("Stop").clicked() {
+                    self.stop(emu);
+                }
+            } else {
+                if ui.button("Record").clicked() {
+                    self.error = emu.record_movie(None).err().map(|e| format!("{:?}", e));
+                }
+                if ui.button("Record from state").clicked() {
+                    // from the slot if there is one, otherwise from here
+                    self.error = match self.state.clone() {
+                        Some(state) => Ok(state),
+                        None => emu.save_state().map_err(Into::into),
+                    }
+                    .and_then(|state| emu.record_movie(Some(state)))
+                    .err()
+                    .map(|e| format!("{:?}", e));
+                }
+                if ui.button("Play").clicked() {
+                    self.error = Movie::load(Path::new(&self.path))
+                        .and_then(|movie| emu.play_movie(movie))
+                        .err()
+                        .map(|e| format!("{:?}", e));
+                }
+            }
+            ui.separator();
+            // recorded into the movie, when there is one
+            if ui.button("Reset").clicked() {
+                emu.queue_commands(COMMAND_RESET);
+            }
+            if ui.button("Power").clicked() {
+                emu.queue_commands(COMMAND_POWER);
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Save state").clicked() {
+                match emu.save_state() {
+                    Ok(state) => {
+                        self.state = Some(state);
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(format!("{:?}", e)),
+                }
+            }
+            // loading while recording counts a rerecord
+            let load = ui.add_enabled(self.state.is_some(), egui::Button::new("Load state"));
+            if load.clicked() {
+                if let Some(state) = &self.state {
+                    self.error = emu.load_state(state).err().map(|e| format!("{:?}", e));
+                }
+            }
+        });
+        if let Some(session) = &emu.movie {
+            let frames = session.movie.frames.len();
+            let status = match session.mode {
+                MovieMode::Recording => format!(
+                    "Recording frame {}, {} rerecords",
+                    session.frame, session.movie.rerecord_count
+                ),
+                MovieMode::Playing if session.finished() => format!("Played all {} frames", frames),
+                MovieMode::Playing => format!("Playing frame {} of {}", session.frame, frames),
+            };
+            ui.colored_label(Color32::RED, status);
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    /// Stops, saving the movie if it was being recorded.
+    fn stop(&mut self, emu: &mut Nes) {
+        let recording = emu
+            .movie
+            .as_ref()
+            .is_some_and(|session| session.mode == MovieMode::Recording);
+        let Some(movie) = emu.stop_movie() else {
+            return;
+        };
+        self.error = None;
+        if recording {
+            self.error = movie
+                .save(Path::new(&self.path))
+                .err()
+                .map(|e| format!("{:?}", e));
+        }
+    }
+}
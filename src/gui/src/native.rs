@@ -3,12 +3,10 @@
 use std::sync::{Arc, RwLock};
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use nesemu::bus::Bus;
-use nesemu::memory::CpuMemory;
 use nesemu::Nes;
-use nesemu_cpu::cpu::CPU;
+use nesemu::rom_db::{DatabaseError, RomDatabase};
 
 use crate::{create_channels, EmulatorMessage, GuiMessage};
 use crate::app::NesemuGui;
@@ -16,19 +14,30 @@ use crate::app::NesemuGui;
 pub fn run() {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
-    let ram = Arc::new(RwLock::new(CpuMemory::default()));
-    let bus = Arc::new(RwLock::new(Bus::new(ram.clone())));
-    let cpu = CPU::new(bus.clone());
-
-    let mut nes = Nes {
-        ram: ram.clone(),
-        cpu,
-        bus: bus.clone(),
+    let mut nes = Nes::new();
+    // the disk system BIOS can't be shipped, so it's looked for here
+    let bios_path = std::env::var("NESEMU_FDS_BIOS").unwrap_or_else(|_| "disksys.rom".to_string());
+    nes.fds_bios = std::fs::read(bios_path).ok();
+    // iNES 1.0 headers rarely say the region, so it's looked up in here
+    let db_path = std::env::var("NESEMU_ROM_DB").unwrap_or_else(|_| "nesdb.txt".to_string());
+    nes.rom_database = match RomDatabase::load(std::path::Path::new(&db_path)) {
+        Ok(db) => Some(db),
+        Err(DatabaseError::Io(_)) => None,
+        Err(e) => {
+            log::error!("can't read {}: {:?}", db_path, e);
+            None
+        }
     };
-    nes.load_rom("nestest.nes").expect("TODO: panic message");
-    nes.cpu.reset();
+    let rom_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "nestest.nes".to_string());
+    nes.load_rom(&rom_path).expect("TODO: panic message");
+    if nes.nsf.is_none() && nes.fds_side_count().is_none() {
+        nes.cpu.reset();
+    }
 
     let nes_ref = Arc::new(RwLock::new(nes));
+    let save_ref = nes_ref.clone();
 
 
     // Set up communication channels between emulator and GUI
@@ -66,7 +75,13 @@ pub fn run() {
             Box::new(NesemuGui::new(cc, gui_tx, nes_ref))
         }),
     )
-        .expect("Failed to start GUI")
+        .expect("Failed to start GUI");
+
+    // keep whatever the game wrote to the disk
+    let saved = save_ref.read().unwrap().save_fds_disk();
+    if let Err(e) = saved {
+        log::error!("saving the disk failed: {:?}", e);
+    }
 }
 
 fn spawn_emulator_thread(
@@ -75,11 +90,18 @@ fn spawn_emulator_thread(
     _gui_tx: Receiver<GuiMessage>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || loop {
-        thread::sleep(Duration::from_millis(100));
-        let mut lock = emulator.write().unwrap();
-        lock.cpu.clock();
+        let started = Instant::now();
+        // a frame at a time, at the speed of the region's TV
+        let frame_time = {
+            let mut lock = emulator.write().unwrap();
+            lock.run_frame();
+            Duration::from_secs_f64(1.0 / lock.region().frame_rate())
+        };
         emulator_tx
             .send(EmulatorMessage::Update)
             .unwrap_or_else(|_| log::info!("sending between threads failed!!!!!!"));
+        if let Some(rest) = frame_time.checked_sub(started.elapsed()) {
+            thread::sleep(rest);
+        }
     })
 }
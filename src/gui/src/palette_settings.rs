@@ -0,0 +1,141 @@
+use egui::{Color32, Sense, Slider, Ui, Vec2};
+
+use nesemu_ppu::ntsc::NtscFilter;
+use nesemu_ppu::palette::{NtscPaletteParams, Palette};
+
+#[derive(PartialEq)]
+enum PaletteSource {
+    Default,
+    Generated,
+    File,
+}
+
+/// The palette picker: the built-in palette, one generated from NTSC
+/// parameters, or a .pal file. The NTSC filter lives here too, since it
+/// shares the picture controls.
+pub struct PaletteSettings {
+    pub palette: Palette,
+    /// Used instead of the palette when set.
+    pub ntsc: Option<NtscFilter>,
+    filter: NtscFilter,
+    source: PaletteSource,
+    params: NtscPaletteParams,
+    path: String,
+    error: Option<String>,
+}
+
+impl Default for PaletteSettings {
+    fn default() -> Self {
+        PaletteSettings {
+            palette: Palette::default(),
+            ntsc: None,
+            filter: NtscFilter::default(),
+            source: PaletteSource::Default,
+            params: NtscPaletteParams::default(),
+            path: String::new(),
+            error: None,
+        }
+    }
+}
+
+impl PaletteSettings {
+    pub fn show(&mut self, ui: &mut Ui) {
+        let previous = self.params;
+        ui.horizontal(|ui| {
+            if ui
+                .radio_value(&mut self.source, PaletteSource::Default, "2C02")
+                .clicked()
+            {
+                self.palette = Palette::default();
+            }
+            if ui
+                .radio_value(&mut self.source, PaletteSource::Generated, "Generated")
+                .clicked()
+            {
+                self.palette = Palette::generate(self.params);
+            }
+            ui.radio_value(&mut self.source, PaletteSource::File, ".pal file");
+        });
+
+        match self.source {
+            PaletteSource::Default => {}
+            PaletteSource::Generated => {
+                ui.add(Slider::new(&mut self.params.hue, -30.0..=30.0).text("Hue"));
+                ui.add(Slider::new(&mut self.params.saturation, 0.0..=2.0).text("Saturation"));
+                ui.add(Slider::new(&mut self.params.contrast, 0.5..=1.5).text("Contrast"));
+                ui.add(Slider::new(&mut self.params.brightness, -0.5..=0.5).text("Brightness"));
+                ui.add(Slider::new(&mut self.params.gamma, 1.0..=3.0).text("Gamma"));
+                if ui.button("Reset").clicked() {
+                    self.params = NtscPaletteParams::default();
+                }
+                if self.params != previous {
+                    self.palette = Palette::generate(self.params);
+                }
+            }
+            PaletteSource::File => {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.path);
+                    if ui.button("Load").clicked() {
+                        self.load_file();
+                    }
+                });
+                if let Some(error) = &self.error {
+                    ui.colored_label(Color32::RED, error);
+                }
+            }
+        }
+
+        ui.separator();
+        create_swatches(ui, &self.palette);
+
+        ui.separator();
+        self.show_ntsc_filter(ui);
+    }
+
+    fn show_ntsc_filter(&mut self, ui: &mut Ui) {
+        let mut enabled = self.ntsc.is_some();
+        ui.checkbox(&mut enabled, "NTSC filter");
+        if enabled {
+            let filter = &mut self.filter;
+            ui.add(Slider::new(&mut filter.sharpness, -1.0..=1.0).text("Sharpness"));
+            ui.add(Slider::new(&mut filter.artifacts, 0.0..=1.0).text("Artifacts"));
+            ui.add(Slider::new(&mut filter.fringing, 0.0..=1.0).text("Fringing"));
+            ui.checkbox(&mut filter.merge_fields, "Merge fields");
+            filter.picture = self.params;
+            self.ntsc = Some(*filter);
+        } else {
+            self.ntsc = None;
+        }
+    }
+
+    fn load_file(&mut self) {
+        let loaded = std::fs::read(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| Palette::from_pal(&bytes).map_err(|e| format!("{:?}", e)));
+        match loaded {
+            Ok(palette) => {
+                self.palette = palette;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+/// The 64 base colours, in the usual 16x4 layout.
+fn create_swatches(ui: &mut Ui, palette: &Palette) {
+    let size = Vec2::splat(14.);
+    for row in 0..4 {
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing = Vec2::splat(1.);
+            for column in 0..16 {
+                let index = row * 16 + column;
+                let [r, g, b] = palette.rgb(index);
+                let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 0., Color32::from_rgb(r, g, b));
+                response.on_hover_text(format!("${:02X}", index));
+            }
+        });
+    }
+}
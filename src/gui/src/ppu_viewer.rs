@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use egui::{
+    Color32, ColorImage, Context, Grid, Image, Pos2, Rect, Response, ScrollArea, Sense, Stroke,
+    TextureHandle, TextureOptions, Ui, Vec2,
+};
+
+use nesemu::cartridge::Cartridge;
+use nesemu::screenshot::Screenshot;
+use nesemu_ppu::palette::Palette;
+use nesemu_ppu::ppu::{HEIGHT, PPU, WIDTH};
+use nesemu_ppu::viewer::{
+    SpriteInfo, NAMETABLE_HEIGHT, NAMETABLE_WIDTH, PATTERN_HEIGHT, PATTERN_WIDTH,
+};
+
+/// Sprites are laid out 8 to a row, each in an 8x16 cell.
+const SPRITE_COLUMNS: usize = 8;
+const SPRITE_ATLAS_WIDTH: usize = SPRITE_COLUMNS * 8;
+const SPRITE_ATLAS_HEIGHT: usize = 64 / SPRITE_COLUMNS * 16;
+
+/// Debug windows showing what is in the PPU's memory.
+#[derive(Default)]
+pub struct PpuViewer {
+    /// Which of the 8 palettes the pattern tables are drawn with.
+    pattern_palette: u8,
+    textures: HashMap<&'static str, TextureHandle>,
+    /// What happened to the last PNG export.
+    status: Option<String>,
+}
+
+impl PpuViewer {
+    pub fn show(&mut self, ctx: &Context, ppu: &PPU<Cartridge>, palette: &Palette) {
+        egui::Window::new("Pattern Tables")
+            .default_open(false)
+            .show(ctx, |ui| self.show_pattern_tables(ui, ppu, palette));
+        egui::Window::new("Nametables")
+            .default_open(false)
+            .show(ctx, |ui| self.show_nametables(ui, ppu, palette));
+        egui::Window::new("Sprites")
+            .default_open(false)
+            .show(ctx, |ui| self.show_sprites(ui, ppu, palette));
+        egui::Window::new("Palette RAM")
+            .default_open(false)
+            .show(ctx, |ui| self.show_palette_ram(ui, ppu, palette));
+    }
+
+    fn show_pattern_tables(&mut self, ui: &mut Ui, ppu: &PPU<Cartridge>, palette: &Palette) {
+        ui.horizontal(|ui| {
+            ui.label("Palette:");
+            for n in 0..8 {
+                ui.selectable_value(&mut self.pattern_palette, n, n.to_string());
+            }
+        });
+        let rgba = palette.to_rgba(&ppu.pattern_table_view(self.pattern_palette));
+        let response = self.show_image(
+            ui,
+            "pattern_tables",
+            PATTERN_WIDTH,
+            PATTERN_HEIGHT,
+            &rgba,
+            2.,
+        );
+        if let Some((x, y)) = hovered_pixel(&response, 2.) {
+            let table = x / 128 * 0x1000;
+            let tile = y / 8 * 16 + x % 128 / 8;
+            response.on_hover_text(format!(
+                "Tile ${:02X}\nAddress ${:04X}",
+                tile,
+                table + tile * 16
+            ));
+        }
+    }
+
+    fn show_nametables(&mut self, ui: &mut Ui, ppu: &PPU<Cartridge>, palette: &Palette) {
+        let rgba = palette.to_rgba(&ppu.nametable_view());
+        let response = self.show_image(
+            ui,
+            "nametables",
+            NAMETABLE_WIDTH,
+            NAMETABLE_HEIGHT,
+            &rgba,
+            1.,
+        );
+
+        // the screen, wrapping around the edges like the scroll does
+        let (scroll_x, scroll_y) = ppu.scroll_position();
+        let painter = ui.painter_at(response.rect);
+        for (dx, dy) in [(0, 0), (-1, 0), (0, -1), (-1, -1)] {
+            let x = (scroll_x as i32 + dx * NAMETABLE_WIDTH as i32) as f32;
+            let y = (scroll_y as i32 + dy * NAMETABLE_HEIGHT as i32) as f32;
+            let min = response.rect.min + Vec2::new(x, y);
+            let screen = Rect::from_min_size(min, Vec2::new(WIDTH as f32, HEIGHT as f32));
+            painter.rect_stroke(screen, 0., Stroke::new(2., Color32::RED));
+        }
+
+        if let Some((x, y)) = hovered_pixel(&response, 1.) {
+            let base = 0x2000 + y / 240 * 0x800 + x / 256 * 0x400;
+            let (x, y) = (x % 256, y % 240);
+            let addr = base + y / 8 * 32 + x / 8;
+            let attribute = base + 0x3C0 + y / 32 * 8 + x / 32;
+            let tile = ppu.read(addr as u16);
+            let quadrant = (y & 0x10) >> 2 | (x & 0x10) >> 3;
+            let palette = ppu.read(attribute as u16) >> quadrant & 0x03;
+            response.on_hover_text(format!(
+                "Tile ${:02X} at ${:04X} ({}, {})\nAttribute ${:04X}, palette {}",
+                tile,
+                addr,
+                x / 8,
+                y / 8,
+                attribute,
+                palette
+            ));
+        }
+    }
+
+    fn show_sprites(&mut self, ui: &mut Ui, ppu: &PPU<Cartridge>, palette: &Palette) {
+        let sprites = ppu.sprites();
+        let mut atlas = vec![ppu.read(0x3F00) as u16; SPRITE_ATLAS_WIDTH * SPRITE_ATLAS_HEIGHT];
+        for sprite in &sprites {
+            let view = ppu.sprite_view(sprite);
+            let column = sprite.index as usize % SPRITE_COLUMNS * 8;
+            let row = sprite.index as usize / SPRITE_COLUMNS * 16;
+            for (n, pixel) in view.iter().enumerate() {
+                atlas[(row + n / 8) * SPRITE_ATLAS_WIDTH + column + n % 8] = *pixel;
+            }
+        }
+        let rgba = palette.to_rgba(&atlas);
+        let response = self.show_image(
+            ui,
+            "sprites",
+            SPRITE_ATLAS_WIDTH,
+            SPRITE_ATLAS_HEIGHT,
+            &rgba,
+            4.,
+        );
+        if let Some((x, y)) = hovered_pixel(&response, 4.) {
+            let sprite = &sprites[y / 16 * SPRITE_COLUMNS + x / 8];
+            response.on_hover_text(describe_sprite(sprite));
+        }
+
+        ui.push_id("sprite-list", |ui| {
+            ScrollArea::vertical().max_height(160.).show(ui, |ui| {
+                Grid::new("sprite list").striped(true).show(ui, |ui| {
+                    for heading in ["#", "X", "Y", "Tile", "Attr", "Pal", "Flags"] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+                    for sprite in &sprites {
+                        ui.monospace(format!("{:02}", sprite.index));
+                        ui.monospace(format!("{:3}", sprite.x));
+                        ui.monospace(format!("{:3}", sprite.y));
+                        ui.monospace(format!("{:02X}", sprite.tile));
+                        ui.monospace(format!("{:02X}", sprite.attributes));
+                        ui.monospace(format!("{}", sprite.palette + 4));
+                        ui.monospace(sprite_flags(sprite));
+                        ui.end_row();
+                    }
+                });
+            })
+        });
+    }
+
+    fn show_palette_ram(&mut self, ui: &mut Ui, ppu: &PPU<Cartridge>, palette: &Palette) {
+        let ram = ppu.palette_ram();
+        let entries: Vec<u16> = ram.iter().map(|entry| *entry as u16).collect();
+        let rgba = palette.to_rgba(&entries);
+        let response = self.show_image(ui, "palette_ram", 16, 2, &rgba, 20.);
+        if let Some((x, y)) = hovered_pixel(&response, 20.) {
+            let index = y * 16 + x;
+            response.on_hover_text(format!("${:04X}: ${:02X}", 0x3F00 + index, ram[index]));
+        }
+    }
+
+    /// Draws an RGBA picture `scale` times its size, with a button to save
+    /// it as `<name>.png`.
+    fn show_image(
+        &mut self,
+        ui: &mut Ui,
+        name: &'static str,
+        width: usize,
+        height: usize,
+        rgba: &[u8],
+        scale: f32,
+    ) -> Response {
+        let image = ColorImage::from_rgba_unmultiplied([width, height], rgba);
+        let texture = self.textures.entry(name).or_insert_with(|| {
+            ui.ctx()
+                .load_texture(name, image.clone(), TextureOptions::NEAREST)
+        });
+        texture.set(image, TextureOptions::NEAREST);
+        let size = Vec2::new(width as f32, height as f32) * scale;
+        let response = ui.add(Image::new((texture.id(), size)).sense(Sense::hover()));
+
+        ui.horizontal(|ui| {
+            if ui.button("Save PNG").clicked() {
+                let path = format!("{}.png", name);
+                let screenshot = Screenshot {
+                    width,
+                    height,
+                    rgba: rgba.to_vec(),
+                };
+                self.status = Some(match screenshot.write_png(Path::new(&path)) {
+                    Ok(()) => format!("Saved {}", path),
+                    Err(e) => format!("Saving {} failed: {}", path, e),
+                });
+            }
+            if let Some(status) = &self.status {
+                ui.label(status);
+            }
+        });
+        response
+    }
+}
+
+/// The picture pixel under the mouse, if it is over the image.
+fn hovered_pixel(response: &Response, scale: f32) -> Option<(usize, usize)> {
+    let pos: Pos2 = response.hover_pos()?;
+    let offset = (pos - response.rect.min) / scale;
+    if offset.x < 0. || offset.y < 0. {
+        return None;
+    }
+    let (x, y) = (offset.x as usize, offset.y as usize);
+    let size = response.rect.size() / scale;
+    if x >= size.x as usize || y >= size.y as usize {
+        return None;
+    }
+    Some((x, y))
+}
+
+fn describe_sprite(sprite: &SpriteInfo) -> String {
+    format!(
+        "Sprite {}\nPosition ({}, {})\nTile ${:02X} at ${:04X}\nAttributes ${:02X}, palette {}\n{}",
+        sprite.index,
+        sprite.x,
+        sprite.y,
+        sprite.tile,
+        sprite.pattern_addr,
+        sprite.attributes,
+        sprite.palette + 4,
+        sprite_flags(sprite)
+    )
+}
+
+fn sprite_flags(sprite: &SpriteInfo) -> String {
+    let mut flags = Vec::new();
+    if sprite.flip_horizontal {
+        flags.push("H");
+    }
+    if sprite.flip_vertical {
+        flags.push("V");
+    }
+    if sprite.behind_background {
+        flags.push("Behind");
+    }
+    flags.join(" ")
+}
@@ -2,10 +2,7 @@
 
 use std::sync::{Arc, RwLock};
 
-use nesemu::bus::Bus;
-use nesemu::memory::CpuMemory;
 use nesemu::Nes;
-use nesemu_cpu::cpu::CPU;
 
 use crate::app::NesemuGui;
 use crate::create_channels;
@@ -26,16 +23,8 @@ pub fn run() {
                 "the_canvas_id", // hardcode it
                 web_options,
                 Box::new(|cc| {
-                    let ram = Arc::new(RwLock::new(CpuMemory::default()));
-                    let bus = Arc::new(RwLock::new(Bus::new(ram.clone())));
-                    let mut cpu = CPU::new(bus.clone());
-                    cpu.reset();
-
-                    let nes = Nes {
-                        ram: ram.clone(),
-                        cpu,
-                        bus: bus.clone(),
-                    };
+                    let mut nes = Nes::new();
+                    nes.cpu.reset();
                     let nes_ref = Arc::new(RwLock::new(nes));
                     let nes_ref_2 = nes_ref.clone();
 
@@ -48,7 +37,7 @@ pub fn run() {
                         Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
                             // if we ever want to handle the data from js
                             //let data = event.data();
-                            nes_ref_2.clone().write().unwrap().cpu.clock();
+                            nes_ref_2.clone().write().unwrap().clock();
                             ctx.request_repaint();
                         }) as Box<dyn FnMut(_)>);
                     worker.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Region;
+
+use crate::audio::ChannelState;
+
+/// The delta modulation channel, $4010-$4013: plays 1-bit delta encoded
+/// samples fetched from $8000-$FFFF, nudging a 7-bit level up or down by
+/// 2 for each bit.
+#[derive(Serialize, Deserialize)]
+pub struct Dmc {
+    pub irq_enabled: bool,
+    pub looping: bool,
+    /// Index into the region's DMC periods.
+    pub rate: u8,
+    pub level: u8,
+    /// Where the sample starts, from $4012.
+    pub sample_addr: u16,
+    /// How long the sample is in bytes, from $4013.
+    pub sample_length: u16,
+    pub irq: bool,
+    addr: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    /// Asked of the DMA unit but not yet fetched.
+    request: Option<u16>,
+    fetching: bool,
+    timer: u16,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            rate: 0,
+            level: 0,
+            sample_addr: 0xC000,
+            sample_length: 1,
+            irq: false,
+            addr: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            request: None,
+            fetching: false,
+            timer: 0,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    /// Writes register 0-3 of the channel.
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                self.looping = data & 0x40 != 0;
+                self.rate = data & 0x0F;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = data & 0x7F,
+            2 => self.sample_addr = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = (data as u16) << 4 | 1,
+        }
+    }
+
+    /// Bit 4 of $4015. Starts the sample over if it had finished, or cuts
+    /// it off. Either way the IRQ is acknowledged.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+        self.fetch();
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Asks for the next byte once the buffer has been emptied.
+    fn fetch(&mut self) {
+        if self.buffer.is_none() && !self.fetching && self.bytes_remaining > 0 {
+            self.request = Some(self.addr);
+            self.fetching = true;
+        }
+    }
+
+    /// Whether a sample byte has been asked for and not yet delivered.
+    pub fn fetching(&self) -> bool {
+        self.fetching
+    }
+
+    pub fn take_request(&mut self) -> Option<u16> {
+        self.request.take()
+    }
+
+    pub fn load_sample(&mut self, sample: u8) {
+        self.fetching = false;
+        if self.bytes_remaining == 0 {
+            // cut off by $4015 while the fetch was under way
+            return;
+        }
+        self.buffer = Some(sample);
+        self.addr = if self.addr == 0xFFFF {
+            0x8000
+        } else {
+            self.addr + 1
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Runs one CPU cycle.
+    pub fn clock_timer(&mut self, region: &Region) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = region.dmc_periods()[self.rate as usize] - 1;
+
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift = sample;
+                }
+                None => self.silence = true,
+            }
+            self.fetch();
+        }
+    }
+
+    pub fn state(&self, name: &'static str, region: &Region) -> ChannelState {
+        ChannelState {
+            name,
+            period: region.dmc_periods()[self.rate as usize],
+            volume: self.level,
+            duty: None,
+            frequency: None,
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Region;
+
+use crate::apu::dmc::Dmc;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::triangle::Triangle;
+use crate::audio::{ChannelState, APU_CHANNELS};
+
+pub mod dmc;
+pub mod noise;
+pub mod pulse;
+pub mod triangle;
+
+/// Length counter loads, indexed by the top 5 bits of the fourth register
+/// of each channel.
+const LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Counts a note down on half frames and silences the channel at zero.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LengthCounter {
+    pub counter: u8,
+    pub halt: bool,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    /// Loads from the length index in bits 3-7 of `data`, if the channel is
+    /// enabled in $4015.
+    pub fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.counter = LENGTHS[data as usize >> 3];
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.counter > 0 && !self.halt {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+/// The volume envelope shared by the pulse and noise channels: either a
+/// constant volume, or a sawtooth decaying from 15 on quarter frames.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Envelope {
+    pub constant: bool,
+    pub looping: bool,
+    /// The constant volume, or the decay period.
+    pub value: u8,
+    start: bool,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Takes bits 0-5 of $4000/$4004/$400C.
+    pub fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.value = data & 0x0F;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.value;
+        } else if self.divider == 0 {
+            self.divider = self.value;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant {
+            self.value
+        } else {
+            self.decay
+        }
+    }
+}
+
+/// The 2A03's sound hardware: two pulse channels, a triangle, noise and
+/// the delta modulation channel, sequenced by the frame counter.
+#[derive(Serialize, Deserialize)]
+pub struct Apu {
+    /// Left out of save states, it goes with the console's setting.
+    #[serde(skip)]
+    pub region: Region,
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    /// CPU cycles since power on. Pulse timers only run on even ones.
+    cycles: u64,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    /// CPU cycles into the frame counter sequence.
+    frame_cycle: u32,
+    /// A $4017 write restarts the sequence 3 or 4 cycles later.
+    frame_reset_delay: u8,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            region: Region::default(),
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            cycles: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            frame_reset_delay: 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 0x03, data),
+            0x4004..=0x4007 => self.pulse2.write(addr & 0x03, data),
+            0x4008..=0x400B => self.triangle.write(addr & 0x03, data),
+            0x400C..=0x400F => self.noise.write(addr & 0x03, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 0x03, data),
+            0x4015 => {
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            0x4017 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_reset_delay = if self.cycles & 1 == 0 { 3 } else { 4 };
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads $4015, which acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    /// $4015 without the side effect.
+    pub fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        for (bit, active) in [
+            self.pulse1.length.active(),
+            self.pulse2.length.active(),
+            self.triangle.length.active(),
+            self.noise.length.active(),
+            self.dmc.active(),
+            false,
+            self.frame_irq,
+            self.dmc.irq,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            status |= (active as u8) << bit;
+        }
+        status
+    }
+
+    /// Runs one CPU cycle.
+    pub fn clock(&mut self) {
+        self.clock_frame_counter();
+
+        self.triangle.clock_timer();
+        if self.cycles & 1 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.noise.clock_timer(&self.region);
+        self.dmc.clock_timer(&self.region);
+        self.cycles += 1;
+    }
+
+    fn clock_frame_counter(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+        }
+
+        self.frame_cycle += 1;
+        let steps = self.region.frame_counter_steps(self.five_step);
+        let cycle = self.frame_cycle;
+        if cycle == steps[0] || cycle == steps[2] {
+            self.clock_quarter_frame();
+        } else if cycle == steps[1] || cycle == steps[4] {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+        // the 4 step sequence raises its IRQ over its last three cycles
+        if !self.five_step && !self.irq_inhibit && cycle >= steps[3] {
+            self.frame_irq = true;
+        }
+        if cycle >= steps[5] {
+            self.frame_cycle = 0;
+        }
+    }
+
+    /// Envelopes and the triangle's linear counter.
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.triangle.clock_linear_counter();
+        self.noise.envelope.clock();
+    }
+
+    /// Length counters and sweeps.
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+    }
+
+    /// Whether the frame counter or the DMC is holding the IRQ line.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    /// The address the DMC wants its next sample byte from, to be handed to
+    /// the DMA unit.
+    pub fn take_dmc_request(&mut self) -> Option<u16> {
+        self.dmc.take_request()
+    }
+
+    /// A sample byte fetched for the DMC.
+    pub fn load_dmc_sample(&mut self, sample: u8) {
+        self.dmc.load_sample(sample);
+    }
+
+    /// What each channel is playing, in `APU_CHANNELS` order.
+    pub fn channel_states(&self) -> [ChannelState; 5] {
+        let clock = self.region.cpu_clock();
+        [
+            self.pulse1.state(APU_CHANNELS[0], clock),
+            self.pulse2.state(APU_CHANNELS[1], clock),
+            self.triangle.state(APU_CHANNELS[2], clock),
+            self.noise.state(APU_CHANNELS[3], &self.region),
+            self.dmc.state(APU_CHANNELS[4], &self.region),
+        ]
+    }
+
+    /// Each channel's DAC input: pulses, triangle and noise 0-15, DMC 0-127.
+    pub fn levels(&self) -> [u8; 5] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+}
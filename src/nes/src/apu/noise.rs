@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Region;
+
+use crate::apu::{Envelope, LengthCounter};
+use crate::audio::ChannelState;
+
+/// Pseudo-random noise from a 15-bit shift register, $400C-$400F.
+#[derive(Serialize, Deserialize)]
+pub struct Noise {
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    /// Short mode taps bit 6 instead of bit 1, for a 93 step metallic loop.
+    pub short_mode: bool,
+    /// Index into the region's noise periods.
+    pub period: u8,
+    timer: u16,
+    shift: u16,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            short_mode: false,
+            period: 0,
+            timer: 0,
+            shift: 1,
+        }
+    }
+
+    /// Writes register 0-3 of the channel. Register 1 is unused.
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.period = data & 0x0F;
+            }
+            3 => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs one CPU cycle.
+    pub fn clock_timer(&mut self, region: &Region) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = region.noise_periods()[self.period as usize] - 1;
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift ^ self.shift >> tap) & 0x01;
+        self.shift = self.shift >> 1 | feedback << 14;
+    }
+
+    pub fn state(&self, name: &'static str, region: &Region) -> ChannelState {
+        ChannelState {
+            name,
+            period: region.noise_periods()[self.period as usize],
+            volume: if self.length.active() {
+                self.envelope.volume()
+            } else {
+                0
+            },
+            duty: None,
+            frequency: None,
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift & 0x01 != 0 || !self.length.active() {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
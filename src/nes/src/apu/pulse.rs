@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+use crate::apu::{Envelope, LengthCounter};
+use crate::audio::ChannelState;
+
+/// The four duty cycles, in the order the sequencer counts down through.
+const DUTIES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Bends the period up or down on half frames.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Sweep {
+    pub enabled: bool,
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+    reload: bool,
+    divider: u8,
+}
+
+/// A square wave channel, $4000-$4003 or $4004-$4007.
+#[derive(Serialize, Deserialize)]
+pub struct Pulse {
+    /// The first pulse negates its sweep in one's complement, so it bends
+    /// one lower than the second.
+    ones_complement: bool,
+    pub duty: u8,
+    pub envelope: Envelope,
+    pub sweep: Sweep,
+    pub length: LengthCounter,
+    /// The timer period in APU cycles, less one.
+    pub period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl Pulse {
+    pub fn new(first: bool) -> Self {
+        Pulse {
+            ones_complement: first,
+            duty: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+            length: LengthCounter::default(),
+            period: 0,
+            timer: 0,
+            step: 0,
+        }
+    }
+
+    /// Writes register 0-3 of the channel.
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep.enabled = data & 0x80 != 0;
+                self.sweep.period = data >> 4 & 0x07;
+                self.sweep.negate = data & 0x08 != 0;
+                self.sweep.shift = data & 0x07;
+                self.sweep.reload = true;
+            }
+            2 => self.period = self.period & 0x0700 | data as u16,
+            _ => {
+                self.period = self.period & 0x00FF | (data as u16 & 0x07) << 8;
+                self.length.load(data);
+                self.envelope.restart();
+                self.step = 0;
+            }
+        }
+    }
+
+    /// Runs one APU cycle, every other CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 7) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        let sweep = &mut self.sweep;
+        if sweep.divider == 0 || sweep.reload {
+            sweep.divider = sweep.period;
+            sweep.reload = false;
+        } else {
+            sweep.divider -= 1;
+        }
+    }
+
+    /// The period the sweep is heading for. Worked out all the time, since
+    /// it mutes the channel when it overflows even with the sweep off.
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep.shift;
+        if !self.sweep.negate {
+            self.period + change
+        } else if self.ones_complement {
+            self.period.saturating_sub(change + 1)
+        } else {
+            self.period.saturating_sub(change)
+        }
+    }
+
+    pub fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    pub fn state(&self, name: &'static str, clock: f64) -> ChannelState {
+        let sounding = !self.muted() && self.length.active() && self.envelope.volume() > 0;
+        ChannelState {
+            name,
+            period: self.period,
+            volume: self.envelope.volume(),
+            duty: Some(self.duty),
+            frequency: sounding.then(|| clock / (16.0 * (self.period as f64 + 1.0))),
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.muted()
+            || !self.length.active()
+            || DUTIES[self.duty as usize][self.step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::apu::LengthCounter;
+use crate::audio::ChannelState;
+
+/// The 32 step triangle, $4008-$400B. It has no volume control, only a
+/// second, finer grained linear counter to cut notes short.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Triangle {
+    pub length: LengthCounter,
+    /// Also the length counter's halt flag.
+    pub control: bool,
+    pub linear_reload: u8,
+    pub linear_counter: u8,
+    reload_linear: bool,
+    /// The timer period in CPU cycles, less one.
+    pub period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl Triangle {
+    /// Writes register 0-3 of the channel. Register 1 is unused.
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload = data & 0x7F;
+            }
+            2 => self.period = self.period & 0x0700 | data as u16,
+            3 => {
+                self.period = self.period & 0x00FF | (data as u16 & 0x07) << 8;
+                self.length.load(data);
+                self.reload_linear = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs one CPU cycle. The sequence only moves while both counters are
+    /// running, so a silenced triangle holds its level instead of popping.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_linear_counter(&mut self) {
+        if self.reload_linear {
+            self.linear_counter = self.linear_reload;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.reload_linear = false;
+        }
+    }
+
+    /// Periods under 2 are too high to hear, though the channel still
+    /// runs.
+    pub fn state(&self, name: &'static str, clock: f64) -> ChannelState {
+        let sounding = self.length.active() && self.linear_counter > 0 && self.period >= 2;
+        ChannelState {
+            name,
+            period: self.period,
+            volume: if sounding { 15 } else { 0 },
+            duty: None,
+            frequency: sounding.then(|| clock / (32.0 * (self.period as f64 + 1.0))),
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.step < 16 {
+            15 - self.step
+        } else {
+            self.step - 16
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{Cursor, Read as ioRead};
+
+use zip::ZipArchive;
+
+use crate::rom_loader::RomError;
+
+/// File extensions we know how to load, in order of preference.
+pub const ROM_EXTENSIONS: [&str; 4] = ["nes", "fds", "unf", "nsf"];
+
+/// zip local file headers always start with "PK\x03\x04".
+pub fn is_zip(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && bytes[0..4] == [0x50, 0x4B, 0x03, 0x04]
+}
+
+pub fn has_rom_extension(name: &str) -> bool {
+    match name.rsplit_once('.') {
+        Some((_, ext)) => ROM_EXTENSIONS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+/// Lists every entry in the archive that looks like something we can load,
+/// in the order they are stored. Handy for letting the user pick one.
+pub fn rom_entries(bytes: &[u8]) -> Result<Vec<String>, RomError> {
+    let mut archive = open(bytes)?;
+    Ok((0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().map(|f| f.name().to_string()))
+        .filter(|name| has_rom_extension(name))
+        .collect::<Vec<String>>())
+}
+
+/// Pulls a single file out of a zip held in memory. With no `name`, the
+/// first entry with a known rom extension is used.
+pub fn extract(bytes: &[u8], name: Option<&str>) -> Result<(String, Vec<u8>), RomError> {
+    let mut archive = open(bytes)?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => (0..archive.len())
+            .filter_map(|i| archive.by_index(i).ok().map(|f| f.name().to_string()))
+            .find(|name| has_rom_extension(name))
+            .ok_or(RomError::NoRomInArchive)?,
+    };
+
+    let mut file = archive
+        .by_name(&name)
+        .map_err(|_| RomError::NoRomInArchive)?;
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut contents)
+        .map_err(|e| RomError::Archive(e.to_string()))?;
+    Ok((name, contents))
+}
+
+fn open(bytes: &[u8]) -> Result<ZipArchive<Cursor<&[u8]>>, RomError> {
+    ZipArchive::new(Cursor::new(bytes)).map_err(|e| RomError::Archive(e.to_string()))
+}
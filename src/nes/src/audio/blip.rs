@@ -0,0 +1,110 @@
+use std::f64::consts::PI;
+
+/// Sub-sample positions a step can land on.
+const PHASE_BITS: u32 = 5;
+const PHASES: usize = 1 << PHASE_BITS;
+/// Output samples each step is spread over. Output lags input by half of
+/// this.
+pub const KERNEL_WIDTH: usize = 16;
+/// Times are kept in 32.32 fixed point output samples.
+const FRAC_BITS: u32 = 32;
+/// Where the band limit sits, as a fraction of the output Nyquist rate.
+const CUTOFF: f64 = 0.9;
+
+/// Turns a signal sampled at the CPU clock into one at the output rate
+/// without aliasing. Level changes go in as deltas at the clock they
+/// happened on, each added as a band-limited impulse, and reading the
+/// buffer integrates them back into a signal.
+pub struct BlipBuffer {
+    /// Output samples per clock.
+    factor: u64,
+    /// Where clock 0 of the current frame falls in `buffer`.
+    offset: u64,
+    buffer: Vec<f64>,
+    integrator: f64,
+    kernel: Vec<[f64; KERNEL_WIDTH]>,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        let mut blip = BlipBuffer {
+            factor: 0,
+            offset: 0,
+            buffer: vec![0.0; KERNEL_WIDTH],
+            integrator: 0.0,
+            kernel: make_kernel(),
+        };
+        blip.set_rates(clock_rate, sample_rate);
+        blip
+    }
+
+    /// Can be changed at any time, which is how the rate gets nudged to
+    /// keep up with the frontend.
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.factor = (sample_rate / clock_rate * (1u64 << FRAC_BITS) as f64) as u64;
+    }
+
+    /// Adds a change of `delta` at `time` clocks into the current frame.
+    pub fn add_delta(&mut self, time: u32, delta: f32) {
+        let pos = self.offset + time as u64 * self.factor;
+        let index = (pos >> FRAC_BITS) as usize;
+        let phase = (pos >> (FRAC_BITS - PHASE_BITS)) as usize & (PHASES - 1);
+        if self.buffer.len() < index + KERNEL_WIDTH {
+            self.buffer.resize(index + KERNEL_WIDTH, 0.0);
+        }
+        let delta = delta as f64;
+        for (sample, tap) in self.buffer[index..].iter_mut().zip(&self.kernel[phase]) {
+            *sample += delta * tap;
+        }
+    }
+
+    /// Ends the frame after `clocks` clocks, making its samples readable.
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.offset += clocks as u64 * self.factor;
+    }
+
+    pub fn samples_available(&self) -> usize {
+        (self.offset >> FRAC_BITS) as usize
+    }
+
+    /// Reads out as many finished samples as fit in `out`, returning how
+    /// many there were.
+    pub fn read_samples(&mut self, out: &mut [f32]) -> usize {
+        let count = self.samples_available().min(out.len());
+        if self.buffer.len() < count {
+            self.buffer.resize(count, 0.0);
+        }
+        for (out, delta) in out.iter_mut().zip(self.buffer.drain(..count)) {
+            self.integrator += delta;
+            *out = self.integrator as f32;
+        }
+        self.offset -= (count as u64) << FRAC_BITS;
+        count
+    }
+}
+
+/// A windowed sinc for each phase, each summing to exactly 1 so steps
+/// settle at the right level.
+fn make_kernel() -> Vec<[f64; KERNEL_WIDTH]> {
+    (0..PHASES)
+        .map(|phase| {
+            let frac = phase as f64 / PHASES as f64;
+            let mut taps = [0.0; KERNEL_WIDTH];
+            for (k, tap) in taps.iter_mut().enumerate() {
+                let x = k as f64 - (KERNEL_WIDTH / 2) as f64 - frac;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x * CUTOFF).sin() / (PI * x * CUTOFF)
+                };
+                // Blackman, centred on the impulse
+                let w = (x + KERNEL_WIDTH as f64 / 2.0) / KERNEL_WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                *tap = sinc * window.max(0.0);
+            }
+            let sum: f64 = taps.iter().sum();
+            taps.iter_mut().for_each(|tap| *tap /= sum);
+            taps
+        })
+        .collect()
+}
@@ -0,0 +1,547 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::audio::blip::BlipBuffer;
+use crate::audio::wav::{WavFormat, WavWriter};
+use crate::Nes;
+
+pub mod blip;
+pub mod wav;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+/// Samples the sink holds before it starts dropping the oldest.
+pub const SINK_CAPACITY: usize = 4096;
+/// CPU cycles mixed between pushes to the sink, about 2ms.
+const FLUSH_CYCLES: u32 = 4096;
+/// The most the output rate gets stretched or squeezed to keep the sink
+/// half full. Half a percent is too little to hear as a pitch change.
+const MAX_RATE_ADJUST: f64 = 0.005;
+/// Expansion chips give 0.0 to 1.0. This puts them at about twice a full
+/// volume pulse channel.
+const EXPANSION_GAIN: f32 = 0.3;
+/// CPU cycles between oscilloscope points, about 44.7kHz.
+const SCOPE_CYCLES: u32 = 40;
+/// Oscilloscope points kept per channel.
+pub const SCOPE_LENGTH: usize = 2048;
+
+pub const APU_CHANNELS: [&str; 5] = ["Pulse 1", "Pulse 2", "Triangle", "Noise", "DMC"];
+
+/// What a channel is playing, for the audio debugger.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelState {
+    pub name: &'static str,
+    /// The timer period, in the channel's own units.
+    pub period: u16,
+    pub volume: u8,
+    pub duty: Option<u8>,
+    /// The pitch in Hz, for tonal channels while they are sounding.
+    pub frequency: Option<f64>,
+}
+
+/// The nearest note to `frequency` and how far off it is, like "A4 +3c".
+pub fn note_name(frequency: f64) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    // semitones up from C0, which puts A4 at 57
+    let semitones = 12.0 * (frequency / 440.0).log2() + 57.0;
+    let nearest = semitones.round();
+    let cents = ((semitones - nearest) * 100.0).round() as i32;
+    let note = nearest as i32;
+    format!(
+        "{}{} {:+}c",
+        NAMES[note.rem_euclid(12) as usize],
+        note.div_euclid(12),
+        cents
+    )
+}
+
+/// A channel's place in the mix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelVolume {
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for ChannelVolume {
+    fn default() -> Self {
+        ChannelVolume {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl ChannelVolume {
+    fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+}
+
+/// Where the mixed audio ends up: a ring buffer the emulator fills and the
+/// frontend's audio callback drains, from whichever thread it likes.
+pub struct AudioSink {
+    samples: Mutex<VecDeque<f32>>,
+    /// Set once a frontend starts draining, before which there is no point
+    /// in rate control.
+    drained: AtomicBool,
+}
+
+impl Default for AudioSink {
+    fn default() -> Self {
+        AudioSink {
+            samples: Mutex::new(VecDeque::with_capacity(SINK_CAPACITY)),
+            drained: AtomicBool::new(false),
+        }
+    }
+}
+
+impl AudioSink {
+    /// Fills `out` with the oldest samples, returning how many there were.
+    /// Anything left over is the frontend's to pad.
+    pub fn drain(&self, out: &mut [f32]) -> usize {
+        self.drained.store(true, Ordering::Relaxed);
+        let mut samples = self.samples.lock().unwrap();
+        let count = samples.len().min(out.len());
+        for (out, sample) in out.iter_mut().zip(samples.drain(..count)) {
+            *out = sample;
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+
+    fn push(&self, new: &[f32]) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.extend(new);
+        let excess = samples.len().saturating_sub(SINK_CAPACITY);
+        samples.drain(..excess);
+    }
+}
+
+/// A first order filter, like the RC stages on the console's audio out.
+struct Filter {
+    high_pass: bool,
+    alpha: f32,
+    last_in: f32,
+    last_out: f32,
+}
+
+impl Filter {
+    fn new(high_pass: bool, cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Filter {
+            high_pass,
+            alpha: if high_pass {
+                rc / (rc + dt)
+            } else {
+                dt / (rc + dt)
+            },
+            last_in: 0.0,
+            last_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        self.last_out = if self.high_pass {
+            self.alpha * (self.last_out + input - self.last_in)
+        } else {
+            self.last_out + self.alpha * (input - self.last_out)
+        };
+        self.last_in = input;
+        self.last_out
+    }
+}
+
+/// A channel resampled on its own, for multitrack recording.
+struct Track {
+    blip: BlipBuffer,
+    filters: [Filter; 3],
+    level: f32,
+    wav: WavWriter,
+}
+
+struct Recording {
+    mix: WavWriter,
+    tracks: Vec<Track>,
+    /// The first write that failed, which ends the recording.
+    error: Option<io::Error>,
+}
+
+impl Recording {
+    fn write(&mut self, samples: &[f32], track: Option<usize>) {
+        if self.error.is_some() {
+            return;
+        }
+        let wav = match track {
+            Some(track) => &mut self.tracks[track].wav,
+            None => &mut self.mix,
+        };
+        if let Err(e) = wav.write(samples) {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// Where a channel's own recording goes: "song - Pulse 1.wav" next to
+/// "song.wav".
+pub fn track_path(path: &Path, channel: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{} - {}.wav", stem, channel))
+}
+
+/// Mixes the APU channels and expansion audio the way the console's DAC
+/// does, and resamples the result to the output rate.
+pub struct Mixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+    blip: BlipBuffer,
+    /// The 90Hz and 440Hz high passes and the 14kHz low pass between the
+    /// 2A03 and the AV jack.
+    filters: [Filter; 3],
+    clock_rate: f64,
+    sample_rate: u32,
+    /// Cycles since the last flush.
+    time: u32,
+    level: f32,
+    /// Stretches the output to keep the sink from running dry or over, once
+    /// something is draining it. Held off while recording, so the files
+    /// keep to their sample rate.
+    pub rate_control: bool,
+    /// Scales the whole mix, 1.0 for full volume. An NSF's track fades out
+    /// through this.
+    pub fade: f32,
+    pub apu_volumes: [ChannelVolume; 5],
+    /// One for each of the cartridge's expansion channels.
+    pub expansion_volumes: Vec<ChannelVolume>,
+    /// Recent levels of each channel, APU first, 0.0 to 1.0 and before
+    /// volume is applied.
+    scopes: Vec<VecDeque<f32>>,
+    scope_timer: u32,
+    sink: Arc<AudioSink>,
+    scratch: Vec<f32>,
+    recording: Option<Recording>,
+}
+
+impl Mixer {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let mut pulse_table = [0.0; 31];
+        for (n, entry) in pulse_table.iter_mut().enumerate().skip(1) {
+            *entry = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        let mut tnd_table = [0.0; 203];
+        for (n, entry) in tnd_table.iter_mut().enumerate().skip(1) {
+            *entry = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        Mixer {
+            pulse_table,
+            tnd_table,
+            blip: BlipBuffer::new(clock_rate, sample_rate as f64),
+            filters: make_filters(sample_rate),
+            clock_rate,
+            sample_rate,
+            time: 0,
+            level: 0.0,
+            rate_control: true,
+            fade: 1.0,
+            apu_volumes: [ChannelVolume::default(); 5],
+            expansion_volumes: Vec::new(),
+            scopes: vec![VecDeque::new(); APU_CHANNELS.len()],
+            scope_timer: 0,
+            sink: Arc::new(AudioSink::default()),
+            scratch: Vec::new(),
+            recording: None,
+        }
+    }
+
+    /// The sink for the frontend to hold on to.
+    pub fn sink(&self) -> Arc<AudioSink> {
+        self.sink.clone()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Changes the output rate. Whatever was waiting in the sink is dropped,
+    /// and any recording is finished.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> io::Result<()> {
+        let finished = self.stop_recording();
+        self.sample_rate = sample_rate;
+        self.blip = BlipBuffer::new(self.clock_rate, sample_rate as f64);
+        self.filters = make_filters(sample_rate);
+        self.time = 0;
+        self.level = 0.0;
+        self.sink.clear();
+        finished
+    }
+
+    /// Follows a change of region.
+    pub fn set_clock_rate(&mut self, clock_rate: f64) {
+        self.clock_rate = clock_rate;
+        self.set_blip_rates(self.sample_rate as f64);
+    }
+
+    fn set_blip_rates(&mut self, sample_rate: f64) {
+        self.blip.set_rates(self.clock_rate, sample_rate);
+        for track in self.recording.iter_mut().flat_map(|r| &mut r.tracks) {
+            track.blip.set_rates(self.clock_rate, sample_rate);
+        }
+    }
+
+    /// Starts writing the mix to `path`. Each channel named in `tracks`,
+    /// numbered as in `Nes::audio_channels`, also gets a file of its own
+    /// at `track_path`, ignoring volume and muting so it can be remixed.
+    pub fn start_recording(
+        &mut self,
+        path: &Path,
+        format: WavFormat,
+        tracks: &[&str],
+    ) -> io::Result<()> {
+        self.stop_recording()?;
+        let mix = WavWriter::create(path, self.sample_rate, format)?;
+        let tracks = tracks
+            .iter()
+            .map(|name| {
+                Ok(Track {
+                    blip: BlipBuffer::new(self.clock_rate, self.sample_rate as f64),
+                    filters: make_filters(self.sample_rate),
+                    level: 0.0,
+                    wav: WavWriter::create(&track_path(path, name), self.sample_rate, format)?,
+                })
+            })
+            .collect::<io::Result<Vec<Track>>>()?;
+        self.recording = Some(Recording {
+            mix,
+            tracks,
+            error: None,
+        });
+        // the mix comes out of the same blip as the sink
+        self.set_blip_rates(self.sample_rate as f64);
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Finishes the files, reporting the first write that failed if any
+    /// did. Does nothing when not recording.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        if self.recording.is_none() {
+            return Ok(());
+        }
+        self.flush();
+        let Some(recording) = self.recording.take() else {
+            return Ok(());
+        };
+        let mut result = recording.mix.finish();
+        for track in recording.tracks {
+            result = result.and(track.wav.finish());
+        }
+        match recording.error {
+            Some(e) => Err(e),
+            None => result,
+        }
+    }
+
+    /// The volume of a channel, numbered as in `Nes::audio_channels`.
+    pub fn volume_mut(&mut self, channel: usize) -> Option<&mut ChannelVolume> {
+        match channel.checked_sub(APU_CHANNELS.len()) {
+            None => self.apu_volumes.get_mut(channel),
+            Some(expansion) => self.expansion_volumes.get_mut(expansion),
+        }
+    }
+
+    /// The oscilloscope history of a channel, numbered as in
+    /// `Nes::audio_channels`, oldest first.
+    pub fn scope(&self, channel: usize) -> Option<&VecDeque<f32>> {
+        self.scopes.get(channel)
+    }
+
+    /// Takes one CPU cycle's worth of output: the APU's channel levels and
+    /// the cartridge's expansion audio.
+    pub fn clock(&mut self, levels: [u8; 5], expansion: &[f32]) {
+        // follow the cartridge's channel count as carts come and go
+        if self.expansion_volumes.len() != expansion.len() {
+            self.expansion_volumes
+                .resize(expansion.len(), ChannelVolume::default());
+        }
+        if self.scopes.len() != APU_CHANNELS.len() + expansion.len() {
+            self.scopes
+                .resize(APU_CHANNELS.len() + expansion.len(), VecDeque::new());
+        }
+        self.record_scopes(levels, expansion);
+        if self.recording.is_some() {
+            self.clock_tracks(levels, expansion);
+        }
+
+        let [pulse1, pulse2, triangle, noise, dmc] = [0, 1, 2, 3, 4]
+            .map(|channel| levels[channel] as f32 * self.apu_volumes[channel].gain());
+        let expansion: f32 = expansion
+            .iter()
+            .zip(&self.expansion_volumes)
+            .map(|(level, volume)| level * volume.gain())
+            .sum();
+        let level = (lookup(&self.pulse_table, pulse1 + pulse2)
+            + lookup(&self.tnd_table, 3.0 * triangle + 2.0 * noise + dmc)
+            + expansion * EXPANSION_GAIN)
+            * self.fade;
+        if level != self.level {
+            self.blip.add_delta(self.time, level - self.level);
+            self.level = level;
+        }
+        self.time += 1;
+        if self.time == FLUSH_CYCLES {
+            self.flush();
+        }
+    }
+
+    /// Each channel through its own part of the DAC, as if it were playing
+    /// alone.
+    fn clock_tracks(&mut self, levels: [u8; 5], expansion: &[f32]) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        for (channel, track) in recording.tracks.iter_mut().enumerate() {
+            let level = match channel {
+                0 | 1 => self.pulse_table[levels[channel] as usize],
+                2 => self.tnd_table[3 * levels[2] as usize],
+                3 => self.tnd_table[2 * levels[3] as usize],
+                4 => self.tnd_table[levels[4] as usize],
+                _ => expansion.get(channel - 5).copied().unwrap_or(0.0) * EXPANSION_GAIN,
+            };
+            if level != track.level {
+                track.blip.add_delta(self.time, level - track.level);
+                track.level = level;
+            }
+        }
+    }
+
+    fn record_scopes(&mut self, levels: [u8; 5], expansion: &[f32]) {
+        self.scope_timer += 1;
+        if self.scope_timer < SCOPE_CYCLES {
+            return;
+        }
+        self.scope_timer = 0;
+        let apu = levels
+            .iter()
+            .enumerate()
+            .map(|(channel, level)| *level as f32 / if channel == 4 { 127.0 } else { 15.0 });
+        for (scope, level) in self
+            .scopes
+            .iter_mut()
+            .zip(apu.chain(expansion.iter().copied()))
+        {
+            if scope.len() == SCOPE_LENGTH {
+                scope.pop_front();
+            }
+            scope.push_back(level);
+        }
+    }
+
+    /// Moves the finished samples through the filters and into the sink.
+    fn flush(&mut self) {
+        let count = read_filtered(
+            &mut self.blip,
+            &mut self.filters,
+            self.time,
+            &mut self.scratch,
+        );
+        self.sink.push(&self.scratch[..count]);
+        if let Some(recording) = &mut self.recording {
+            recording.write(&self.scratch[..count], None);
+            for track in 0..recording.tracks.len() {
+                let Track { blip, filters, .. } = &mut recording.tracks[track];
+                let count = read_filtered(blip, filters, self.time, &mut self.scratch);
+                recording.write(&self.scratch[..count], Some(track));
+            }
+        }
+        self.time = 0;
+
+        let recording = self.recording.is_some();
+        if self.rate_control && !recording && self.sink.drained.load(Ordering::Relaxed) {
+            // more samples when the sink is running low, fewer when it is
+            // filling up
+            let fill = self.sink.len() as f64 / SINK_CAPACITY as f64;
+            let adjust = 1.0 + (0.5 - fill) * 2.0 * MAX_RATE_ADJUST;
+            self.set_blip_rates(self.sample_rate as f64 * adjust);
+        }
+    }
+}
+
+/// Ends a blip buffer's frame after `time` clocks and reads its samples out
+/// into `out` through `filters`, returning how many there were.
+fn read_filtered(
+    blip: &mut BlipBuffer,
+    filters: &mut [Filter; 3],
+    time: u32,
+    out: &mut Vec<f32>,
+) -> usize {
+    blip.end_frame(time);
+    out.resize(blip.samples_available(), 0.0);
+    let count = blip.read_samples(out);
+    for sample in &mut out[..count] {
+        *sample = filters
+            .iter_mut()
+            .fold(*sample, |sample, filter| filter.process(sample));
+    }
+    count
+}
+
+/// Reads a mixing table between entries, for channels turned down to
+/// fractional levels.
+fn lookup(table: &[f32], index: f32) -> f32 {
+    let below = (index as usize).min(table.len() - 1);
+    let above = (below + 1).min(table.len() - 1);
+    let frac = index - below as f32;
+    table[below] + (table[above] - table[below]) * frac
+}
+
+fn make_filters(sample_rate: u32) -> [Filter; 3] {
+    [
+        Filter::new(true, 90.0, sample_rate),
+        Filter::new(true, 440.0, sample_rate),
+        Filter::new(false, 14000.0, sample_rate),
+    ]
+}
+
+impl Nes {
+    /// Records the mix to `path`, and with `per_channel` every channel to
+    /// a file of its own beside it.
+    pub fn start_recording(
+        &mut self,
+        path: &Path,
+        format: WavFormat,
+        per_channel: bool,
+    ) -> io::Result<()> {
+        let names: Vec<&str> = if per_channel {
+            self.audio_channels()
+                .iter()
+                .map(|channel| channel.name)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.mixer.start_recording(path, format, &names)
+    }
+}
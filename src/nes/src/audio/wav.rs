@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 44;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WavFormat {
+    #[default]
+    Pcm16,
+    Float32,
+}
+
+impl WavFormat {
+    fn bytes_per_sample(&self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 2,
+            WavFormat::Float32 => 4,
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 1,
+            WavFormat::Float32 => 3,
+        }
+    }
+}
+
+/// A mono WAV file being written. The sizes in the header are filled in
+/// by `finish`.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    format: WavFormat,
+    sample_rate: u32,
+    samples: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32, format: WavFormat) -> io::Result<Self> {
+        let mut writer = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            format,
+            sample_rate,
+            samples: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let sample_rate = self.sample_rate;
+        let block_align = self.format.bytes_per_sample();
+        let data_size = self.samples * block_align as u32;
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&self.format.format_tag().to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&(block_align * 8).to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&data_size.to_le_bytes())
+    }
+
+    /// Adds samples in -1.0 to 1.0. Louder ones are clipped in 16-bit files.
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            match self.format {
+                WavFormat::Pcm16 => {
+                    let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                    self.file.write_all(&value.to_le_bytes())?;
+                }
+                WavFormat::Float32 => self.file.write_all(&sample.to_le_bytes())?,
+            }
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    /// Goes back and fills in the header now the length is known.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
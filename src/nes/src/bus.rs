@@ -1,15 +1,31 @@
-use std::cell::RefCell;
-use std::rc::Rc;
 use std::sync::{Arc, Mutex, RwLock};
 
-use crate::memory::CpuMemory;
 use nesemu_core::{Read, Write};
+use nesemu_ppu::ppu::PPU;
+
+use crate::apu::Apu;
+use crate::cartridge::Cartridge;
+use crate::events::{Event, EventKind, EventLog};
+use crate::input::{self, ExpansionDevice, InputDevice};
 
 pub struct Bus<Memory>
 where
     Memory: Read + Write,
 {
     pub ram: Arc<RwLock<Memory>>,
+    pub ppu: Arc<RwLock<PPU<Cartridge>>>,
+    pub apu: Arc<RwLock<Apu>>,
+    pub cartridge: Option<Arc<RwLock<Cartridge>>>,
+    /// Page written to $4014, waiting for the DMA unit to pick it up.
+    pub oam_dma: Option<u8>,
+    /// Where the instruction being run started, for the event log.
+    pub pc: u16,
+    /// Behind a lock since reads get logged too.
+    pub events: Mutex<EventLog>,
+    /// The controller ports, read through $4016 and $4017.
+    pub ports: Mutex<[Box<dyn InputDevice>; 2]>,
+    /// The Famicom expansion port, read through both.
+    pub expansion: Mutex<Option<Box<dyn ExpansionDevice>>>,
 }
 
 impl<Memory> Write for Bus<Memory>
@@ -17,7 +33,32 @@ where
     Memory: Read + Write,
 {
     fn write(&mut self, addr: u16, data: u8) {
-        self.ram.write().unwrap().write(addr, data)
+        match (addr, &self.cartridge) {
+            (0x2000..=0x3FFF, _) => {
+                self.ppu.write().unwrap().cpu_write(addr, data);
+                self.log_event(EventKind::PpuWrite, addr, data);
+            }
+            (0x4014, _) => {
+                self.oam_dma = Some(data);
+                self.log_event(EventKind::PpuWrite, addr, data);
+            }
+            (0x4016, _) => {
+                for port in self.ports.lock().unwrap().iter_mut() {
+                    port.write(data);
+                }
+                if let Some(expansion) = self.expansion.lock().unwrap().as_mut() {
+                    expansion.write(data);
+                }
+            }
+            (0x4000..=0x4013 | 0x4015 | 0x4017, _) => self.apu.write().unwrap().write(addr, data),
+            (0x4020..=0xFFFF, Some(cartridge)) => {
+                cartridge.write().unwrap().write(addr, data);
+                if !(0x6000..=0x7FFF).contains(&addr) {
+                    self.log_event(EventKind::MapperWrite, addr, data);
+                }
+            }
+            _ => self.ram.write().unwrap().write(addr, data),
+        }
     }
 }
 
@@ -26,7 +67,37 @@ where
     Memory: Read + Write,
 {
     fn read(&self, addr: u16, _read_only: bool) -> u8 {
-        self.ram.read().unwrap().read(addr, false)
+        match (addr, &self.cartridge) {
+            (0x2000..=0x3FFF, _) if _read_only => self.ppu.read().unwrap().peek_register(addr),
+            (0x2000..=0x3FFF, _) => {
+                let data = self.ppu.write().unwrap().cpu_read(addr);
+                self.log_event(EventKind::PpuRead, addr, data);
+                data
+            }
+            (0x4015, _) if _read_only => self.apu.read().unwrap().peek_status(),
+            (0x4015, _) => self.apu.write().unwrap().read_status(),
+            (0x4016 | 0x4017, _) => {
+                let index = addr as usize - 0x4016;
+                let mut ports = self.ports.lock().unwrap();
+                let port = &mut ports[index];
+                let mut data = if _read_only { port.peek() } else { port.read() };
+                if let Some(expansion) = self.expansion.lock().unwrap().as_mut() {
+                    data |= if _read_only {
+                        expansion.peek(index)
+                    } else {
+                        expansion.read(index)
+                    };
+                }
+                // only bits 0-4 are driven; the rest keep the last byte on
+                // the bus, the high byte of the address
+                (addr >> 8) as u8 & 0xE0 | data & 0x1F
+            }
+            (0x4020..=0xFFFF, Some(cartridge)) if _read_only => {
+                cartridge.read().unwrap().read(addr, true)
+            }
+            (0x4020..=0xFFFF, Some(cartridge)) => cartridge.write().unwrap().cpu_read(addr),
+            _ => self.ram.read().unwrap().read(addr, false),
+        }
     }
 }
 
@@ -34,7 +105,43 @@ impl<Memory> Bus<Memory>
 where
     Memory: Read + Write,
 {
-    pub fn new(ram: Arc<RwLock<Memory>>) -> Self {
-        Bus { ram }
+    pub fn new(
+        ram: Arc<RwLock<Memory>>,
+        ppu: Arc<RwLock<PPU<Cartridge>>>,
+        apu: Arc<RwLock<Apu>>,
+    ) -> Self {
+        Bus {
+            ram,
+            ppu,
+            apu,
+            cartridge: None,
+            oam_dma: None,
+            pc: 0,
+            events: Mutex::new(EventLog::default()),
+            ports: Mutex::new(input::default_ports()),
+            expansion: Mutex::new(None),
+        }
+    }
+
+    /// Logs an event at the PPU's current position.
+    pub fn log_event(&self, kind: EventKind, addr: u16, value: u8) {
+        if self.events.lock().unwrap().enabled {
+            let (scanline, dot) = {
+                let ppu = self.ppu.read().unwrap();
+                (ppu.scanline, ppu.dot)
+            };
+            self.log_event_at(kind, scanline, dot, addr, value);
+        }
+    }
+
+    pub fn log_event_at(&self, kind: EventKind, scanline: u16, dot: u16, addr: u16, value: u8) {
+        self.events.lock().unwrap().record(Event {
+            kind,
+            scanline,
+            dot,
+            pc: self.pc,
+            addr,
+            value,
+        });
     }
 }
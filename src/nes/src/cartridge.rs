@@ -0,0 +1,91 @@
+use nesemu_core::{Mirroring, Read, Write};
+use nesemu_ppu::ppu::PpuBus;
+
+use crate::audio::ChannelState;
+use crate::mapper::{self, Mapper};
+use crate::rom_loader::{Rom, RomError};
+use crate::state::StateError;
+
+/// A loaded game: the rom contents plus whatever mapper hardware the board
+/// had. The CPU side is exposed through `Read`/`Write` like the rest of the
+/// bus, the PPU side through `PpuBus`.
+pub struct Cartridge {
+    mapper: Box<dyn Mapper>,
+    pub battery: bool,
+}
+
+impl Cartridge {
+    pub fn new(rom: &Rom) -> Result<Self, RomError> {
+        Ok(Cartridge {
+            mapper: mapper::for_rom(rom)?,
+            battery: rom.battery,
+        })
+    }
+
+    /// For things that aren't built from a `Rom`, like the NSF player.
+    pub fn with_mapper(mapper: Box<dyn Mapper>) -> Self {
+        Cartridge {
+            mapper,
+            battery: false,
+        }
+    }
+
+    /// A CPU read that is allowed to have side effects on the board.
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.mapper.cpu_read_mut(addr)
+    }
+
+    pub fn clock(&mut self) {
+        self.mapper.clock()
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    pub fn audio(&self, levels: &mut Vec<f32>) {
+        self.mapper.audio(levels)
+    }
+
+    pub fn audio_channels(&self, clock: f64) -> Vec<ChannelState> {
+        self.mapper.audio_channels(clock)
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.mapper.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        self.mapper.load_state(state)
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.mapper.as_mut()
+    }
+}
+
+impl Read for Cartridge {
+    fn read(&self, addr: u16, _read_only: bool) -> u8 {
+        self.mapper.cpu_read(addr)
+    }
+}
+
+impl Write for Cartridge {
+    fn write(&mut self, addr: u16, data: u8) {
+        self.mapper.cpu_write(addr, data)
+    }
+}
+
+impl PpuBus for Cartridge {
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.mapper.ppu_read(addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.mapper.ppu_write(addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+}
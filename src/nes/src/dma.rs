@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::{Read, Write};
+
+use crate::bus::Bus;
+use crate::memory::CpuMemory;
+
+/// Direct memory access: the sprite copy started by writing a page number
+/// to $4014, and the sample fetches of the APU's DMC channel. Both take
+/// the bus away from the CPU, which sits halted until they are done.
+///
+/// Reads only happen on "get" (even) cycles and writes on "put" (odd)
+/// cycles, so an OAM copy takes a halt cycle, sometimes an alignment
+/// cycle, then 256 get/put pairs: 513 or 514 cycles in all.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Dma {
+    pending_oam: Option<u8>,
+    oam: Option<OamTransfer>,
+    dmc_request: Option<u16>,
+    dmc_addr: u16,
+    dmc_stall: u8,
+    dmc_sample: Option<u8>,
+    /// Set on the cycle a DMC fetch halts the CPU by itself.
+    dmc_halt: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OamTransfer {
+    page: u8,
+    index: u16,
+    data: Option<u8>,
+    halted: bool,
+}
+
+/// A DMC fetch on its own halts the CPU for this many cycles.
+const DMC_STALL: u8 = 4;
+
+impl Dma {
+    /// Queues a copy of page `$XX00` to OAM, as a write to $4014 does. It
+    /// starts once the current instruction is done.
+    pub fn start_oam(&mut self, page: u8) {
+        self.pending_oam = Some(page);
+    }
+
+    /// Asks for a DMC sample byte. The result shows up in
+    /// `take_dmc_sample` a few cycles later.
+    pub fn request_dmc(&mut self, addr: u16) {
+        self.dmc_request = Some(addr);
+    }
+
+    pub fn take_dmc_sample(&mut self) -> Option<u8> {
+        self.dmc_sample.take()
+    }
+
+    /// Whether a DMC fetch halted the CPU this cycle, interrupting
+    /// whatever read it was doing rather than an OAM copy.
+    pub fn dmc_halted(&self) -> bool {
+        self.dmc_halt
+    }
+
+    pub fn active(&self) -> bool {
+        self.pending_oam.is_some()
+            || self.oam.is_some()
+            || self.dmc_request.is_some()
+            || self.dmc_stall > 0
+    }
+
+    /// Runs one CPU cycle of DMA. Returns whether the CPU is halted for it.
+    pub fn clock(&mut self, bus: &mut Bus<CpuMemory>, cycle: u64, instruction_done: bool) -> bool {
+        self.dmc_halt = false;
+        if self.oam.is_none() && instruction_done {
+            if let Some(page) = self.pending_oam.take() {
+                self.oam = Some(OamTransfer {
+                    page,
+                    index: 0,
+                    data: None,
+                    halted: false,
+                });
+            }
+        }
+
+        let get = cycle & 1 == 0;
+        if let Some(oam) = &mut self.oam {
+            if !oam.halted {
+                oam.halted = true;
+            } else if get {
+                if let Some(addr) = self.dmc_request.take() {
+                    // the DMC takes this get cycle, pushing the OAM read to
+                    // the next one: two cycles lost
+                    self.dmc_sample = Some(bus.read(addr, false));
+                } else if oam.data.is_none() {
+                    let addr = (oam.page as u16) << 8 | oam.index;
+                    oam.data = Some(bus.read(addr, false));
+                }
+            } else if let Some(data) = oam.data.take() {
+                bus.write(0x2004, data);
+                oam.index += 1;
+                if oam.index == 0x100 {
+                    self.oam = None;
+                }
+            }
+            return true;
+        }
+
+        if self.dmc_stall > 0 {
+            self.dmc_stall -= 1;
+            if self.dmc_stall == 0 {
+                self.dmc_sample = Some(bus.read(self.dmc_addr, false));
+            }
+            return true;
+        }
+        if let Some(addr) = self.dmc_request.take() {
+            self.dmc_addr = addr;
+            self.dmc_stall = DMC_STALL - 1;
+            self.dmc_halt = true;
+            return true;
+        }
+        false
+    }
+}
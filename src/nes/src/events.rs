@@ -0,0 +1,76 @@
+//! A log of what happened during a frame and where the PPU was when it
+//! did, for the event viewer.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    PpuRead,
+    PpuWrite,
+    /// Writes to the cartridge outside of PRG RAM.
+    MapperWrite,
+    Nmi,
+    Irq,
+    SpriteZeroHit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub scanline: u16,
+    pub dot: u16,
+    /// The instruction that caused it, or the one running at the time.
+    pub pc: u16,
+    /// Zero for the interrupts and sprite-0 hits.
+    pub addr: u16,
+    pub value: u8,
+}
+
+/// Only records while enabled, since it's only wanted while somebody is
+/// looking at it.
+#[derive(Default)]
+pub struct EventLog {
+    pub enabled: bool,
+    frame: Vec<Event>,
+    last_frame: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn record(&mut self, event: Event) {
+        if self.enabled {
+            self.frame.push(event);
+        }
+    }
+
+    /// The frame in progress becomes the last frame.
+    pub fn end_frame(&mut self) {
+        self.last_frame = std::mem::take(&mut self.frame);
+    }
+
+    /// Everything from the last complete frame.
+    pub fn last_frame(&self) -> &[Event] {
+        &self.last_frame
+    }
+
+    /// The events so far in the frame being drawn.
+    pub fn current_frame(&self) -> &[Event] {
+        &self.frame
+    }
+}
+
+/// What the registers an event touched are called.
+pub fn register_name(addr: u16) -> Option<&'static str> {
+    let name = match addr {
+        0x2000..=0x3FFF => match addr & 0x07 {
+            0 => "PPUCTRL",
+            1 => "PPUMASK",
+            2 => "PPUSTATUS",
+            3 => "OAMADDR",
+            4 => "OAMDATA",
+            5 => "PPUSCROLL",
+            6 => "PPUADDR",
+            _ => "PPUDATA",
+        },
+        0x4014 => "OAMDMA",
+        _ => return None,
+    };
+    Some(name)
+}
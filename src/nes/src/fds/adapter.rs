@@ -0,0 +1,388 @@
+use std::any::Any;
+
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::audio::ChannelState;
+use crate::fds::audio::FdsAudio;
+use crate::fds::{update_crc, FdsDisk};
+use crate::mapper::Mapper;
+use crate::state::{self, StateError};
+
+const PRG_RAM_SIZE: usize = 0x8000;
+const CHR_RAM_SIZE: usize = 0x2000;
+/// CPU cycles for one byte to pass under the head.
+const BYTE_CYCLES: u32 = 150;
+/// How long the head takes to get back to the start of the disk.
+const REWIND_CYCLES: u32 = 50000;
+/// How long a side stays out of the drive when switching, about half a
+/// second.
+const INSERT_CYCLES: u32 = 1_000_000;
+
+/// The RAM adapter: 32k of program ram at $6000-$DFFF, the BIOS at
+/// $E000-$FFFF, 8k of CHR ram, the timer IRQ, the disk drive and the
+/// wavetable sound channel.
+#[derive(Serialize, Deserialize)]
+pub struct FdsAdapter {
+    #[serde(skip)]
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    pub disk: FdsDisk,
+    side: Option<usize>,
+    pending_side: Option<usize>,
+    insert_delay: u32,
+    pub audio: FdsAudio,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+    disk_irq: bool,
+
+    disk_io: bool,
+    sound_io: bool,
+    ext_output: u8,
+
+    /// $4025
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    horizontal: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    previous_crc_control: bool,
+    crc: u16,
+    head: usize,
+    delay: u32,
+}
+
+impl FdsAdapter {
+    pub fn new(bios: Vec<u8>, disk: FdsDisk) -> Self {
+        FdsAdapter {
+            bios,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_ram: vec![0; CHR_RAM_SIZE],
+            disk,
+            side: Some(0),
+            pending_side: None,
+            insert_delay: 0,
+            audio: FdsAudio::new(),
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+            disk_irq: false,
+            disk_io: false,
+            sound_io: false,
+            ext_output: 0,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            horizontal: false,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            previous_crc_control: false,
+            crc: 0,
+            head: 0,
+            delay: 0,
+        }
+    }
+
+    pub fn side(&self) -> Option<usize> {
+        self.side
+    }
+
+    pub fn eject(&mut self) {
+        self.side = None;
+        self.pending_side = None;
+    }
+
+    pub fn insert(&mut self, side: usize) {
+        if side < self.disk.side_count() {
+            self.side = None;
+            self.pending_side = Some(side);
+            self.insert_delay = INSERT_CYCLES;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            if !self.irq_repeat {
+                self.irq_enabled = false;
+            }
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_insert(&mut self) {
+        if let Some(side) = self.pending_side {
+            self.insert_delay = self.insert_delay.saturating_sub(1);
+            if self.insert_delay == 0 {
+                self.side = Some(side);
+                self.pending_side = None;
+            }
+        }
+    }
+
+    /// Moves the disk along under the head. Each byte takes `BYTE_CYCLES`;
+    /// in read mode the gap before a block is skipped until its start
+    /// mark, in write mode whatever is in $4024 goes onto the disk, then
+    /// the CRC once the BIOS asks for it.
+    fn clock_drive(&mut self) {
+        let Some(side) = self.side else {
+            self.scanning = false;
+            self.end_of_head = true;
+            return;
+        };
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.head = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut irq = self.disk_irq_enabled;
+        if self.read_mode {
+            let data = self.disk.side(side)[self.head];
+            if !self.previous_crc_control {
+                self.crc = update_crc(self.crc, data);
+            }
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if data != 0 && !self.gap_ended {
+                // the start mark itself isn't handed to the BIOS
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                self.disk_irq |= irq;
+            }
+            if !self.disk_ready {
+                data = 0;
+            }
+            if !self.crc_control {
+                self.crc = update_crc(self.crc, data);
+            } else {
+                if !self.previous_crc_control {
+                    self.crc = update_crc(update_crc(self.crc, 0), 0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            self.disk.write(side, self.head, data);
+            self.gap_ended = false;
+        }
+
+        self.previous_crc_control = self.crc_control;
+        self.head += 1;
+        if self.head >= self.disk.side(side).len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn read_register(&mut self, addr: u16) -> u8 {
+        let value = self.peek_register(addr);
+        match addr {
+            0x4030 => {
+                self.timer_irq = false;
+                self.disk_irq = false;
+                self.transfer_complete = false;
+            }
+            0x4031 => {
+                self.disk_irq = false;
+                self.transfer_complete = false;
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x4030 if self.disk_io => {
+                (self.timer_irq as u8)
+                    | (self.transfer_complete as u8) << 1
+                    | (self.end_of_head as u8) << 6
+                    | (self.disk_ready as u8) << 7
+            }
+            0x4031 if self.disk_io => self.read_data,
+            0x4032 if self.disk_io => {
+                let inserted = self.side.is_some();
+                0x40 | (!inserted as u8)
+                    | ((!inserted || !self.scanning) as u8) << 1
+                    | (!inserted as u8) << 2
+            }
+            // bit 7 is the battery check, always good
+            0x4033 if self.disk_io => (self.ext_output & 0x7F) | 0x80,
+            0x4040..=0x4092 if self.sound_io => self.audio.read(addr),
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for FdsAdapter {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => self.peek_register(addr),
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000],
+            0xE000..=0xFFFF => self.bios[addr as usize - 0xE000],
+            _ => 0,
+        }
+    }
+
+    fn cpu_read_mut(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => self.read_register(addr),
+            _ => self.cpu_read(addr),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | data as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (data as u16) << 8,
+            0x4022 if self.disk_io => {
+                self.irq_repeat = data & 0x01 != 0;
+                self.irq_enabled = data & 0x02 != 0;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_io = data & 0x01 != 0;
+                self.sound_io = data & 0x02 != 0;
+                if !self.disk_io {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_io => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_io => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.motor_on = data & 0x01 != 0;
+                self.reset_transfer = data & 0x02 != 0;
+                self.read_mode = data & 0x04 != 0;
+                self.horizontal = data & 0x08 != 0;
+                self.crc_control = data & 0x10 != 0;
+                self.disk_ready = data & 0x40 != 0;
+                self.disk_irq_enabled = data & 0x80 != 0;
+            }
+            0x4026 if self.disk_io => self.ext_output = data,
+            0x4040..=0x408A if self.sound_io => self.audio.write(addr, data),
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize & (CHR_RAM_SIZE - 1)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr_ram[addr as usize & (CHR_RAM_SIZE - 1)] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.horizontal {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.disk_io {
+            self.clock_timer();
+        }
+        self.clock_insert();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio(&self, levels: &mut Vec<f32>) {
+        levels.push(self.audio.output());
+    }
+
+    fn audio_channels(&self, clock: f64) -> Vec<ChannelState> {
+        vec![self.audio.state(clock)]
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: FdsAdapter = state::decode(state)?;
+        loaded.bios = std::mem::take(&mut self.bios);
+        loaded.disk.original = std::mem::take(&mut self.disk.original);
+        *self = loaded;
+        Ok(())
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+}
@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+
+use crate::audio::ChannelState;
+
+/// Gain values above this are accepted but clipped on output.
+const MAX_GAIN: u8 = 32;
+/// Output scale for each setting of the master volume bits in $4089.
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+/// Counter steps for the 3-bit mod table entries. `None` resets it.
+const MOD_STEPS: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+
+#[derive(Default, Serialize, Deserialize)]
+struct Envelope {
+    /// $4080/$4084 bit 7: gain is set directly instead of ramping.
+    disabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8, master_speed: u8) {
+        self.disabled = data & 0x80 != 0;
+        self.increase = data & 0x40 != 0;
+        self.speed = data & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < MAX_GAIN {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+/// The FDS sound channel: a 64 step, 6-bit wavetable whose pitch is bent
+/// by a second table of frequency modulation steps, each with its own
+/// gain envelope.
+#[derive(Serialize, Deserialize)]
+pub struct FdsAudio {
+    #[serde(with = "nesemu_core::array_serde")]
+    wave: [u8; 64],
+    wave_write: bool,
+    wave_freq: u16,
+    wave_halt: bool,
+    wave_acc: u32,
+    wave_pos: usize,
+    envelope_halt: bool,
+    volume: Envelope,
+    /// Volume gain is only picked up at the start of each wave cycle.
+    latched_gain: u8,
+    master_volume: u8,
+    master_speed: u8,
+
+    #[serde(with = "nesemu_core::array_serde")]
+    mod_table: [u8; 64],
+    mod_pos: usize,
+    mod_freq: u16,
+    mod_halt: bool,
+    mod_acc: u32,
+    mod_counter: i8,
+    modulation: Envelope,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave: [0; 64],
+            wave_write: false,
+            wave_freq: 0,
+            wave_halt: true,
+            wave_acc: 0,
+            wave_pos: 0,
+            envelope_halt: false,
+            volume: Envelope::default(),
+            latched_gain: 0,
+            master_volume: 0,
+            master_speed: 0xE8,
+            mod_table: [0; 64],
+            mod_pos: 0,
+            mod_freq: 0,
+            mod_halt: true,
+            mod_acc: 0,
+            mod_counter: 0,
+            modulation: Envelope::default(),
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => 0x40 | self.wave[addr as usize - 0x4040],
+            0x4090 => 0x40 | self.volume.gain,
+            0x4092 => 0x40 | self.modulation.gain,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => self.wave[addr as usize - 0x4040] = data & 0x3F,
+            0x4080 => self.volume.write(data, self.master_speed),
+            0x4082 => self.wave_freq = (self.wave_freq & 0x0F00) | data as u16,
+            0x4083 => {
+                self.wave_freq = (self.wave_freq & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.wave_halt = data & 0x80 != 0;
+                self.envelope_halt = data & 0x40 != 0;
+                if self.wave_halt {
+                    self.wave_acc = 0;
+                    self.wave_pos = 0;
+                }
+                if self.envelope_halt {
+                    self.volume.reset_timer(self.master_speed);
+                    self.modulation.reset_timer(self.master_speed);
+                }
+            }
+            0x4084 => self.modulation.write(data, self.master_speed),
+            // 7-bit signed
+            0x4085 => self.mod_counter = ((data << 1) as i8) >> 1,
+            0x4086 => self.mod_freq = (self.mod_freq & 0x0F00) | data as u16,
+            0x4087 => {
+                self.mod_freq = (self.mod_freq & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.mod_halt = data & 0x80 != 0;
+                if self.mod_halt {
+                    self.mod_acc = 0;
+                }
+            }
+            // each write fills two entries, and only while halted
+            0x4088 if self.mod_halt => {
+                self.mod_table[self.mod_pos] = data & 0x07;
+                self.mod_table[self.mod_pos + 1] = data & 0x07;
+                self.mod_pos = (self.mod_pos + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.wave_write = data & 0x80 != 0;
+                self.master_volume = data & 0x03;
+            }
+            0x408A => self.master_speed = data,
+            _ => {}
+        }
+    }
+
+    /// Called once per CPU cycle.
+    pub fn clock(&mut self) {
+        if !self.envelope_halt && !self.wave_halt {
+            self.volume.clock(self.master_speed);
+            self.modulation.clock(self.master_speed);
+        }
+
+        if !self.mod_halt && self.mod_freq > 0 {
+            self.mod_acc += self.mod_freq as u32;
+            if self.mod_acc >= 0x10000 {
+                self.mod_acc -= 0x10000;
+                match MOD_STEPS[self.mod_table[self.mod_pos] as usize] {
+                    Some(step) => {
+                        // wraps within 7 bits
+                        self.mod_counter = (self.mod_counter.wrapping_add(step) << 1) >> 1;
+                    }
+                    None => self.mod_counter = 0,
+                }
+                self.mod_pos = (self.mod_pos + 1) & 0x3F;
+            }
+        }
+
+        let pitch = self.pitch();
+        if !self.wave_halt && !self.wave_write && pitch > 0 {
+            self.wave_acc += pitch as u32;
+            if self.wave_acc >= 0x10000 {
+                self.wave_acc &= 0xFFFF;
+                self.wave_pos = (self.wave_pos + 1) & 0x3F;
+            }
+        }
+        if self.wave_pos == 0 {
+            self.latched_gain = self.volume.gain.min(MAX_GAIN);
+        }
+    }
+
+    /// Wave frequency after modulation, following the hardware's
+    /// rounding exactly since games depend on the resulting tuning.
+    fn pitch(&self) -> i32 {
+        let freq = self.wave_freq as i32;
+        if self.mod_halt {
+            return freq;
+        }
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= freq;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        freq + temp
+    }
+
+    pub fn state(&self, clock: f64) -> ChannelState {
+        let pitch = self.pitch();
+        let sounding = !self.wave_halt && pitch > 0 && self.latched_gain > 0;
+        ChannelState {
+            name: "FDS",
+            period: self.wave_freq,
+            volume: self.latched_gain,
+            duty: None,
+            // the accumulator steps through 64 entries per 2^16 overflows
+            frequency: sounding.then(|| clock * pitch as f64 / 65536.0 / 64.0),
+        }
+    }
+
+    /// The current output level, 0.0 to 1.0.
+    pub fn output(&self) -> f32 {
+        let level = self.wave[self.wave_pos] as f32 * self.latched_gain as f32;
+        level / (63.0 * MAX_GAIN as f32) * MASTER_VOLUME[self.master_volume as usize]
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::{Read, Region};
+
+use crate::fds::adapter::FdsAdapter;
+use crate::patch;
+use crate::rom_loader::RomError;
+use crate::Nes;
+
+pub mod adapter;
+pub mod audio;
+
+pub const FDS_MAGIC: &[u8] = b"FDS\x1A";
+const DISK_INFO_MAGIC: &[u8] = b"\x01*NINTENDO-HVC*";
+const HEADER_SIZE: usize = 0x10;
+pub const SIDE_SIZE: usize = 65500;
+pub const BIOS_SIZE: usize = 0x2000;
+
+/// 28300 bits of nothing before the first block on a real disk.
+const LEAD_IN: usize = 28300 / 8;
+/// The gap the drive leaves between blocks, 976 bits.
+const GAP: usize = 976 / 8;
+const BLOCK_START: u8 = 0x80;
+
+pub fn is_fds(bytes: &[u8]) -> bool {
+    bytes.starts_with(FDS_MAGIC) || bytes.starts_with(DISK_INFO_MAGIC)
+}
+
+/// Where modifications to a disk image are kept: `game.sav` next to
+/// `game.fds`, as an IPS patch against the original image.
+pub fn save_path_for(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+/// A disk image in both forms: the .fds file as it was loaded, and each
+/// side laid out the way the drive sees it (lead-in, start marks, CRCs
+/// and gaps), which is what the game reads and writes.
+#[derive(Serialize, Deserialize)]
+pub struct FdsDisk {
+    /// Left out of save states, which only need what the game wrote.
+    #[serde(skip)]
+    original: Vec<u8>,
+    sides: Vec<Vec<u8>>,
+    modified: bool,
+}
+
+impl FdsDisk {
+    pub fn parse(bytes: &[u8]) -> Result<FdsDisk, RomError> {
+        if !is_fds(bytes) {
+            return Err(RomError::BadHeader);
+        }
+        let data = if bytes.starts_with(FDS_MAGIC) {
+            bytes.get(HEADER_SIZE..).ok_or(RomError::Truncated)?
+        } else {
+            bytes
+        };
+        if data.len() < DISK_INFO_MAGIC.len() {
+            return Err(RomError::Truncated);
+        }
+        let mut original = data.to_vec();
+        let padded = original.chunks(SIDE_SIZE).len() * SIDE_SIZE;
+        original.resize(padded, 0);
+        Ok(FdsDisk {
+            sides: original.chunks(SIDE_SIZE).map(to_raw).collect(),
+            original,
+            modified: false,
+        })
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    pub fn side(&self, side: usize) -> &[u8] {
+        &self.sides[side]
+    }
+
+    pub fn write(&mut self, side: usize, pos: usize, data: u8) {
+        if self.sides[side][pos] != data {
+            self.sides[side][pos] = data;
+            self.modified = true;
+        }
+    }
+
+    pub fn modified(&self) -> bool {
+        self.modified
+    }
+
+    /// The disk as a headerless .fds image, with whatever the game wrote.
+    pub fn image(&self) -> Vec<u8> {
+        self.sides.iter().flat_map(|side| from_raw(side)).collect()
+    }
+
+    /// An IPS patch from the original image to the current one.
+    pub fn save_data(&self) -> Result<Vec<u8>, RomError> {
+        Ok(patch::create_ips(&self.original, &self.image())?)
+    }
+
+    /// Brings back modifications made in an earlier session. The original
+    /// image is kept so the next save is still against it.
+    pub fn apply_save(&mut self, save: &[u8]) -> Result<(), RomError> {
+        let mut image = patch::apply_ips(&self.original, save)?;
+        image.resize(self.original.len(), 0);
+        self.sides = image.chunks(SIDE_SIZE).map(to_raw).collect();
+        Ok(())
+    }
+}
+
+/// Block lengths, not counting the start mark and CRC. File data blocks
+/// (type 4) take their size from the file header block before them.
+fn block_len(data: &[u8], pos: usize, file_size: usize) -> Option<usize> {
+    match data.get(pos)? {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+fn file_size(header: &[u8]) -> usize {
+    u16::from_le_bytes([header[13], header[14]]) as usize
+}
+
+/// Turns a side from an .fds file into what passes under the drive head.
+pub fn to_raw(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEAD_IN];
+    let mut pos = 0;
+    let mut size = 0;
+    while let Some(len) = block_len(side, pos, size) {
+        let Some(block) = side.get(pos..pos + len) else {
+            break;
+        };
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+        raw.push(BLOCK_START);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&crc(block).to_le_bytes());
+        raw.resize(raw.len() + GAP, 0);
+        pos += len;
+    }
+    raw.resize(raw.len().max(LEAD_IN + SIDE_SIZE + 0x1000), 0);
+    raw
+}
+
+/// The other way round: finds the blocks in a raw side and strips the
+/// marks, CRCs and gaps.
+pub fn from_raw(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut pos = 0;
+    let mut size = 0;
+    loop {
+        while pos < raw.len() && raw[pos] != BLOCK_START {
+            pos += 1;
+        }
+        pos += 1;
+        let Some(len) = block_len(raw, pos, size) else {
+            break;
+        };
+        let Some(block) = raw.get(pos..pos + len) else {
+            break;
+        };
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+        side.extend_from_slice(block);
+        pos += len + 2;
+    }
+    side.resize(SIDE_SIZE, 0);
+    side
+}
+
+/// The CRC the drive appends to every block, start mark included.
+pub fn crc(block: &[u8]) -> u16 {
+    let mut crc = 0;
+    for byte in [BLOCK_START].iter().chain(block).chain(&[0, 0]) {
+        crc = update_crc(crc, *byte);
+    }
+    crc
+}
+
+pub(crate) fn update_crc(mut crc: u16, byte: u8) -> u16 {
+    for bit in 0..8 {
+        let carry = crc & 1 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if byte & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+impl Nes {
+    /// Loads a disk image. The RAM adapter can't do anything without the
+    /// BIOS, which has to be supplied through `fds_bios` first.
+    pub fn load_fds(&mut self, bytes: &[u8]) -> Result<(), RomError> {
+        let bios = self.fds_bios.clone().ok_or(RomError::MissingBios)?;
+        if bios.len() != BIOS_SIZE {
+            return Err(RomError::MissingBios);
+        }
+        let disk = FdsDisk::parse(bytes)?;
+        self.nsf = None;
+        self.insert_mapper(Box::new(FdsAdapter::new(bios, disk)));
+        // the disk system was only sold in Japan
+        self.set_detected_region(Some(Region::Ntsc));
+
+        self.cpu.reset();
+        let bus = self.bus.read().unwrap();
+        let low = bus.read(0xFFFC, true) as u16;
+        let high = bus.read(0xFFFD, true) as u16;
+        self.cpu.pgrm_ctr = (high << 8) | low;
+        Ok(())
+    }
+
+    fn with_fds<T>(&self, f: impl FnOnce(&mut FdsAdapter) -> T) -> Option<T> {
+        let bus = self.bus.read().unwrap();
+        let mut cartridge = bus.cartridge.as_ref()?.write().unwrap();
+        let adapter = cartridge
+            .mapper_mut()
+            .as_any_mut()?
+            .downcast_mut::<FdsAdapter>()?;
+        Some(f(adapter))
+    }
+
+    /// Number of disk sides, or `None` when no disk is loaded.
+    pub fn fds_side_count(&self) -> Option<usize> {
+        self.with_fds(|fds| fds.disk.side_count())
+    }
+
+    /// The side in the drive, `None` when ejected (or not an FDS game).
+    pub fn fds_current_side(&self) -> Option<usize> {
+        self.with_fds(|fds| fds.side()).flatten()
+    }
+
+    pub fn fds_eject(&mut self) {
+        self.with_fds(|fds| fds.eject());
+    }
+
+    /// Ejects the current side and inserts another one a moment later,
+    /// since games look for the disk being taken out before they accept
+    /// the next.
+    pub fn fds_insert_side(&mut self, side: usize) {
+        self.with_fds(|fds| fds.insert(side));
+    }
+
+    /// The disk as it is now, as a headerless .fds image.
+    pub fn fds_disk_image(&self) -> Option<Vec<u8>> {
+        self.with_fds(|fds| fds.disk.image())
+    }
+
+    /// Applies a save file made by `save_fds_disk` to the loaded disk.
+    pub fn load_fds_save(&mut self, save: &[u8]) -> Result<(), RomError> {
+        self.with_fds(|fds| fds.disk.apply_save(save))
+            .unwrap_or(Ok(()))
+    }
+
+    /// Writes the disk modifications next to the rom as an IPS patch. Does
+    /// nothing if the game never wrote to the disk, or the rom didn't come
+    /// from a file.
+    pub fn save_fds_disk(&self) -> Result<(), RomError> {
+        let Some(path) = &self.rom_path else {
+            return Ok(());
+        };
+        let save = self.with_fds(|fds| match fds.disk.modified() {
+            true => fds.disk.save_data().map(Some),
+            false => Ok(None),
+        });
+        if let Some(save) = save.transpose()?.flatten() {
+            fs::write(save_path_for(path), save)?;
+        }
+        Ok(())
+    }
+}
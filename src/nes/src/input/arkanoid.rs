@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::{ExpansionDevice, InputDevice, Pointer};
+use crate::state;
+
+/// The knob's readings at its two ends.
+const KNOB_LEFT: u8 = 0x62;
+const KNOB_RIGHT: u8 = 0xF2;
+
+/// The Vaus paddle's knob and button, worked by the mouse: the knob
+/// follows it across the picture.
+#[derive(Default, Serialize, Deserialize)]
+struct Vaus {
+    knob: u8,
+    button: bool,
+    strobe: bool,
+    /// The knob reading being shifted out, top bit first.
+    shift: u8,
+}
+
+impl Vaus {
+    fn new() -> Self {
+        Vaus {
+            knob: KNOB_LEFT,
+            ..Default::default()
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        let strobe = data & 0x01 != 0;
+        if self.strobe || strobe {
+            self.shift = self.knob;
+        }
+        self.strobe = strobe;
+    }
+
+    /// The next knob bit, which the paddle sends inverted.
+    fn knob_bit(&self) -> u8 {
+        !self.shift >> 7 & 0x01
+    }
+
+    fn next_bit(&mut self) {
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        if let Some((x, _)) = pointer.position {
+            let range = (KNOB_RIGHT - KNOB_LEFT) as f32;
+            let fraction = (x / 256.0).clamp(0.0, 1.0);
+            self.knob = KNOB_LEFT + (fraction * range) as u8;
+        }
+        self.button = pointer.pressed;
+    }
+}
+
+/// The NES Vaus, in port 1: knob bits on D3, the button on D4.
+#[derive(Serialize, Deserialize)]
+pub struct Arkanoid {
+    vaus: Vaus,
+}
+
+impl Default for Arkanoid {
+    fn default() -> Self {
+        Arkanoid { vaus: Vaus::new() }
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn write(&mut self, data: u8) {
+        self.vaus.write(data);
+    }
+
+    fn read(&mut self) -> u8 {
+        let data = self.peek();
+        self.vaus.next_bit();
+        data
+    }
+
+    fn peek(&self) -> u8 {
+        let button = if self.vaus.button { 0x10 } else { 0x00 };
+        self.vaus.knob_bit() << 3 | button
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        self.vaus.set_pointer(pointer);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
+
+/// The Famicom Vaus, in the expansion port: the button on D1 of $4016
+/// and knob bits on D1 of $4017.
+#[derive(Serialize, Deserialize)]
+pub struct FamicomArkanoid {
+    vaus: Vaus,
+}
+
+impl Default for FamicomArkanoid {
+    fn default() -> Self {
+        FamicomArkanoid { vaus: Vaus::new() }
+    }
+}
+
+impl ExpansionDevice for FamicomArkanoid {
+    fn write(&mut self, data: u8) {
+        self.vaus.write(data);
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let data = self.peek(port);
+        if port == 1 {
+            self.vaus.next_bit();
+        }
+        data
+    }
+
+    fn peek(&self, port: usize) -> u8 {
+        match port {
+            0 => (self.vaus.button as u8) << 1,
+            _ => self.vaus.knob_bit() << 1,
+        }
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        self.vaus.set_pointer(pointer);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
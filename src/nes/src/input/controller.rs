@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::{InputDevice, MovieDevice};
+use crate::state;
+
+/// The standard controller: a shift register loaded with the buttons while
+/// the strobe is high, then read out a bit at a time, A first.
+#[derive(Default, Serialize, Deserialize)]
+pub struct StandardController {
+    pub buttons: u8,
+    strobe: bool,
+    shift: u8,
+}
+
+impl InputDevice for StandardController {
+    /// The buttons are latched for reading as the strobe goes low.
+    fn write(&mut self, data: u8) {
+        let strobe = data & 0x01 != 0;
+        if self.strobe || strobe {
+            self.shift = self.buttons;
+        }
+        self.strobe = strobe;
+    }
+
+    /// Official controllers read 1 once all eight buttons are out.
+    fn read(&mut self) -> u8 {
+        let bit = self.peek();
+        if !self.strobe {
+            self.shift = self.shift >> 1 | 0x80;
+        }
+        bit
+    }
+
+    /// With the strobe held high the register keeps reloading, so only A
+    /// ever comes out.
+    fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons & 0x01
+        } else {
+            self.shift & 0x01
+        }
+    }
+
+    fn set_buttons(&mut self, index: usize, buttons: u8) {
+        if index == 0 {
+            self.buttons = buttons;
+        }
+    }
+
+    fn movie_device(&self) -> Option<MovieDevice> {
+        Some(MovieDevice::Controller)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
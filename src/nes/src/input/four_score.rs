@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::{ExpansionDevice, InputDevice, MovieDevice};
+use crate::state;
+
+/// The signatures that follow the buttons, in the order they are read: 0,
+/// 0, 0, 1 then zeros on port 0 of a Four Score, and 0, 0, 1 on port 1.
+/// Hori's are the other way round.
+const SIGNATURES: [u8; 2] = [0x08, 0x04];
+
+/// One side of the NES Four Score. Port 0 shifts out players 1 and 3 and
+/// port 1 players 2 and 4, 8 bits each, then a signature byte that lets
+/// games tell the adapter is there.
+#[derive(Serialize, Deserialize)]
+pub struct FourScore {
+    port: usize,
+    buttons: [u8; 2],
+    strobe: bool,
+    shift: u32,
+}
+
+impl FourScore {
+    pub fn new(port: usize) -> Self {
+        FourScore {
+            port,
+            buttons: [0; 2],
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    /// Both sides, for ports 0 and 1.
+    pub fn pair() -> [FourScore; 2] {
+        [FourScore::new(0), FourScore::new(1)]
+    }
+
+    fn latch(&mut self) {
+        self.shift = self.buttons[0] as u32
+            | (self.buttons[1] as u32) << 8
+            | (SIGNATURES[self.port] as u32) << 16;
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, data: u8) {
+        let strobe = data & 0x01 != 0;
+        if self.strobe || strobe {
+            self.latch();
+        }
+        self.strobe = strobe;
+    }
+
+    /// Reads 1 once all 24 bits are out, like a controller does after 8.
+    fn read(&mut self) -> u8 {
+        let bit = self.peek();
+        if !self.strobe {
+            self.shift = self.shift >> 1 | 1 << 23;
+        }
+        bit
+    }
+
+    fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons[0] & 0x01
+        } else {
+            (self.shift & 0x01) as u8
+        }
+    }
+
+    fn set_buttons(&mut self, index: usize, buttons: u8) {
+        if let Some(held) = self.buttons.get_mut(index) {
+            *held = buttons;
+        }
+    }
+
+    fn movie_device(&self) -> Option<MovieDevice> {
+        Some(MovieDevice::FourScore)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
+
+/// Hori's Famicom adapter in 4-player mode, in the expansion port. It
+/// talks like a Four Score on D1 of $4016 and $4017, but players 1 and 2
+/// stay on the Famicom's own controllers, so its first 8 bits on each port
+/// are empty and players 3 and 4 follow, then the signature.
+#[derive(Default, Serialize, Deserialize)]
+pub struct HoriAdapter {
+    buttons: [u8; 2],
+    strobe: bool,
+    shift: [u32; 2],
+}
+
+impl HoriAdapter {
+    fn latch(&mut self) {
+        for port in 0..2 {
+            self.shift[port] =
+                (self.buttons[port] as u32) << 8 | (SIGNATURES[1 - port] as u32) << 16;
+        }
+    }
+}
+
+impl ExpansionDevice for HoriAdapter {
+    fn write(&mut self, data: u8) {
+        let strobe = data & 0x01 != 0;
+        if self.strobe || strobe {
+            self.latch();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let bit = self.peek(port);
+        if !self.strobe {
+            self.shift[port] = self.shift[port] >> 1 | 1 << 23;
+        }
+        bit
+    }
+
+    /// With the strobe high the first bit keeps reloading, and that one is
+    /// always empty.
+    fn peek(&self, port: usize) -> u8 {
+        if self.strobe {
+            0
+        } else {
+            ((self.shift[port] & 0x01) as u8) << 1
+        }
+    }
+
+    fn set_buttons(&mut self, player: usize, buttons: u8) {
+        if let Some(held) = player.checked_sub(2).and_then(|i| self.buttons.get_mut(i)) {
+            *held = buttons;
+        }
+    }
+
+    fn movie_device(&self) -> Option<MovieDevice> {
+        Some(MovieDevice::FourScore)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::ExpansionDevice;
+use crate::state;
+
+/// The keys, row by row: each of the 9 rows has two columns of 4, read on
+/// D1-D4. Keys are passed to `set_keys` in this order.
+#[rustfmt::skip]
+pub const KEYBOARD_KEYS: [&str; 72] = [
+    "F8", "Return", "[", "]", "Kana", "Right Shift", "Yen", "Stop",
+    "F7", "@", ":", ";", "_", "/", "-", "^",
+    "F6", "O", "L", "K", ".", ",", "P", "0",
+    "F5", "I", "U", "J", "M", "N", "9", "8",
+    "F4", "Y", "G", "H", "B", "V", "7", "6",
+    "F3", "T", "R", "D", "F", "C", "5", "4",
+    "F2", "W", "S", "A", "X", "Z", "E", "3",
+    "F1", "Esc", "Q", "Ctrl", "Left Shift", "Grph", "1", "2",
+    "Clr Home", "Up", "Right", "Left", "Down", "Space", "Del", "Ins",
+];
+
+const ROWS: u8 = 9;
+
+/// The Family BASIC keyboard, scanned through $4016 writes: OUT0 goes back
+/// to the first row, OUT1 picks a column and moves on a row as it falls,
+/// and OUT2 turns the keyboard on. Held keys in the current row and column
+/// read low on D1-D4 of $4017.
+#[derive(Default, Serialize, Deserialize)]
+pub struct FamilyKeyboard {
+    keys: Vec<bool>,
+    row: u8,
+    column: u8,
+    enabled: bool,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        FamilyKeyboard {
+            keys: vec![false; KEYBOARD_KEYS.len()],
+            ..Default::default()
+        }
+    }
+}
+
+impl ExpansionDevice for FamilyKeyboard {
+    fn write(&mut self, data: u8) {
+        let column = data >> 1 & 0x01;
+        if self.column == 1 && column == 0 {
+            self.row = (self.row + 1) % (ROWS + 1);
+        }
+        self.column = column;
+        if data & 0x01 != 0 {
+            self.row = 0;
+        }
+        self.enabled = data & 0x04 != 0;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        self.peek(port)
+    }
+
+    /// Past the last row everything reads as let go.
+    fn peek(&self, port: usize) -> u8 {
+        if port == 0 || !self.enabled {
+            return 0;
+        }
+        if self.row >= ROWS {
+            return 0x1E;
+        }
+        let first = self.row as usize * 8 + self.column as usize * 4;
+        let pressed = self.keys[first..first + 4]
+            .iter()
+            .enumerate()
+            .filter(|(_, held)| **held)
+            .fold(0, |bits, (bit, _)| bits | 0x02 << bit);
+        !pressed & 0x1E
+    }
+
+    fn set_keys(&mut self, keys: &[bool]) {
+        for (held, key) in self.keys.iter_mut().zip(keys) {
+            *held = *key;
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
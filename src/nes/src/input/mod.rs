@@ -0,0 +1,235 @@
+use crate::input::arkanoid::{Arkanoid, FamicomArkanoid};
+use crate::input::controller::StandardController;
+use crate::input::four_score::{FourScore, HoriAdapter};
+use crate::input::keyboard::FamilyKeyboard;
+use crate::input::power_pad::{FamilyTrainer, PowerPad};
+use crate::input::zapper::Zapper;
+use crate::Nes;
+
+pub mod arkanoid;
+pub mod controller;
+pub mod four_score;
+pub mod keyboard;
+pub mod power_pad;
+pub mod zapper;
+
+/// Something plugged into a controller port. Both ports see every write to
+/// $4016, and each is read back through its own address, $4016 or $4017.
+pub trait InputDevice: Send {
+    /// Takes a write to $4016. Bit 0 is the strobe; bits 1 and 2 are the
+    /// expansion port's other outputs.
+    fn write(&mut self, data: u8);
+
+    /// Reads the port, which moves most devices on to their next bit. Only
+    /// bits 0-4 reach the CPU.
+    fn read(&mut self) -> u8;
+
+    /// What `read` would return, without moving anything on.
+    fn peek(&self) -> u8;
+
+    /// Sets the buttons held down, as `Button` bits, on devices that have
+    /// them. `index` picks the controller on devices with more than one.
+    fn set_buttons(&mut self, _index: usize, _buttons: u8) {}
+
+    /// Moves the aim and presses or releases the trigger, on devices
+    /// driven by a mouse.
+    fn set_pointer(&mut self, _pointer: Pointer) {}
+
+    /// Sets which keys are held, numbered the device's own way, on devices
+    /// with more of them than a controller has buttons.
+    fn set_keys(&mut self, _keys: &[bool]) {}
+
+    /// What an input movie records this as, `None` for devices whose input
+    /// it can't hold.
+    fn movie_device(&self) -> Option<MovieDevice> {
+        None
+    }
+
+    /// Where the device is up to, for a save state. Devices with nothing
+    /// of their own to keep, like the Zapper, give nothing.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Picks up from `save_state`. A state from another kind of device is
+    /// ignored, leaving whatever is plugged in now as it was.
+    fn load_state(&mut self, _state: &[u8]) {}
+}
+
+/// The devices input movies hold the input of: buttons, nothing else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieDevice {
+    Controller,
+    /// Players 3 and 4, on one side of a Four Score or on Hori's adapter.
+    FourScore,
+}
+
+/// Something in the Famicom's expansion port. It sees every $4016 write
+/// and drives its own bits of both $4016 and $4017, alongside whatever is
+/// in the controller ports.
+pub trait ExpansionDevice: Send {
+    fn write(&mut self, data: u8);
+
+    /// Reads its bits of $4016 (port 0) or $4017 (port 1).
+    fn read(&mut self, port: usize) -> u8;
+
+    fn peek(&self, port: usize) -> u8;
+
+    fn set_pointer(&mut self, _pointer: Pointer) {}
+
+    fn set_keys(&mut self, _keys: &[bool]) {}
+
+    /// Sets the buttons `player` holds, counting from 0, on devices that
+    /// add controllers.
+    fn set_buttons(&mut self, _player: usize, _buttons: u8) {}
+
+    /// What an input movie records this as, `None` for devices whose input
+    /// it can't hold.
+    fn movie_device(&self) -> Option<MovieDevice> {
+        None
+    }
+
+    /// As `InputDevice::save_state`.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, _state: &[u8]) {}
+}
+
+/// Where the mouse is over the picture, in NES pixels, and whether its
+/// button is down. The position is `None` when it is off the picture.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pointer {
+    pub position: Option<(f32, f32)>,
+    pub pressed: bool,
+}
+
+/// The buttons of a standard controller, in the order it reports them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    pub fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Button::A => "A",
+            Button::B => "B",
+            Button::Select => "Select",
+            Button::Start => "Start",
+            Button::Up => "Up",
+            Button::Down => "Down",
+            Button::Left => "Left",
+            Button::Right => "Right",
+        }
+    }
+}
+
+/// A standard controller in each port, as the console comes.
+pub fn default_ports() -> [Box<dyn InputDevice>; 2] {
+    [
+        Box::<StandardController>::default(),
+        Box::<StandardController>::default(),
+    ]
+}
+
+/// What a game was made to be played with, going by its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefaultInput {
+    Controllers,
+    FourScore,
+    /// The Famicom's 4-player adapter from Hori, in the expansion port.
+    Hori,
+    Zapper,
+    PowerPad,
+    FamilyTrainer,
+    Arkanoid,
+    FamicomArkanoid,
+    FamilyKeyboard,
+}
+
+impl DefaultInput {
+    /// From the default expansion device in byte 15 of an NES 2.0 header.
+    /// Devices that can't be emulated yet give `None`.
+    pub fn from_nes2(device: u8) -> Option<Self> {
+        match device {
+            0x01 => Some(DefaultInput::Controllers),
+            0x02 => Some(DefaultInput::FourScore),
+            0x03 => Some(DefaultInput::Hori),
+            0x08 => Some(DefaultInput::Zapper),
+            0x0B | 0x0C => Some(DefaultInput::PowerPad),
+            0x0D | 0x0E => Some(DefaultInput::FamilyTrainer),
+            0x0F => Some(DefaultInput::Arkanoid),
+            0x10 | 0x11 => Some(DefaultInput::FamicomArkanoid),
+            0x23 => Some(DefaultInput::FamilyKeyboard),
+            _ => None,
+        }
+    }
+
+    /// From the bits of a UNIF `CTRL` chunk, taking the most unusual
+    /// device it lists.
+    pub fn from_unif(controllers: u8) -> Option<Self> {
+        [
+            (0x20, DefaultInput::FourScore),
+            (0x02, DefaultInput::Zapper),
+            (0x08, DefaultInput::Arkanoid),
+            (0x10, DefaultInput::PowerPad),
+            (0x01, DefaultInput::Controllers),
+        ]
+        .into_iter()
+        .find(|(bit, _)| controllers & bit != 0)
+        .map(|(_, input)| input)
+    }
+}
+
+impl Nes {
+    /// Plugs in what `input` needs, with standard controllers wherever it
+    /// leaves a port free.
+    pub fn plug_input(&mut self, input: DefaultInput) {
+        let mut ports = default_ports();
+        let mut expansion: Option<Box<dyn ExpansionDevice>> = None;
+        match input {
+            DefaultInput::Controllers => {}
+            DefaultInput::FourScore => {
+                ports = FourScore::pair().map(|side| Box::new(side) as _);
+            }
+            DefaultInput::Hori => expansion = Some(Box::<HoriAdapter>::default()),
+            DefaultInput::Zapper => {
+                ports[1] = Box::new(Zapper::new(self.ppu.clone(), self.zapper_radius));
+            }
+            DefaultInput::PowerPad => ports[1] = Box::<PowerPad>::default(),
+            DefaultInput::Arkanoid => ports[1] = Box::<Arkanoid>::default(),
+            DefaultInput::FamilyTrainer => expansion = Some(Box::<FamilyTrainer>::default()),
+            DefaultInput::FamicomArkanoid => {
+                expansion = Some(Box::<FamicomArkanoid>::default());
+            }
+            DefaultInput::FamilyKeyboard => expansion = Some(Box::new(FamilyKeyboard::new())),
+        }
+        let bus = self.bus.read().unwrap();
+        *bus.ports.lock().unwrap() = ports;
+        *bus.expansion.lock().unwrap() = expansion;
+    }
+}
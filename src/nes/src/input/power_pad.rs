@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::{ExpansionDevice, InputDevice};
+use crate::state;
+
+/// The mat's 12 buttons, numbered 1-12 left to right and top to bottom on
+/// side B. Keys are passed in that order.
+pub const PAD_BUTTONS: usize = 12;
+
+/// Buttons, counting from 0, in the order they come out on D3 and D4.
+const D3_ORDER: [usize; 8] = [1, 0, 4, 8, 5, 9, 10, 6];
+const D4_ORDER: [usize; 4] = [3, 2, 11, 7];
+
+fn held(keys: &[bool; PAD_BUTTONS], order: &[usize]) -> u8 {
+    order
+        .iter()
+        .enumerate()
+        .filter(|(_, button)| keys[**button])
+        .fold(0, |bits, (bit, _)| bits | 1 << bit)
+}
+
+/// The NES Power Pad, in port 1. Like a controller it is strobed and then
+/// shifted out, but on two lines at once: 8 buttons on D3 and 4 on D4.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PowerPad {
+    keys: [bool; PAD_BUTTONS],
+    strobe: bool,
+    d3: u8,
+    d4: u8,
+}
+
+impl PowerPad {
+    fn latch(&mut self) {
+        self.d3 = held(&self.keys, &D3_ORDER);
+        // D4 only has 4 buttons, then reads 1s like D3 does after 8
+        self.d4 = held(&self.keys, &D4_ORDER) | 0xF0;
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, data: u8) {
+        let strobe = data & 0x01 != 0;
+        if self.strobe || strobe {
+            self.latch();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self) -> u8 {
+        let data = self.peek();
+        if self.strobe {
+            self.latch();
+        } else {
+            self.d3 = self.d3 >> 1 | 0x80;
+            self.d4 = self.d4 >> 1 | 0x80;
+        }
+        data
+    }
+
+    fn peek(&self) -> u8 {
+        (self.d4 & 0x01) << 4 | (self.d3 & 0x01) << 3
+    }
+
+    fn set_keys(&mut self, keys: &[bool]) {
+        for (held, key) in self.keys.iter_mut().zip(keys) {
+            *held = *key;
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
+
+/// The Famicom Family Trainer mat, in the expansion port. Its buttons are
+/// wired as a matrix: each of OUT0-2 written low selects a row of four,
+/// and any held button in a selected row pulls its bit of D1-D4 of $4017
+/// low.
+#[derive(Default, Serialize, Deserialize)]
+pub struct FamilyTrainer {
+    keys: [bool; PAD_BUTTONS],
+    rows: u8,
+}
+
+impl ExpansionDevice for FamilyTrainer {
+    fn write(&mut self, data: u8) {
+        self.rows = !data & 0x07;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        self.peek(port)
+    }
+
+    fn peek(&self, port: usize) -> u8 {
+        if port == 0 {
+            return 0;
+        }
+        let mut pressed = 0;
+        for row in (0..3).filter(|row| self.rows & 1 << row != 0) {
+            for column in 0..4 {
+                if self.keys[row * 4 + column] {
+                    pressed |= 0x02 << column;
+                }
+            }
+        }
+        !pressed & 0x1E
+    }
+
+    fn set_keys(&mut self, keys: &[bool]) {
+        for (held, key) in self.keys.iter_mut().zip(keys) {
+            *held = *key;
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        if let Ok(loaded) = state::decode(state) {
+            *self = loaded;
+        }
+    }
+}
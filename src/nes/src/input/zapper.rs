@@ -0,0 +1,87 @@
+use std::sync::{Arc, RwLock};
+
+use nesemu_ppu::palette::Palette;
+use nesemu_ppu::ppu::{HEIGHT, PPU, WIDTH};
+
+use crate::cartridge::Cartridge;
+use crate::input::{InputDevice, Pointer};
+
+/// Scanlines the photodiode keeps seeing a pixel for after the beam has
+/// drawn it.
+const LIGHT_LINES: usize = 20;
+/// A radius that copes with the mouse being a little off.
+pub const DEFAULT_RADIUS: usize = 3;
+/// How bright a pixel has to be to register, from 0 to 1.
+const LIGHT_LEVEL: f32 = 0.6;
+
+/// The light gun. It reads no serial data: bit 3 is low while the sensor
+/// sees light and bit 4 is high while the trigger is pulled.
+pub struct Zapper {
+    ppu: Arc<RwLock<PPU<Cartridge>>>,
+    palette: Palette,
+    /// Pixels around the aim the sensor picks up, as a circle's radius.
+    pub radius: usize,
+    pointer: Pointer,
+}
+
+impl Zapper {
+    pub fn new(ppu: Arc<RwLock<PPU<Cartridge>>>, radius: usize) -> Self {
+        Zapper {
+            ppu,
+            palette: Palette::default(),
+            radius,
+            pointer: Pointer::default(),
+        }
+    }
+
+    /// Whether anything bright near the aim has been drawn in the last few
+    /// scanlines of this frame.
+    fn sees_light(&self) -> bool {
+        let Some((x, y)) = self.pointer.position else {
+            return false;
+        };
+        if x < 0.0 || y < 0.0 || x >= WIDTH as f32 || y >= HEIGHT as f32 {
+            return false;
+        }
+        let (x, y) = (x as usize, y as usize);
+        let ppu = self.ppu.read().unwrap();
+        let (scanline, dot) = (ppu.scanline as usize, ppu.dot as usize);
+        let radius = self.radius;
+        let rows = y.saturating_sub(radius)..=(y + radius).min(HEIGHT - 1);
+        let columns = x.saturating_sub(radius)..=(x + radius).min(WIDTH - 1);
+        rows.filter(|row| *row <= scanline && scanline - row <= LIGHT_LINES)
+            .any(|row| {
+                columns.clone().any(|column| {
+                    let (dx, dy) = (column.abs_diff(x), row.abs_diff(y));
+                    // the beam has only got so far along the current line
+                    let drawn = row < scanline || column + 1 < dot;
+                    drawn
+                        && dx * dx + dy * dy <= radius * radius
+                        && self.brightness(ppu.frame[row * WIDTH + column]) >= LIGHT_LEVEL
+                })
+            })
+    }
+
+    fn brightness(&self, pixel: u16) -> f32 {
+        let [r, g, b] = self.palette.rgb(pixel);
+        (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _data: u8) {}
+
+    fn read(&mut self) -> u8 {
+        self.peek()
+    }
+
+    fn peek(&self) -> u8 {
+        let trigger = if self.pointer.pressed { 0x10 } else { 0x00 };
+        let light = if self.sees_light() { 0x00 } else { 0x08 };
+        trigger | light
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        self.pointer = pointer;
+    }
+}
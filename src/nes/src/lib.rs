@@ -1,18 +1,420 @@
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
+use nesemu_core::{Read, Region};
+use nesemu_cpu::addressing_mode::AddressingMode;
 use nesemu_cpu::cpu::{CpuDebugInfo, FlagData, CPU};
+use nesemu_cpu::op_code::Opcode;
+use nesemu_ppu::ppu::{PpuStatus, PPU};
 
+use crate::apu::Apu;
+use crate::audio::{ChannelState, Mixer, DEFAULT_SAMPLE_RATE};
 use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::dma::Dma;
+use crate::events::EventKind;
+use crate::input::zapper::DEFAULT_RADIUS;
+use crate::input::{DefaultInput, ExpansionDevice, InputDevice, Pointer};
 use crate::memory::CpuMemory;
+use crate::movie::MovieSession;
+use crate::nsf::NsfPlayer;
+use crate::rom_db::RomDatabase;
+use crate::rom_loader::RomError;
 
+pub mod apu;
+pub mod archive;
+pub mod audio;
 pub mod bus;
+pub mod cartridge;
+pub mod dma;
+pub mod events;
+pub mod fds;
+pub mod input;
+pub mod mapper;
 pub mod memory;
-mod rom_loader;
+pub mod movie;
+pub mod nsf;
+pub mod patch;
+pub mod rom_db;
+pub mod rom_loader;
+pub mod screenshot;
+pub mod state;
+pub mod unif;
 
 pub struct Nes {
     pub cpu: CPU<Bus<CpuMemory>>,
     pub ram: Arc<RwLock<CpuMemory>>,
+    pub ppu: Arc<RwLock<PPU<Cartridge>>>,
+    pub apu: Arc<RwLock<Apu>>,
     pub bus: Arc<RwLock<Bus<CpuMemory>>>,
+    pub dma: Dma,
+    pub mixer: Mixer,
+    /// CPU cycles since power on.
+    pub cycles: u64,
+    pub nsf: Option<NsfPlayer>,
+    /// The Famicom Disk System BIOS, needed before loading .fds images.
+    pub fds_bios: Option<Vec<u8>>,
+    /// Regions for iNES 1.0 roms, whose headers can't be trusted to say.
+    pub rom_database: Option<RomDatabase>,
+    /// Where the current rom was loaded from, if it came from a file.
+    pub rom_path: Option<PathBuf>,
+    /// The image the current rom was loaded from, after any patch, so it
+    /// can be loaded afresh on a power cycle.
+    rom_image: Option<Vec<u8>>,
+    /// The input devices the current rom's header asks for. Loading the
+    /// rom leaves the ports alone; `plug_input` plugs these in.
+    pub default_input: Option<DefaultInput>,
+    /// How far around its aim a Zapper from `plug_input` sees.
+    pub zapper_radius: usize,
+    /// Forced from the settings. `None` goes with what the rom asks for.
+    region_setting: Option<Region>,
+    /// What the loaded rom asked for, if it said.
+    detected_region: Option<Region>,
+    /// PAL runs 3.2 dots per CPU cycle, so the fractions are carried over.
+    dot_remainder: u32,
+    /// The IRQ line last cycle, so only new IRQs get logged.
+    irq_line: bool,
+    /// The cartridge's expansion audio levels this cycle.
+    expansion_audio: Vec<f32>,
+    /// The input movie being recorded or played back.
+    pub movie: Option<MovieSession>,
+    /// What each player has held, from `set_buttons`.
+    buttons: [u8; 4],
+    /// Commands waiting for the next frame, as `movie::COMMAND_*` bits.
+    commands: u8,
+    /// The PPU frame input was last taken for, `None` straight after power
+    /// on.
+    input_frame: Option<u64>,
+    /// The disk side a `COMMAND_FDS_INSERT` puts in.
+    fds_next_side: usize,
+    /// The side `fds_change_side` is working towards.
+    fds_wanted_side: Option<usize>,
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Nes {
+    pub fn new() -> Self {
+        let ram = Arc::new(RwLock::new(CpuMemory::default()));
+        let ppu = Arc::new(RwLock::new(PPU::new()));
+        let apu = Arc::new(RwLock::new(Apu::new()));
+        let bus = Arc::new(RwLock::new(Bus::new(ram.clone(), ppu.clone(), apu.clone())));
+        let cpu = CPU::new(bus.clone());
+        Nes {
+            cpu,
+            ram,
+            ppu,
+            apu,
+            bus,
+            dma: Dma::default(),
+            mixer: Mixer::new(Region::default().cpu_clock(), DEFAULT_SAMPLE_RATE),
+            cycles: 0,
+            nsf: None,
+            fds_bios: None,
+            rom_database: None,
+            rom_path: None,
+            rom_image: None,
+            default_input: None,
+            zapper_radius: DEFAULT_RADIUS,
+            region_setting: None,
+            detected_region: None,
+            dot_remainder: 0,
+            irq_line: false,
+            expansion_audio: Vec::new(),
+            movie: None,
+            buttons: [0; 4],
+            commands: 0,
+            input_frame: None,
+            fds_next_side: 0,
+            fds_wanted_side: None,
+        }
+    }
+
+    /// Runs one CPU cycle, and the PPU dots that go with it.
+    pub fn clock(&mut self) {
+        let frame = self.ppu.read().unwrap().frame_count;
+        if self.input_frame != Some(frame) {
+            self.input_frame = Some(frame);
+            self.start_input_frame();
+        }
+        self.clock_ppu();
+        let irq = self.clock_cartridge() | self.clock_apu();
+        if irq && !self.irq_line {
+            self.bus.read().unwrap().log_event(EventKind::Irq, 0, 0);
+        }
+        self.irq_line = irq;
+
+        let halted = {
+            let mut bus = self.bus.write().unwrap();
+            if let Some(page) = bus.oam_dma.take() {
+                self.dma.start_oam(page);
+            }
+            if self.cpu.cycles == 0 {
+                bus.pc = self.cpu.pgrm_ctr;
+            }
+            self.dma.clock(&mut bus, self.cycles, self.cpu.cycles == 0)
+        };
+        if self.dma.dmc_halted() && self.region() == Region::Ntsc {
+            self.repeat_port_read();
+        }
+        if !halted {
+            // interrupts are only taken between instructions
+            if self.cpu.cycles == 0 {
+                if self.ppu.write().unwrap().take_nmi() {
+                    self.cpu.nmi();
+                } else if irq {
+                    self.cpu.irq();
+                }
+            }
+            self.cpu.clock();
+        }
+        if let Some(player) = &mut self.nsf {
+            player.clock(&mut self.cpu);
+        }
+        self.cycles += 1;
+    }
+
+    /// The 2A03 repeats the read it was halted on while the DMC fetches,
+    /// and a controller read twice loses a bit. The PAL 2A07 fixed this.
+    /// Instructions run all at once here, so any read of a port in flight
+    /// counts.
+    fn repeat_port_read(&mut self) {
+        if self.cpu.cycles == 0 || !matches!(self.cpu.addr_abs, 0x4016 | 0x4017) {
+            return;
+        }
+        let instruction = self.cpu.lookup(self.cpu.opcode);
+        let absolute = matches!(
+            instruction.addressing_mode,
+            AddressingMode::ABS
+                | AddressingMode::ABX
+                | AddressingMode::ABY
+                | AddressingMode::IZX
+                | AddressingMode::IZY
+        );
+        let reads = matches!(
+            instruction.opcode,
+            Opcode::LDA
+                | Opcode::LDX
+                | Opcode::LDY
+                | Opcode::ADC
+                | Opcode::SBC
+                | Opcode::AND
+                | Opcode::ORA
+                | Opcode::EOR
+                | Opcode::CMP
+                | Opcode::CPX
+                | Opcode::CPY
+                | Opcode::BIT
+        );
+        if absolute && reads {
+            self.bus.read().unwrap().read(self.cpu.addr_abs, false);
+        }
+    }
+
+    /// Plugs a device into port 0 or 1, in place of whatever was there.
+    pub fn plug(&mut self, port: usize, device: Box<dyn InputDevice>) {
+        self.bus.read().unwrap().ports.lock().unwrap()[port] = device;
+    }
+
+    /// Sets the buttons held by player 0-3, as `Button` bits. Players 0
+    /// and 1 are on ports 0 and 1; players 2 and 3 need a 4-player adapter,
+    /// which puts them on the same ports again. While a movie plays, its
+    /// buttons are pressed instead.
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.buttons[player] = buttons;
+        if !self.movie_playing() {
+            self.press_buttons(player, buttons);
+        }
+    }
+
+    fn press_buttons(&self, player: usize, buttons: u8) {
+        let bus = self.bus.read().unwrap();
+        bus.ports.lock().unwrap()[player % 2].set_buttons(player / 2, buttons);
+        if let Some(device) = bus.expansion.lock().unwrap().as_mut() {
+            device.set_buttons(player, buttons);
+        };
+    }
+
+    /// Aims the device in port 0 or 1, for mice and light guns.
+    pub fn set_pointer(&self, port: usize, pointer: Pointer) {
+        self.bus.read().unwrap().ports.lock().unwrap()[port].set_pointer(pointer);
+    }
+
+    /// Sets the keys held on the device in port 0 or 1, in its numbering.
+    pub fn set_keys(&self, port: usize, keys: &[bool]) {
+        self.bus.read().unwrap().ports.lock().unwrap()[port].set_keys(keys);
+    }
+
+    /// Plugs a device into the expansion port, or with `None` empties it.
+    pub fn plug_expansion(&mut self, device: Option<Box<dyn ExpansionDevice>>) {
+        *self.bus.read().unwrap().expansion.lock().unwrap() = device;
+    }
+
+    pub fn set_expansion_pointer(&self, pointer: Pointer) {
+        if let Some(device) = self.bus.read().unwrap().expansion.lock().unwrap().as_mut() {
+            device.set_pointer(pointer);
+        }
+    }
+
+    pub fn set_expansion_keys(&self, keys: &[bool]) {
+        if let Some(device) = self.bus.read().unwrap().expansion.lock().unwrap().as_mut() {
+            device.set_keys(keys);
+        }
+    }
+
+    /// Presses the reset button: the CPU starts again from the reset
+    /// vector, the PPU's registers clear and the APU goes quiet. NSFs
+    /// start their song over.
+    pub fn reset(&mut self) {
+        if let Some(player) = &self.nsf {
+            let track = player.track;
+            self.select_nsf_track(track);
+            return;
+        }
+        self.cpu.reset();
+        self.ppu.write().unwrap().reset();
+        self.apu.write().unwrap().write(0x4015, 0);
+        self.dma = Dma::default();
+        let bus = self.bus.read().unwrap();
+        let low = bus.read(0xFFFC, true) as u16;
+        let high = bus.read(0xFFFD, true) as u16;
+        self.cpu.pgrm_ctr = (high << 8) | low;
+    }
+
+    /// Turns the console off and on again. The rom is loaded afresh from
+    /// the image it came from, so the board and its RAM start over too.
+    /// Settings and what is plugged into the ports are kept.
+    pub fn power_cycle(&mut self) -> Result<(), RomError> {
+        let Some(image) = self.rom_image.clone() else {
+            return Ok(());
+        };
+        *self.ram.write().unwrap() = CpuMemory::default();
+        {
+            let mut ppu = self.ppu.write().unwrap();
+            let unlimited_sprites = ppu.unlimited_sprites;
+            *ppu = PPU::new();
+            ppu.unlimited_sprites = unlimited_sprites;
+        }
+        *self.apu.write().unwrap() = Apu::new();
+        self.dma = Dma::default();
+        self.cycles = 0;
+        self.dot_remainder = 0;
+        self.irq_line = false;
+        self.input_frame = None;
+        self.fds_next_side = 0;
+        self.fds_wanted_side = None;
+
+        let (rom_path, detected_region) = (self.rom_path.clone(), self.detected_region);
+        self.load_rom_bytes(&image)?;
+        self.rom_path = rom_path;
+        self.set_detected_region(detected_region);
+        if self.nsf.is_none() && self.fds_side_count().is_none() {
+            self.cpu.reset();
+        }
+        Ok(())
+    }
+
+    /// The region the console is running as.
+    pub fn region(&self) -> Region {
+        self.region_setting
+            .or(self.detected_region)
+            .unwrap_or_default()
+    }
+
+    pub fn region_setting(&self) -> Option<Region> {
+        self.region_setting
+    }
+
+    /// Forces a region, or with `None` goes back to picking it from the rom.
+    pub fn set_region(&mut self, region: Option<Region>) {
+        self.region_setting = region;
+        self.update_region();
+    }
+
+    pub(crate) fn set_detected_region(&mut self, region: Option<Region>) {
+        self.detected_region = region;
+        self.update_region();
+    }
+
+    fn update_region(&mut self) {
+        let region = self.region();
+        self.ppu.write().unwrap().region = region;
+        self.apu.write().unwrap().region = region;
+        self.mixer.set_clock_rate(region.cpu_clock());
+    }
+
+    /// Runs the PPU dots for one CPU cycle, logging NMIs, sprite-0 hits and
+    /// the ends of frames as they happen.
+    fn clock_ppu(&mut self) {
+        let bus = self.bus.read().unwrap();
+        let logging = bus.events.lock().unwrap().enabled;
+        let (dots, cycles) = self.region().dots_per_cpu_cycle();
+        self.dot_remainder += dots;
+        while self.dot_remainder >= cycles {
+            self.dot_remainder -= cycles;
+            let mut ppu = self.ppu.write().unwrap();
+            if !logging {
+                ppu.clock();
+                continue;
+            }
+            let hit = PpuStatus::SpriteZeroHit.bit();
+            let (scanline, dot, frame) = (ppu.scanline, ppu.dot, ppu.frame_count);
+            let (had_hit, had_nmi) = (ppu.status & hit != 0, ppu.nmi_pending());
+            ppu.clock();
+            let got_hit = !had_hit && ppu.status & hit != 0;
+            let got_nmi = !had_nmi && ppu.nmi_pending();
+            let frame_done = ppu.frame_count != frame;
+            drop(ppu);
+            if got_hit {
+                bus.log_event_at(EventKind::SpriteZeroHit, scanline, dot, 0, 0);
+            }
+            if got_nmi {
+                bus.log_event_at(EventKind::Nmi, scanline, dot, 0, 0);
+            }
+            if frame_done {
+                bus.events.lock().unwrap().end_frame();
+            }
+        }
+    }
+
+    /// Clocks the APU, passing DMC sample fetches to and from the DMA unit
+    /// and its output on to the mixer, and returns whether it wants an IRQ.
+    fn clock_apu(&mut self) -> bool {
+        let mut apu = self.apu.write().unwrap();
+        // only the DMC's own fetches are handed back to it
+        if apu.dmc.fetching() {
+            if let Some(sample) = self.dma.take_dmc_sample() {
+                apu.load_dmc_sample(sample);
+            }
+        }
+        apu.clock();
+        if let Some(addr) = apu.take_dmc_request() {
+            self.dma.request_dmc(addr);
+        }
+        self.mixer.fade = self.nsf.as_ref().map_or(1.0, NsfPlayer::volume);
+        self.mixer.clock(apu.levels(), &self.expansion_audio);
+        apu.irq()
+    }
+
+    /// Clocks the board, collecting its expansion audio, and returns
+    /// whether it wants an IRQ.
+    fn clock_cartridge(&mut self) -> bool {
+        let bus = self.bus.read().unwrap();
+        self.expansion_audio.clear();
+        match &bus.cartridge {
+            Some(cartridge) => {
+                let mut cartridge = cartridge.write().unwrap();
+                cartridge.clock();
+                cartridge.audio(&mut self.expansion_audio);
+                cartridge.irq()
+            }
+            None => false,
+        }
+    }
 }
 
 impl Nes {
@@ -25,7 +427,35 @@ impl Nes {
     }
 
     pub fn get_ppu_registers(&self) -> [u8; 8] {
-        *self.ram.read().unwrap().ppu_registers()
+        let ppu = self.ppu.read().unwrap();
+        let mut registers = [0; 8];
+        for (k, v) in registers.iter_mut().enumerate() {
+            *v = ppu.peek_register(0x2000 + k as u16);
+        }
+        registers
+    }
+
+    /// Runs until the PPU finishes the frame it is on.
+    pub fn run_frame(&mut self) {
+        let frame = self.ppu.read().unwrap().frame_count;
+        while self.ppu.read().unwrap().frame_count == frame {
+            self.clock();
+        }
+    }
+
+    /// What every sound channel is playing: the APU's five, then any the
+    /// cartridge adds. Numbered the same as the mixer's volumes and scopes.
+    pub fn audio_channels(&self) -> Vec<ChannelState> {
+        let mut channels = self.apu.read().unwrap().channel_states().to_vec();
+        if let Some(cartridge) = &self.bus.read().unwrap().cartridge {
+            let clock = self.region().cpu_clock();
+            channels.extend(cartridge.read().unwrap().audio_channels(clock));
+        }
+        channels
+    }
+
+    pub fn get_frame(&self) -> Vec<u16> {
+        self.ppu.read().unwrap().frame.clone()
     }
 
     pub fn get_ppu_mirrors(&self) -> [u8; 8184] {
@@ -41,7 +471,17 @@ impl Nes {
     }
 
     pub fn get_cartridge_space(&self) -> [u8; 49120] {
-        *self.ram.read().unwrap().cartridge_space()
+        let bus = self.bus.read().unwrap();
+        match &bus.cartridge {
+            Some(_) => {
+                let mut space = [0; 49120];
+                for (k, v) in space.iter_mut().enumerate() {
+                    *v = bus.read((k + 0x4020) as u16, true);
+                }
+                space
+            }
+            None => *self.ram.read().unwrap().cartridge_space(),
+        }
     }
 }
 
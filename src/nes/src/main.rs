@@ -1,6 +1,127 @@
-mod bus;
-mod memory;
+use std::path::Path;
+use std::process::exit;
 
+use nesemu::audio::wav::WavFormat;
+use nesemu::movie::Movie;
+use nesemu::rom_db::{DatabaseError, RomDatabase};
+use nesemu::Nes;
+use nesemu_core::Region;
+use nesemu_ppu::ntsc::NtscFilter;
+use nesemu_ppu::palette::Palette;
+
+const USAGE: &str =
+    "usage: headless_nes <rom> <frames> <screenshot.png> [--ntsc] [--palette <file.pal>] [--region ntsc|pal|dendy] [--wav <out.wav> [--wav-float] [--wav-channels]] [--movie <file.fm2>]";
+
+/// Runs a rom for a number of frames without a window and saves what ends
+/// up on screen.
 fn main() {
-    println!("no main yet!")
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 3 {
+        usage();
+    }
+    let frames: u32 = args[1].parse().unwrap_or_else(|_| usage());
+
+    let mut palette = Palette::default();
+    let mut ntsc = None;
+    let mut region = None;
+    let mut wav = None;
+    let mut wav_format = WavFormat::Pcm16;
+    let mut wav_channels = false;
+    let mut movie = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--ntsc" => ntsc = Some(NtscFilter::default()),
+            "--palette" => {
+                let path = options.next().unwrap_or_else(|| usage());
+                let bytes = std::fs::read(path).unwrap_or_else(|e| {
+                    eprintln!("can't read {}: {}", path, e);
+                    exit(1)
+                });
+                palette = Palette::from_pal(&bytes).unwrap_or_else(|e| {
+                    eprintln!("can't use {}: {:?}", path, e);
+                    exit(1)
+                });
+            }
+            "--region" => {
+                region = match options.next().map(String::as_str) {
+                    Some("ntsc") => Some(Region::Ntsc),
+                    Some("pal") => Some(Region::Pal),
+                    Some("dendy") => Some(Region::Dendy),
+                    _ => usage(),
+                }
+            }
+            "--wav" => wav = Some(options.next().unwrap_or_else(|| usage())),
+            "--wav-float" => wav_format = WavFormat::Float32,
+            "--wav-channels" => wav_channels = true,
+            "--movie" => {
+                let path = options.next().unwrap_or_else(|| usage());
+                movie = Some(Movie::load(Path::new(path)).unwrap_or_else(|e| {
+                    eprintln!("can't read movie {}: {:?}", path, e);
+                    exit(1)
+                }));
+            }
+            _ => usage(),
+        }
+    }
+
+    let mut nes = Nes::new();
+    nes.set_region(region);
+    let bios_path = std::env::var("NESEMU_FDS_BIOS").unwrap_or_else(|_| "disksys.rom".to_string());
+    nes.fds_bios = std::fs::read(bios_path).ok();
+    let db_path = std::env::var("NESEMU_ROM_DB").unwrap_or_else(|_| "nesdb.txt".to_string());
+    nes.rom_database = match RomDatabase::load(Path::new(&db_path)) {
+        Ok(db) => Some(db),
+        Err(DatabaseError::Io(_)) => None,
+        Err(e) => {
+            eprintln!("can't read {}: {:?}", db_path, e);
+            None
+        }
+    };
+    if let Err(e) = nes.load_rom(&args[0]) {
+        eprintln!("can't load {}: {:?}", args[0], e);
+        exit(1);
+    }
+    if let Some(player) = &nes.nsf {
+        let unsupported = player.nsf.expansion.unsupported_names();
+        if !unsupported.is_empty() {
+            eprintln!("not emulated, so silent: {}", unsupported.join(", "));
+        }
+    }
+    if let Some(input) = nes.default_input {
+        nes.plug_input(input);
+    }
+    if nes.nsf.is_none() && nes.fds_side_count().is_none() {
+        nes.cpu.reset();
+    }
+    if let Some(movie) = movie {
+        if let Err(e) = nes.play_movie(movie) {
+            eprintln!("can't play the movie: {:?}", e);
+            exit(1);
+        }
+    }
+    if let Some(path) = wav {
+        if let Err(e) = nes.start_recording(Path::new(path), wav_format, wav_channels) {
+            eprintln!("can't record to {}: {}", path, e);
+            exit(1);
+        }
+    }
+    for _ in 0..frames {
+        nes.run_frame();
+    }
+    if let Err(e) = nes.mixer.stop_recording() {
+        eprintln!("can't finish recording: {}", e);
+        exit(1);
+    }
+
+    let screenshot = nes.screenshot(&palette, ntsc.as_ref());
+    if let Err(e) = screenshot.write_png(Path::new(&args[2])) {
+        eprintln!("can't write {}: {}", args[2], e);
+        exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(2)
 }
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::mapper::{Board, Mapper};
+use crate::state::{self, StateError};
+
+/// Mapper 7. 32k PRG banks, and single-screen mirroring picked by the same
+/// register.
+#[derive(Serialize, Deserialize)]
+pub struct AxRom {
+    board: Board,
+    bank: usize,
+    upper_screen: bool,
+}
+
+impl AxRom {
+    pub fn new(board: Board) -> Self {
+        AxRom {
+            board,
+            bank: 0,
+            upper_screen: false,
+        }
+    }
+}
+
+impl Mapper for AxRom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.board.read_prg(0x8000, self.bank, addr),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            self.bank = data as usize & 0x07;
+            self.upper_screen = data & 0x10 != 0;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.read_chr(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.board.write_chr(0x2000, 0, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.upper_screen {
+            Mirroring::SingleScreenUpper
+        } else {
+            Mirroring::SingleScreenLower
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: AxRom = state::decode(state)?;
+        loaded.board.take_rom(&mut self.board);
+        *self = loaded;
+        Ok(())
+    }
+}
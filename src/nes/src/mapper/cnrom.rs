@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::mapper::{Board, Mapper};
+use crate::state::{self, StateError};
+
+/// Mapper 3. Fixed PRG like NROM, with a switchable 8k CHR bank.
+#[derive(Serialize, Deserialize)]
+pub struct CnRom {
+    board: Board,
+    chr_bank: usize,
+}
+
+impl CnRom {
+    pub fn new(board: Board) -> Self {
+        CnRom { board, chr_bank: 0 }
+    }
+}
+
+impl Mapper for CnRom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.board.read_prg_ram(addr),
+            0x8000..=0xFFFF => self.board.read_prg(0x8000, 0, addr),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.board.write_prg_ram(addr, data),
+            0x8000..=0xFFFF => self.chr_bank = data as usize & 0x03,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.read_chr(0x2000, self.chr_bank, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.board.write_chr(0x2000, self.chr_bank, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.board.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: CnRom = state::decode(state)?;
+        loaded.board.take_rom(&mut self.board);
+        *self = loaded;
+        Ok(())
+    }
+}
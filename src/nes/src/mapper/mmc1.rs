@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::mapper::{Board, Mapper};
+use crate::state::{self, StateError};
+
+/// Mapper 1 (SxROM boards). Registers are loaded one bit at a time through
+/// a 5-bit shift register; the fifth write picks the register by address.
+///
+/// SUROM and SXROM carry 512k of PRG, more than the 16 banks the PRG
+/// register reaches; bit 4 of the first CHR register picks which 256k half
+/// both it and the fixed bank come from.
+#[derive(Serialize, Deserialize)]
+pub struct Mmc1 {
+    board: Board,
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: usize,
+    chr_bank_1: usize,
+    prg_bank: usize,
+}
+
+impl Mmc1 {
+    pub fn new(board: Board) -> Self {
+        Mmc1 {
+            board,
+            shift: 0,
+            shift_count: 0,
+            // power on in "fix last bank at $C000" mode
+            control: 0x0C,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    /// The first 16k bank of the 256k half in use, on 512k boards.
+    fn prg_outer_bank(&self) -> usize {
+        if self.board.prg_banks(0x4000) > 16 {
+            self.chr_bank_0 & 0x10
+        } else {
+            0
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    fn load_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value as usize,
+            0xC000..=0xDFFF => self.chr_bank_1 = value as usize,
+            _ => self.prg_bank = value as usize,
+        }
+    }
+
+    /// Maps a CHR address to (4k bank, address), whatever the CHR mode.
+    fn chr_bank(&self, addr: u16) -> usize {
+        if self.control & 0x10 == 0 {
+            // one 8k bank, low bit ignored
+            (self.chr_bank_0 & !1) + (addr as usize >> 12)
+        } else if addr < 0x1000 {
+            self.chr_bank_0
+        } else {
+            self.chr_bank_1
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        let outer = self.prg_outer_bank();
+        let bank = outer | (self.prg_bank & 0x0F);
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.board.read_prg_ram(addr),
+            0x8000..=0xFFFF => match (self.control >> 2) & 0x03 {
+                0 | 1 => self.board.read_prg(0x8000, bank >> 1, addr),
+                2 if addr < 0xC000 => self.board.read_prg(0x4000, outer, addr),
+                2 => self.board.read_prg(0x4000, bank, addr),
+                _ if addr < 0xC000 => self.board.read_prg(0x4000, bank, addr),
+                _ => {
+                    let last = (outer | 0x0F).min(self.board.prg_banks(0x4000) - 1);
+                    self.board.read_prg(0x4000, last, addr)
+                }
+            },
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.board.write_prg_ram(addr, data),
+            0x8000..=0xFFFF => {
+                if data & 0x80 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+                self.shift |= (data & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.load_register(addr, self.shift);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.read_chr(0x1000, self.chr_bank(addr), addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_bank(addr);
+        self.board.write_chr(0x1000, bank, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: Mmc1 = state::decode(state)?;
+        loaded.board.take_rom(&mut self.board);
+        *self = loaded;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::any::Any;
+
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::audio::ChannelState;
+use crate::mapper::axrom::AxRom;
+use crate::mapper::cnrom::CnRom;
+use crate::mapper::mmc1::Mmc1;
+use crate::mapper::nrom::NRom;
+pub use crate::mapper::nsf::NsfMapper;
+use crate::mapper::uxrom::UxRom;
+use crate::rom_loader::{Rom, RomError};
+use crate::state::StateError;
+
+mod axrom;
+mod cnrom;
+mod mmc1;
+mod nrom;
+mod nsf;
+mod uxrom;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// Everything on the cartridge side of the two buses. CPU addresses are
+/// $4020-$FFFF, PPU addresses are the pattern tables at $0000-$1FFF.
+pub trait Mapper: Send + Sync {
+    /// Side-effect free read, safe for debuggers to call.
+    fn cpu_read(&self, addr: u16) -> u8;
+    /// The read the CPU actually does. Boards with registers that change
+    /// when read (IRQ acknowledges and the like) override this.
+    fn cpu_read_mut(&mut self, addr: u16) -> u8 {
+        self.cpu_read(addr)
+    }
+    fn cpu_write(&mut self, addr: u16, data: u8);
+    fn ppu_read(&self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, data: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    /// Called once per CPU cycle, for boards with timers or audio.
+    fn clock(&mut self) {}
+
+    /// Whether the board is holding the IRQ line low.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Adds the board's expansion audio channels to `levels`, each 0.0 to
+    /// 1.0. Boards without any add nothing.
+    fn audio(&self, _levels: &mut Vec<f32>) {}
+
+    /// What each of the expansion channels is playing, in the order
+    /// `audio` gives them. `clock` is the CPU clock in Hz.
+    fn audio_channels(&self, _clock: f64) -> Vec<ChannelState> {
+        Vec::new()
+    }
+
+    /// The board's registers and ram for a save state, without the rom.
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Takes back what `save_state` gave. The board is left as it was if
+    /// the state doesn't decode.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError>;
+
+    /// For getting at board specific controls (disk sides and so on).
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
+}
+
+pub fn for_rom(rom: &Rom) -> Result<Box<dyn Mapper>, RomError> {
+    let board = Board::new(rom);
+    match rom.mapper {
+        0 => Ok(Box::new(NRom::new(board))),
+        1 => Ok(Box::new(Mmc1::new(board))),
+        2 => Ok(Box::new(UxRom::new(board))),
+        3 => Ok(Box::new(CnRom::new(board))),
+        7 => Ok(Box::new(AxRom::new(board))),
+        n => Err(RomError::UnsupportedMapper(n)),
+    }
+}
+
+/// The memory every board has in some form: PRG rom, CHR rom (or ram when
+/// the rom has none), and 8k of PRG ram at $6000-$7FFF. Save states leave
+/// the roms out, see `take_rom`.
+#[derive(Serialize, Deserialize)]
+pub struct Board {
+    #[serde(skip)]
+    pub prg_rom: Vec<u8>,
+    #[serde(skip)]
+    pub chr_rom: Vec<u8>,
+    /// Only boards without CHR rom have any.
+    pub chr_ram: Vec<u8>,
+    pub prg_ram: Vec<u8>,
+    pub mirroring: Mirroring,
+}
+
+impl Board {
+    pub fn new(rom: &Rom) -> Self {
+        let mut prg_ram = vec![0; PRG_RAM_SIZE];
+        if let Some(trainer) = &rom.trainer {
+            // trainers always load at $7000
+            prg_ram[0x1000..0x1000 + trainer.len()].copy_from_slice(trainer);
+        }
+        Board {
+            prg_rom: rom.prg_rom.clone(),
+            chr_rom: rom.chr_rom.clone(),
+            chr_ram: if rom.chr_rom.is_empty() {
+                vec![0; CHR_RAM_SIZE]
+            } else {
+                Vec::new()
+            },
+            prg_ram,
+            mirroring: rom.mirroring,
+        }
+    }
+
+    /// Moves the rom over from the board a save state is loaded over.
+    pub fn take_rom(&mut self, old: &mut Board) {
+        self.prg_rom = std::mem::take(&mut old.prg_rom);
+        self.chr_rom = std::mem::take(&mut old.chr_rom);
+    }
+
+    pub fn prg_banks(&self, bank_size: usize) -> usize {
+        (self.prg_rom.len() / bank_size).max(1)
+    }
+
+    pub fn read_prg(&self, bank_size: usize, bank: usize, addr: u16) -> u8 {
+        banked(&self.prg_rom, bank_size, bank, addr)
+    }
+
+    pub fn read_chr(&self, bank_size: usize, bank: usize, addr: u16) -> u8 {
+        if self.chr_rom.is_empty() {
+            banked(&self.chr_ram, bank_size, bank, addr)
+        } else {
+            banked(&self.chr_rom, bank_size, bank, addr)
+        }
+    }
+
+    pub fn write_chr(&mut self, bank_size: usize, bank: usize, addr: u16, data: u8) {
+        if !self.chr_ram.is_empty() {
+            let index = bank * bank_size + (addr as usize % bank_size);
+            let len = self.chr_ram.len();
+            self.chr_ram[index % len] = data;
+        }
+    }
+
+    pub fn read_prg_ram(&self, addr: u16) -> u8 {
+        self.prg_ram[addr as usize & (PRG_RAM_SIZE - 1)]
+    }
+
+    pub fn write_prg_ram(&mut self, addr: u16, data: u8) {
+        self.prg_ram[addr as usize & (PRG_RAM_SIZE - 1)] = data;
+    }
+}
+
+/// Reads from a bank-switched window. Bank numbers wrap around the data,
+/// which is what the hardware does when the unused high bits are ignored.
+fn banked(data: &[u8], bank_size: usize, bank: usize, addr: u16) -> u8 {
+    if data.is_empty() {
+        return 0;
+    }
+    data[(bank * bank_size + (addr as usize % bank_size)) % data.len()]
+}
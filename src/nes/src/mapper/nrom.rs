@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::mapper::{Board, Mapper};
+use crate::state::{self, StateError};
+
+/// Mapper 0. No bank switching at all; a 16k rom is mirrored into both
+/// halves of $8000-$FFFF.
+#[derive(Serialize, Deserialize)]
+pub struct NRom {
+    board: Board,
+}
+
+impl NRom {
+    pub fn new(board: Board) -> Self {
+        NRom { board }
+    }
+}
+
+impl Mapper for NRom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.board.read_prg_ram(addr),
+            0x8000..=0xFFFF => self.board.read_prg(0x8000, 0, addr),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.board.write_prg_ram(addr, data)
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.read_chr(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.board.write_chr(0x2000, 0, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.board.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: NRom = state::decode(state)?;
+        loaded.board.take_rom(&mut self.board);
+        *self = loaded;
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::audio::ChannelState;
+use crate::fds::audio::FdsAudio;
+use crate::mapper::Mapper;
+use crate::nsf::{Nsf, DRIVER_ADDR};
+use crate::state::{self, StateError};
+
+const PAGE_SIZE: usize = 0x1000;
+/// $6000-$FFFF in 4k pages. Only FDS rips can bank the first two.
+const PAGES: usize = 10;
+
+/// The cartridge side of an NSF: its data in 4k pages (bank switched
+/// through $5FF6-$5FFF when the rip asks for it), 8k of work ram, and the
+/// driver stub. FDS rips get ram over $6000-$DFFF instead, and the disk
+/// system's sound channel at $4040-$408A.
+#[derive(Serialize, Deserialize)]
+pub struct NsfMapper {
+    /// Left out of save states along with the driver, both come from the
+    /// file.
+    #[serde(skip)]
+    prg: Vec<u8>,
+    pages: [usize; PAGES],
+    ram: Vec<u8>,
+    fds: bool,
+    fds_audio: Option<FdsAudio>,
+    #[serde(skip)]
+    driver: [u8; 12],
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Self {
+        let fds = nsf.expansion.fds();
+        let mut pages = [0; PAGES];
+        let prg = if nsf.is_bankswitched() {
+            // banks are counted from the 4k boundary below the load address
+            let mut prg = vec![0; nsf.load_addr as usize & 0x0FFF];
+            prg.extend_from_slice(&nsf.data);
+            pages[0] = nsf.bank_init[6] as usize;
+            pages[1] = nsf.bank_init[7] as usize;
+            for (page, bank) in pages[2..].iter_mut().zip(nsf.bank_init.iter()) {
+                *page = *bank as usize;
+            }
+            prg
+        } else {
+            let mut prg = vec![0; PAGES * PAGE_SIZE];
+            let start = (nsf.load_addr as usize).saturating_sub(0x6000);
+            let len = nsf.data.len().min(prg.len() - start);
+            prg[start..start + len].copy_from_slice(&nsf.data[..len]);
+            for (i, page) in pages.iter_mut().enumerate() {
+                *page = i;
+            }
+            prg
+        };
+
+        let mut mapper = NsfMapper {
+            prg,
+            pages,
+            ram: vec![0; if fds { 8 * PAGE_SIZE } else { 2 * PAGE_SIZE }],
+            fds,
+            fds_audio: fds.then(FdsAudio::new),
+            driver: nsf.driver(),
+        };
+        if fds {
+            for page in 0..8 {
+                mapper.load_ram_page(page);
+            }
+        }
+        mapper
+    }
+
+    fn read_page(&self, page: usize, addr: u16) -> u8 {
+        let index = self.pages[page] * PAGE_SIZE + (addr as usize & 0x0FFF);
+        self.prg.get(index).copied().unwrap_or(0)
+    }
+
+    /// FDS rips run from ram, so switching a bank copies it in.
+    fn load_ram_page(&mut self, page: usize) {
+        for offset in 0..PAGE_SIZE {
+            self.ram[page * PAGE_SIZE + offset] = self.read_page(page, offset as u16);
+        }
+    }
+
+    fn ram_window(&self) -> u16 {
+        if self.fds {
+            0xDFFF
+        } else {
+            0x7FFF
+        }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        let driver_end = DRIVER_ADDR + self.driver.len() as u16;
+        match addr {
+            _ if (DRIVER_ADDR..driver_end).contains(&addr) => {
+                self.driver[(addr - DRIVER_ADDR) as usize]
+            }
+            0x4040..=0x4092 => self.fds_audio.as_ref().map_or(0, |audio| audio.read(addr)),
+            0x6000..=0xFFFF if addr <= self.ram_window() => self.ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => self.read_page((addr as usize - 0x6000) / PAGE_SIZE, addr),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x408A => {
+                if let Some(audio) = &mut self.fds_audio {
+                    audio.write(addr, data);
+                }
+            }
+            0x5FF6..=0x5FFF => {
+                let page = (addr - 0x5FF6) as usize;
+                if page < 2 && !self.fds {
+                    return;
+                }
+                self.pages[page] = data as usize;
+                if self.fds && page < 8 {
+                    self.load_ram_page(page);
+                }
+            }
+            0x6000..=0xFFFF if addr <= self.ram_window() => self.ram[addr as usize - 0x6000] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn clock(&mut self) {
+        if let Some(audio) = &mut self.fds_audio {
+            audio.clock();
+        }
+    }
+
+    fn audio(&self, levels: &mut Vec<f32>) {
+        if let Some(audio) = &self.fds_audio {
+            levels.push(audio.output());
+        }
+    }
+
+    fn audio_channels(&self, clock: f64) -> Vec<ChannelState> {
+        self.fds_audio
+            .iter()
+            .map(|audio| audio.state(clock))
+            .collect()
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: NsfMapper = state::decode(state)?;
+        loaded.prg = std::mem::take(&mut self.prg);
+        loaded.driver = self.driver;
+        *self = loaded;
+        Ok(())
+    }
+}
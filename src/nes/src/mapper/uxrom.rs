@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::Mirroring;
+
+use crate::mapper::{Board, Mapper};
+use crate::state::{self, StateError};
+
+/// Mapper 2. A switchable 16k bank at $8000 with the last bank fixed at
+/// $C000. CHR is always ram.
+#[derive(Serialize, Deserialize)]
+pub struct UxRom {
+    board: Board,
+    bank: usize,
+}
+
+impl UxRom {
+    pub fn new(board: Board) -> Self {
+        UxRom { board, bank: 0 }
+    }
+}
+
+impl Mapper for UxRom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.board.read_prg_ram(addr),
+            0x8000..=0xBFFF => self.board.read_prg(0x4000, self.bank, addr),
+            0xC000..=0xFFFF => {
+                let last = self.board.prg_banks(0x4000) - 1;
+                self.board.read_prg(0x4000, last, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.board.write_prg_ram(addr, data),
+            0x8000..=0xFFFF => self.bank = data as usize & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.read_chr(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.board.write_chr(0x2000, 0, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.board.mirroring
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::encode(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut loaded: UxRom = state::decode(state)?;
+        loaded.board.take_rom(&mut self.board);
+        *self = loaded;
+        Ok(())
+    }
+}
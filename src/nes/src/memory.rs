@@ -4,19 +4,19 @@ use nesemu_core::{Read, Write};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CpuMemory {
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     main_ram: [u8; 0x0800],
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     main_ram_mirror: [u8; 0x1800],
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     ppu_registers: [u8; 0x0008],
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     ppu_mirrors: [u8; 0x1FF8],
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     apu_io_registers: [u8; 0x0018],
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     apu_io_expansion: [u8; 0x0008],
-    #[serde(with = "array_serde")]
+    #[serde(with = "nesemu_core::array_serde")]
     pub cartridge_space: [u8; 0xBFE0],
 }
 
@@ -34,35 +34,6 @@ impl Default for CpuMemory {
     }
 }
 
-mod array_serde {
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
-
-    pub fn serialize<S>(array: &[u8], serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        array.serialize(serializer)
-    }
-
-    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let vec: Vec<u8> = Vec::deserialize(deserializer)?;
-        if vec.len() == N {
-            let mut array = [0u8; N];
-            array.copy_from_slice(&vec);
-            Ok(array)
-        } else {
-            Err(serde::de::Error::custom(format!(
-                "Expected array of length {}, found {}",
-                N,
-                vec.len()
-            )))
-        }
-    }
-}
-
 impl Read for CpuMemory {
     fn read(&self, address: u16, _read_only: bool) -> u8 {
         match address {
@@ -72,7 +43,7 @@ impl Read for CpuMemory {
             0x2008..=0x3FFF => self.ppu_mirrors[address as usize & 0x1FF7],
             0x4000..=0x4017 => self.apu_io_registers[address as usize & 0x0016],
             0x4018..=0x401F => self.apu_io_expansion[address as usize & 0x0006],
-            0x4020..=0xFFFF => self.cartridge_space[address as usize & 0xBFDE],
+            0x4020..=0xFFFF => self.cartridge_space[address as usize - 0x4020],
             _ => 0x0000,
         }
     }
@@ -87,7 +58,7 @@ impl Write for CpuMemory {
             0x2008..=0x3FFF => self.ppu_mirrors[address as usize & 0x1FF7] = data,
             0x4000..=0x4017 => self.apu_io_registers[address as usize & 0x0016] = data,
             0x4018..=0x401F => self.apu_io_expansion[address as usize & 0x0006] = data,
-            0x4020..=0xFFFF => self.cartridge_space[address as usize & 0xBFDE] = data,
+            0x4020..=0xFFFF => self.cartridge_space[address as usize - 0x4020] = data,
             _ => {}
         }
     }
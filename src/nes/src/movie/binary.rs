@@ -0,0 +1,131 @@
+//! The native movie format. After the magic and a version byte, all
+//! little endian:
+//!
+//! - flags (u8): 1 Four Score, 2 PAL, 4 FDS, 8 has a save state
+//! - rerecord count (u32), frame count (u32)
+//! - rom file name, rom checksum and guid, each a u16 length and UTF-8
+//! - comment count (u16), then each comment the same way
+//! - the save state, a u32 length and its bytes, if the flag says
+//! - 5 bytes a frame: the commands, then players 1-4's buttons
+
+use crate::movie::{Movie, MovieError, MovieFrame};
+
+pub const MAGIC: &[u8] = b"NESMOV\x1A";
+const VERSION: u8 = 1;
+
+const FOUR_SCORE: u8 = 0x01;
+const PAL: u8 = 0x02;
+const FDS: u8 = 0x04;
+const SAVESTATE: u8 = 0x08;
+
+const FRAME_SIZE: usize = 5;
+
+pub fn parse(bytes: &[u8]) -> Result<Movie, MovieError> {
+    let mut reader = Reader {
+        bytes,
+        offset: MAGIC.len(),
+    };
+    if !bytes.starts_with(MAGIC) || reader.take(1)?[0] != VERSION {
+        return Err(MovieError::UnknownFormat);
+    }
+    let flags = reader.take(1)?[0];
+    let rerecord_count = reader.u32()?;
+    let frame_count = reader.u32()? as usize;
+    let mut movie = Movie {
+        rerecord_count,
+        four_score: flags & FOUR_SCORE != 0,
+        pal: flags & PAL != 0,
+        fds: flags & FDS != 0,
+        rom_filename: reader.string()?,
+        rom_checksum: reader.string()?,
+        guid: reader.string()?,
+        ..Default::default()
+    };
+    for _ in 0..reader.u16()? {
+        movie.comments.push(reader.string()?);
+    }
+    if flags & SAVESTATE != 0 {
+        let len = reader.u32()? as usize;
+        movie.savestate = Some(reader.take(len)?.to_vec());
+    }
+    let frames = reader.take(frame_count.saturating_mul(FRAME_SIZE))?;
+    movie.frames = frames
+        .chunks(FRAME_SIZE)
+        .map(|frame| MovieFrame {
+            commands: frame[0],
+            buttons: [frame[1], frame[2], frame[3], frame[4]],
+        })
+        .collect();
+    Ok(movie)
+}
+
+pub fn write(movie: &Movie) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    let mut flags = 0;
+    for (set, flag) in [
+        (movie.uses_four_score(), FOUR_SCORE),
+        (movie.pal, PAL),
+        (movie.fds, FDS),
+        (movie.savestate.is_some(), SAVESTATE),
+    ] {
+        if set {
+            flags |= flag;
+        }
+    }
+    bytes.push(flags);
+    bytes.extend(movie.rerecord_count.to_le_bytes());
+    bytes.extend((movie.frames.len() as u32).to_le_bytes());
+    for text in [&movie.rom_filename, &movie.rom_checksum, &movie.guid] {
+        write_string(&mut bytes, text);
+    }
+    bytes.extend((movie.comments.len() as u16).to_le_bytes());
+    for comment in &movie.comments {
+        write_string(&mut bytes, comment);
+    }
+    if let Some(state) = &movie.savestate {
+        bytes.extend((state.len() as u32).to_le_bytes());
+        bytes.extend(state);
+    }
+    for frame in &movie.frames {
+        bytes.push(frame.commands);
+        bytes.extend(frame.buttons);
+    }
+    bytes
+}
+
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+    let text = &text.as_bytes()[..text.len().min(u16::MAX as usize)];
+    bytes.extend((text.len() as u16).to_le_bytes());
+    bytes.extend(text);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MovieError> {
+        let end = self.offset.checked_add(len).ok_or(MovieError::Truncated)?;
+        let slice = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or(MovieError::Truncated)?;
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, MovieError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, MovieError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, MovieError> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
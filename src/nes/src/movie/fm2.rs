@@ -0,0 +1,187 @@
+//! FCEUX's FM2 movies: `key value` header lines, then one line per frame
+//! like `|0|RLDUTSBA|........||`, the commands then each gamepad, with a
+//! letter for every button held.
+
+use crate::movie::{Movie, MovieError, MovieFrame};
+
+/// Button letters as they are written, Right down to A.
+const BUTTONS: &[u8; 8] = b"RLDUTSBA";
+/// What FCEUX calls an empty port, and a standard controller, in the
+/// `port0`/`port1` lines.
+const NONE: &str = "0";
+const GAMEPAD: &str = "1";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn parse(bytes: &[u8]) -> Result<Movie, MovieError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| MovieError::UnknownFormat)?;
+    let mut movie = Movie::default();
+    let mut version = None;
+    let mut gamepads = [true; 2];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let bad = || MovieError::BadLine(number + 1);
+        if line.starts_with('|') {
+            let plugged = match movie.four_score {
+                true => [true; 4],
+                false => [gamepads[0], gamepads[1], false, false],
+            };
+            movie
+                .frames
+                .push(parse_frame(line, plugged).ok_or_else(bad)?);
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let flag = || match value {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(bad()),
+        };
+        match key {
+            "version" => version = Some(value),
+            "rerecordCount" => movie.rerecord_count = value.parse().map_err(|_| bad())?,
+            "palFlag" => movie.pal = flag()?,
+            "FDS" => movie.fds = flag()?,
+            "fourscore" => movie.four_score = flag()?,
+            "romFilename" => movie.rom_filename = value.to_string(),
+            "romChecksum" => movie.rom_checksum = value.to_string(),
+            "guid" => movie.guid = value.to_string(),
+            "comment" => movie.comments.push(value.to_string()),
+            "savestate" => movie.savestate = Some(parse_blob(value).ok_or_else(bad)?),
+            "port0" | "port1" => {
+                if value != NONE && value != GAMEPAD {
+                    return Err(MovieError::Unsupported(format!("{} device {}", key, value)));
+                }
+                gamepads[(key == "port1") as usize] = value == GAMEPAD;
+            }
+            "port2" if value != NONE => {
+                return Err(MovieError::Unsupported(format!("port2 device {}", value)));
+            }
+            "binary" if value != "0" => {
+                return Err(MovieError::Unsupported("binary input".to_string()));
+            }
+            _ => {}
+        }
+    }
+    match version {
+        Some("3") => Ok(movie),
+        _ => Err(MovieError::UnknownFormat),
+    }
+}
+
+/// Reads `|commands|pad|pad|...`, where a pad's buttons are held unless
+/// they are `.` or a space. Empty ports may leave an empty field or none.
+fn parse_frame(line: &str, plugged: [bool; 4]) -> Option<MovieFrame> {
+    let mut fields = line.split('|').skip(1).peekable();
+    let mut frame = MovieFrame {
+        commands: fields.next()?.trim().parse().ok()?,
+        ..Default::default()
+    };
+    for (buttons, plugged) in frame.buttons.iter_mut().zip(plugged) {
+        if !plugged {
+            fields.next_if(|field| field.is_empty());
+            continue;
+        }
+        let field = fields.next()?.as_bytes();
+        if field.len() != BUTTONS.len() {
+            return None;
+        }
+        for (bit, c) in field.iter().rev().enumerate() {
+            if *c != b'.' && *c != b' ' {
+                *buttons |= 1 << bit;
+            }
+        }
+    }
+    Some(frame)
+}
+
+/// Movies only hold controllers, see `Nes::record_movie`, so both ports
+/// are written as gamepads.
+pub fn write(movie: &Movie) -> String {
+    let four_score = movie.uses_four_score();
+    let flag = |set: bool| if set { "1" } else { "0" };
+    let mut text = String::from("version 3\n");
+    text += &format!("rerecordCount {}\n", movie.rerecord_count);
+    text += &format!("palFlag {}\n", flag(movie.pal));
+    text += &format!("FDS {}\n", flag(movie.fds));
+    text += &format!("fourscore {}\n", flag(four_score));
+    text += &format!("port0 {}\nport1 {}\nport2 {}\n", GAMEPAD, GAMEPAD, NONE);
+    text += &format!("romFilename {}\n", movie.rom_filename);
+    if !movie.rom_checksum.is_empty() {
+        text += &format!("romChecksum {}\n", movie.rom_checksum);
+    }
+    if !movie.guid.is_empty() {
+        text += &format!("guid {}\n", movie.guid);
+    }
+    for comment in &movie.comments {
+        text += &format!("comment {}\n", comment);
+    }
+    if let Some(state) = &movie.savestate {
+        text += &format!("savestate base64:{}\n", encode_base64(state));
+    }
+    text += &format!("length {}\n", movie.frames.len());
+
+    let gamepads = if four_score { 4 } else { 2 };
+    for frame in &movie.frames {
+        text += &format!("|{}", frame.commands);
+        for buttons in &frame.buttons[..gamepads] {
+            text.push('|');
+            for (n, letter) in BUTTONS.iter().enumerate() {
+                let held = buttons & (0x80 >> n) != 0;
+                text.push(if held { *letter as char } else { '.' });
+            }
+        }
+        text += "||\n";
+    }
+    text
+}
+
+/// FCEUX writes binary values as `base64:...` or as `0x` and hex digits.
+fn parse_blob(value: &str) -> Option<Vec<u8>> {
+    if let Some(base64) = value.strip_prefix("base64:") {
+        return decode_base64(base64);
+    }
+    let hex = value.strip_prefix("0x")?;
+    if hex.len() & 1 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for n in 0..4 {
+            if n <= chunk.len() {
+                text.push(BASE64[((bits >> (18 - 6 * n)) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE64.iter().position(|b| *b == c)? as u32;
+        bits = (bits << 6) | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
@@ -0,0 +1,375 @@
+//! Input movies: the buttons held on every frame, recorded from power on
+//! or from a save state, for playing back exactly as they happened.
+//!
+//! - FM2: FCEUX's text format, one line of buttons per frame, with the
+//!   reset, power and disk commands it shares with us.
+//! - Native: the same in a small binary file, see `binary`.
+
+use std::fs;
+use std::path::Path;
+
+use nesemu_core::Region;
+
+use crate::input::{DefaultInput, MovieDevice};
+use crate::rom_loader::RomError;
+use crate::state::StateError;
+use crate::Nes;
+
+pub mod binary;
+pub mod fm2;
+
+/// Presses reset before the frame.
+pub const COMMAND_RESET: u8 = 0x01;
+/// Turns the console off and on before the frame.
+pub const COMMAND_POWER: u8 = 0x02;
+/// Ejects the disk, or puts in the selected side if none is in.
+pub const COMMAND_FDS_INSERT: u8 = 0x04;
+/// Picks the next disk side to put in. Only works with the disk out.
+pub const COMMAND_FDS_SELECT: u8 = 0x08;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(std::io::Error),
+    /// Neither a movie format we know, nor FM2 version 3.
+    UnknownFormat,
+    Truncated,
+    /// A header value or input line that doesn't parse, with its line.
+    BadLine(usize),
+    /// Something a movie would need that isn't supported here, such as a
+    /// Zapper in a port.
+    Unsupported(String),
+    /// Powering on to record or play back failed.
+    Rom(RomError),
+    /// The save state the movie starts from doesn't load.
+    State(StateError),
+}
+
+impl From<std::io::Error> for MovieError {
+    fn from(e: std::io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+impl From<RomError> for MovieError {
+    fn from(e: RomError) -> Self {
+        MovieError::Rom(e)
+    }
+}
+
+impl From<StateError> for MovieError {
+    fn from(e: StateError) -> Self {
+        MovieError::State(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MovieFormat {
+    Fm2,
+    Native,
+}
+
+impl MovieFormat {
+    /// FM2 for `.fm2` files, the native format for anything else.
+    pub fn from_path(path: &Path) -> MovieFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("fm2") => MovieFormat::Fm2,
+            _ => MovieFormat::Native,
+        }
+    }
+}
+
+/// One frame of input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    /// `COMMAND_*` bits, carried out before the frame runs.
+    pub commands: u8,
+    /// Each player's buttons, as `Button` bits.
+    pub buttons: [u8; 4],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Movie {
+    /// How many times a state was loaded while recording.
+    pub rerecord_count: u32,
+    /// Players 3 and 4 are on a Four Score.
+    pub four_score: bool,
+    pub pal: bool,
+    pub fds: bool,
+    pub rom_filename: String,
+    /// As FCEUX writes it, the base64 of the rom's MD5. Kept, not checked.
+    pub rom_checksum: String,
+    pub guid: String,
+    /// Free text such as the author, one entry per line.
+    pub comments: Vec<String>,
+    /// The state recording began from, as `Nes::save_state` gives it.
+    /// `None` starts from power on.
+    pub savestate: Option<Vec<u8>>,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Reads a movie in either format, going by what the file starts with.
+    pub fn parse(bytes: &[u8]) -> Result<Movie, MovieError> {
+        if bytes.starts_with(binary::MAGIC) {
+            binary::parse(bytes)
+        } else {
+            fm2::parse(bytes)
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, MovieError> {
+        Movie::parse(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self, format: MovieFormat) -> Vec<u8> {
+        match format {
+            MovieFormat::Fm2 => fm2::write(self).into_bytes(),
+            MovieFormat::Native => binary::write(self),
+        }
+    }
+
+    /// Saves in the format the file's extension calls for.
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_bytes(MovieFormat::from_path(path)))?;
+        Ok(())
+    }
+
+    /// Whether anyone but players 1 and 2 pressed anything.
+    pub fn uses_four_score(&self) -> bool {
+        self.four_score
+            || self
+                .frames
+                .iter()
+                .any(|frame| frame.buttons[2] | frame.buttons[3] != 0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+/// A movie being recorded or played back.
+#[derive(Debug)]
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    /// The next frame to record or play.
+    pub frame: usize,
+}
+
+impl MovieSession {
+    /// Whether playback has run out of frames.
+    pub fn finished(&self) -> bool {
+        self.mode == MovieMode::Playing && self.frame >= self.movie.frames.len()
+    }
+}
+
+impl Nes {
+    /// Starts recording a new movie. With no `savestate` the console is
+    /// powered on afresh first; otherwise it goes to that state, usually
+    /// one just saved, and recording carries on from there. Only
+    /// controllers and 4-player adapters can be recorded, so anything else
+    /// plugged in is refused.
+    pub fn record_movie(&mut self, savestate: Option<Vec<u8>>) -> Result<(), MovieError> {
+        let four_score = self.movie_four_score()?;
+        self.movie = None;
+        match &savestate {
+            Some(state) => self.load_state(state)?,
+            None => self.power_cycle()?,
+        }
+        let rom_filename = self
+            .rom_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let movie = Movie {
+            four_score,
+            pal: self.region() == Region::Pal,
+            fds: self.fds_side_count().is_some(),
+            rom_filename,
+            savestate,
+            ..Default::default()
+        };
+        self.movie = Some(MovieSession {
+            movie,
+            mode: MovieMode::Recording,
+            frame: 0,
+        });
+        Ok(())
+    }
+
+    /// Whether players 3 and 4 are plugged in, on a Four Score or on Hori's
+    /// adapter, rather than just two controllers.
+    fn movie_four_score(&self) -> Result<bool, MovieError> {
+        let bus = self.bus.read().unwrap();
+        let expansion = bus.expansion.lock().unwrap();
+        let hori = match expansion.as_ref().map(|device| device.movie_device()) {
+            None => false,
+            Some(Some(MovieDevice::FourScore)) => true,
+            Some(_) => {
+                return Err(MovieError::Unsupported(
+                    "expansion port devices".to_string(),
+                ))
+            }
+        };
+        let ports = bus.ports.lock().unwrap();
+        match [ports[0].movie_device(), ports[1].movie_device()] {
+            [Some(MovieDevice::Controller), Some(MovieDevice::Controller)] => Ok(hori),
+            [Some(MovieDevice::FourScore), Some(MovieDevice::FourScore)] if !hori => Ok(true),
+            _ => Err(MovieError::Unsupported(
+                "devices other than controllers".to_string(),
+            )),
+        }
+    }
+
+    /// Plays a movie back from power on, or from the save state it starts
+    /// from, on PAL if it was recorded there and with the controllers it
+    /// was, a Four Score for players 3 and 4, or Hori's adapter for games
+    /// that ask for it.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        self.movie = None;
+        if movie.pal {
+            self.set_region(Some(Region::Pal));
+        }
+        // movies can't tell Hori's adapter from a Four Score, so Hori games
+        // get theirs back
+        self.plug_input(match (movie.uses_four_score(), self.default_input) {
+            (true, Some(DefaultInput::Hori)) => DefaultInput::Hori,
+            (true, _) => DefaultInput::FourScore,
+            (false, _) => DefaultInput::Controllers,
+        });
+        match &movie.savestate {
+            Some(state) => self.load_state(state)?,
+            None => self.power_cycle()?,
+        }
+        self.movie = Some(MovieSession {
+            movie,
+            mode: MovieMode::Playing,
+            frame: 0,
+        });
+        Ok(())
+    }
+
+    /// Stops recording or playing, handing the movie back, and gives the
+    /// ports back to `set_buttons`.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let session = self.movie.take()?;
+        for player in 0..self.buttons.len() {
+            self.press_buttons(player, self.buttons[player]);
+        }
+        Some(session.movie)
+    }
+
+    /// Tells the movie that a state saved on its `frame` was loaded, which
+    /// `load_state` does by itself. While recording, everything after that
+    /// frame goes and a rerecord is counted; while playing, playback jumps
+    /// there.
+    pub fn movie_state_loaded(&mut self, frame: usize) {
+        let Some(session) = &mut self.movie else {
+            return;
+        };
+        session.frame = frame.min(session.movie.frames.len());
+        if session.mode == MovieMode::Recording {
+            session.movie.frames.truncate(session.frame);
+            session.movie.rerecord_count += 1;
+        }
+    }
+
+    pub(crate) fn movie_playing(&self) -> bool {
+        self.movie
+            .as_ref()
+            .is_some_and(|session| session.mode == MovieMode::Playing && !session.finished())
+    }
+
+    /// Asks for `COMMAND_*` bits to be carried out as the next frame
+    /// starts, and recorded if a movie is recording.
+    pub fn queue_commands(&mut self, commands: u8) {
+        self.commands |= commands;
+    }
+
+    /// Puts disk `side` in through the `COMMAND_FDS_*` bits, so a movie
+    /// recording gets it too. That takes a few frames, a command each: the
+    /// disk comes out, the side is selected, then it goes in.
+    pub fn fds_change_side(&mut self, side: usize) {
+        self.fds_wanted_side = Some(side);
+    }
+
+    /// Queues the next command `fds_change_side` needs, if any.
+    fn queue_fds_change(&mut self) {
+        let Some(side) = self.fds_wanted_side else {
+            return;
+        };
+        if self.fds_side_count().map_or(true, |sides| side >= sides) {
+            self.fds_wanted_side = None;
+            return;
+        }
+        match self.fds_current_side() {
+            Some(current) if current == side => self.fds_wanted_side = None,
+            Some(_) => self.queue_commands(COMMAND_FDS_INSERT),
+            // selecting steps through the sides one at a time
+            None if self.fds_next_side != side => self.queue_commands(COMMAND_FDS_SELECT),
+            None => {
+                self.queue_commands(COMMAND_FDS_INSERT);
+                self.fds_wanted_side = None;
+            }
+        }
+    }
+
+    /// Takes this frame's commands and buttons, from the movie when one is
+    /// playing, and records them when one is recording.
+    pub(crate) fn start_input_frame(&mut self) {
+        if !self.movie_playing() {
+            self.queue_fds_change();
+        }
+        let mut frame = MovieFrame {
+            commands: std::mem::take(&mut self.commands),
+            buttons: self.buttons,
+        };
+        match &mut self.movie {
+            Some(session) if session.mode == MovieMode::Recording => {
+                session.movie.frames.push(frame);
+                session.frame += 1;
+            }
+            Some(session) if !session.finished() => {
+                frame = session.movie.frames[session.frame];
+                session.frame += 1;
+            }
+            _ => {}
+        }
+        self.run_commands(frame.commands);
+        for (player, buttons) in frame.buttons.into_iter().enumerate() {
+            self.press_buttons(player, buttons);
+        }
+    }
+
+    fn run_commands(&mut self, commands: u8) {
+        if commands & COMMAND_POWER != 0 {
+            let frame = self.ppu.read().unwrap().frame_count;
+            // the image loaded before, so it loads again
+            self.power_cycle().ok();
+            // the count carries on, and this frame's input is already taken
+            self.ppu.write().unwrap().frame_count = frame;
+            self.input_frame = Some(frame);
+            return;
+        }
+        if commands & COMMAND_RESET != 0 {
+            self.reset();
+        }
+        if let Some(sides) = self.fds_side_count() {
+            let inserted = self.fds_current_side().is_some();
+            if commands & COMMAND_FDS_SELECT != 0 && !inserted {
+                self.fds_next_side = (self.fds_next_side + 1) % sides;
+            }
+            if commands & COMMAND_FDS_INSERT != 0 {
+                if inserted {
+                    self.fds_eject();
+                } else {
+                    self.fds_insert_side(self.fds_next_side);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! NSF and NSFe music rips. These aren't games, just the sound engine of
+//! one plus its data, so we wrap them in a tiny driver that calls the
+//! rip's INIT routine once per track and its PLAY routine at the rate the
+//! header asks for.
+
+use std::time::Duration;
+
+use nesemu_core::{Read, Region, Write};
+use nesemu_cpu::cpu::CPU;
+
+use crate::bus::Bus;
+use crate::mapper::NsfMapper;
+use crate::memory::CpuMemory;
+use crate::rom_loader::RomError;
+use crate::Nes;
+
+pub const NSF_MAGIC: &[u8] = b"NESM\x1A";
+pub const NSFE_MAGIC: &[u8] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+
+/// Where the driver stub lives. Nothing in the NSF memory map uses it.
+pub const DRIVER_ADDR: u16 = 0x4100;
+const DRIVER_IDLE: u16 = DRIVER_ADDR + 3;
+const DRIVER_PLAY: u16 = DRIVER_ADDR + 6;
+
+const NTSC_CPU_HZ: u64 = 1_789_773;
+const PAL_CPU_HZ: u64 = 1_662_607;
+const NTSC_DEFAULT_SPEED: u16 = 16_639;
+const PAL_DEFAULT_SPEED: u16 = 19_997;
+
+/// Most rips don't say how long a track is; this is what players
+/// traditionally fall back on.
+pub const DEFAULT_TRACK_LENGTH: Duration = Duration::from_secs(150);
+pub const DEFAULT_FADE: Duration = Duration::from_secs(5);
+
+/// The expansion sound chips an NSF may use, straight from the header.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExpansionAudio(pub u8);
+
+impl ExpansionAudio {
+    pub fn vrc6(&self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    pub fn vrc7(&self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    pub fn fds(&self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    pub fn mmc5(&self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    pub fn namco163(&self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    pub fn sunsoft5b(&self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.chips()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name)
+            .collect::<Vec<&str>>()
+    }
+
+    /// The chips asked for that aren't emulated, which play silently. Only
+    /// the FDS channel is.
+    pub fn unsupported_names(&self) -> Vec<&'static str> {
+        self.chips()
+            .filter(|(set, name)| *set && *name != "FDS")
+            .map(|(_, name)| name)
+            .collect::<Vec<&str>>()
+    }
+
+    fn chips(&self) -> impl Iterator<Item = (bool, &'static str)> {
+        [
+            (self.vrc6(), "VRC6"),
+            (self.vrc7(), "VRC7"),
+            (self.fds(), "FDS"),
+            (self.mmc5(), "MMC5"),
+            (self.namco163(), "Namco 163"),
+            (self.sunsoft5b(), "Sunsoft 5B"),
+        ]
+        .into_iter()
+    }
+}
+
+pub struct Nsf {
+    pub version: u8,
+    pub total_songs: u8,
+    /// 0 based, unlike the header.
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ripper: Option<String>,
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    pub bank_init: [u8; 8],
+    pub pal: bool,
+    pub dual_region: bool,
+    pub expansion: ExpansionAudio,
+    pub data: Vec<u8>,
+    pub track_names: Vec<Option<String>>,
+    pub track_lengths: Vec<Option<Duration>>,
+    pub track_fades: Vec<Option<Duration>>,
+    pub playlist: Option<Vec<u8>>,
+}
+
+impl Nsf {
+    pub fn is_nsf(bytes: &[u8]) -> bool {
+        bytes.starts_with(NSF_MAGIC) || bytes.starts_with(NSFE_MAGIC)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Nsf, RomError> {
+        if bytes.starts_with(NSFE_MAGIC) {
+            let mut nsf = Nsf::empty();
+            nsf.read_nsfe_chunks(&bytes[NSFE_MAGIC.len()..], true)?;
+            return Ok(nsf);
+        }
+        if !bytes.starts_with(NSF_MAGIC) {
+            return Err(RomError::BadHeader);
+        }
+        let head = bytes.get(..NSF_HEADER_SIZE).ok_or(RomError::Truncated)?;
+        let word = |at: usize| u16::from_le_bytes([head[at], head[at + 1]]);
+
+        // NSF2 may append NSFe metadata chunks after the program data
+        let program_len = u32::from_le_bytes([head[0x7D], head[0x7E], head[0x7F], 0]) as usize;
+        let (data, metadata) = if head[0x05] >= 2 && program_len != 0 {
+            let end = (NSF_HEADER_SIZE + program_len).min(bytes.len());
+            (&bytes[NSF_HEADER_SIZE..end], Some(&bytes[end..]))
+        } else {
+            (&bytes[NSF_HEADER_SIZE..], None)
+        };
+
+        let mut nsf = Nsf {
+            version: head[0x05],
+            total_songs: head[0x06],
+            starting_song: head[0x07].saturating_sub(1),
+            load_addr: word(0x08),
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            title: header_string(&head[0x0E..0x2E]),
+            artist: header_string(&head[0x2E..0x4E]),
+            copyright: header_string(&head[0x4E..0x6E]),
+            ripper: None,
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            bank_init: head[0x70..0x78].try_into().unwrap(),
+            pal: head[0x7A] & 0x01 != 0,
+            dual_region: head[0x7A] & 0x02 != 0,
+            expansion: ExpansionAudio(head[0x7B]),
+            data: data.to_vec(),
+            track_names: Vec::new(),
+            track_lengths: Vec::new(),
+            track_fades: Vec::new(),
+            playlist: None,
+        };
+        if let Some(metadata) = metadata {
+            nsf.read_nsfe_chunks(metadata, false)?;
+        }
+        Ok(nsf)
+    }
+
+    fn empty() -> Nsf {
+        Nsf {
+            version: 0,
+            total_songs: 1,
+            starting_song: 0,
+            load_addr: 0x8000,
+            init_addr: 0x8000,
+            play_addr: 0x8000,
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            ripper: None,
+            ntsc_speed: NTSC_DEFAULT_SPEED,
+            pal_speed: PAL_DEFAULT_SPEED,
+            bank_init: [0; 8],
+            pal: false,
+            dual_region: false,
+            expansion: ExpansionAudio(0),
+            data: Vec::new(),
+            track_names: Vec::new(),
+            track_lengths: Vec::new(),
+            track_fades: Vec::new(),
+            playlist: None,
+        }
+    }
+
+    /// NSFe chunks are a length, a four character id, then the data. Ids
+    /// starting with an upper case letter must be understood to play the
+    /// file; lower case ones are optional metadata.
+    fn read_nsfe_chunks(&mut self, bytes: &[u8], require_info: bool) -> Result<(), RomError> {
+        let mut seen_info = !require_info;
+        let mut seen_data = !require_info;
+        let mut offset = 0;
+        while offset + 8 <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+            let id = &bytes[offset + 4..offset + 8];
+            let data = bytes
+                .get(offset + 8..offset + 8 + len)
+                .ok_or(RomError::Truncated)?;
+            offset += 8 + len;
+
+            match id {
+                b"INFO" => {
+                    if data.len() < 9 {
+                        return Err(RomError::Truncated);
+                    }
+                    self.load_addr = u16::from_le_bytes([data[0], data[1]]);
+                    self.init_addr = u16::from_le_bytes([data[2], data[3]]);
+                    self.play_addr = u16::from_le_bytes([data[4], data[5]]);
+                    self.pal = data[6] & 0x01 != 0;
+                    self.dual_region = data[6] & 0x02 != 0;
+                    self.expansion = ExpansionAudio(data[7]);
+                    self.total_songs = data[8];
+                    self.starting_song = data.get(9).copied().unwrap_or(0);
+                    seen_info = true;
+                }
+                b"DATA" => {
+                    self.data = data.to_vec();
+                    seen_data = true;
+                }
+                b"BANK" => {
+                    self.bank_init = [0; 8];
+                    let n = data.len().min(8);
+                    self.bank_init[..n].copy_from_slice(&data[..n]);
+                }
+                b"RATE" => {
+                    if data.len() >= 2 {
+                        self.ntsc_speed = u16::from_le_bytes([data[0], data[1]]);
+                    }
+                    if data.len() >= 4 {
+                        self.pal_speed = u16::from_le_bytes([data[2], data[3]]);
+                    }
+                }
+                b"NEND" => break,
+                b"auth" => {
+                    let mut strings = data.split(|b| *b == 0).map(header_string);
+                    // the first string is the game title, like an NSF header
+                    self.title = strings.next().unwrap_or_default();
+                    self.artist = strings.next().unwrap_or_default();
+                    self.copyright = strings.next().unwrap_or_default();
+                    self.ripper = strings.next().filter(|s| !s.is_empty());
+                }
+                b"tlbl" => {
+                    self.track_names = data
+                        .split(|b| *b == 0)
+                        .take(self.total_songs as usize)
+                        .map(|name| Some(header_string(name)).filter(|s| !s.is_empty()))
+                        .collect::<Vec<Option<String>>>();
+                }
+                b"time" => self.track_lengths = millis(data),
+                b"fade" => self.track_fades = millis(data),
+                b"plst" => self.playlist = Some(data.to_vec()),
+                _ => {
+                    if id[0].is_ascii_uppercase() {
+                        return Err(RomError::BadHeader);
+                    }
+                }
+            }
+        }
+        if !(seen_info && seen_data) {
+            return Err(RomError::BadHeader);
+        }
+        Ok(())
+    }
+
+    pub fn is_bankswitched(&self) -> bool {
+        self.bank_init.iter().any(|bank| *bank != 0)
+    }
+
+    pub fn track_name(&self, track: u8) -> Option<&str> {
+        self.track_names
+            .get(track as usize)
+            .and_then(|name| name.as_deref())
+    }
+
+    pub fn track_length(&self, track: u8) -> Duration {
+        self.track_lengths
+            .get(track as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(DEFAULT_TRACK_LENGTH)
+    }
+
+    pub fn track_fade(&self, track: u8) -> Duration {
+        self.track_fades
+            .get(track as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(DEFAULT_FADE)
+    }
+
+    fn plays_pal(&self) -> bool {
+        self.pal && !self.dual_region
+    }
+
+    /// How many CPU cycles go by between PLAY calls.
+    pub fn play_period(&self) -> u64 {
+        let (speed, default, hz) = if self.plays_pal() {
+            (self.pal_speed, PAL_DEFAULT_SPEED, PAL_CPU_HZ)
+        } else {
+            (self.ntsc_speed, NTSC_DEFAULT_SPEED, NTSC_CPU_HZ)
+        };
+        let speed = if speed == 0 { default } else { speed };
+        speed as u64 * hz / 1_000_000
+    }
+
+    fn cpu_hz(&self) -> u64 {
+        if self.plays_pal() {
+            PAL_CPU_HZ
+        } else {
+            NTSC_CPU_HZ
+        }
+    }
+
+    /// The driver stub: call INIT, spin, and jump to PLAY whenever the
+    /// player moves the program counter there.
+    pub(crate) fn driver(&self) -> [u8; 12] {
+        let [init_lo, init_hi] = self.init_addr.to_le_bytes();
+        let [play_lo, play_hi] = self.play_addr.to_le_bytes();
+        let [idle_lo, idle_hi] = DRIVER_IDLE.to_le_bytes();
+        [
+            0x20, init_lo, init_hi, // JSR init
+            0x4C, idle_lo, idle_hi, // JMP idle
+            0x20, play_lo, play_hi, // JSR play
+            0x4C, idle_lo, idle_hi, // JMP idle
+        ]
+    }
+}
+
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// NSFe stores times as signed milliseconds, negative meaning unknown.
+fn millis(data: &[u8]) -> Vec<Option<Duration>> {
+    data.chunks_exact(4)
+        .map(|c| i32::from_le_bytes(c.try_into().unwrap()))
+        .map(|ms| u64::try_from(ms).ok().map(Duration::from_millis))
+        .collect::<Vec<Option<Duration>>>()
+}
+
+/// Keeps track of the current song and when PLAY is next due.
+pub struct NsfPlayer {
+    pub nsf: Nsf,
+    pub track: u8,
+    pub(crate) cycles_until_play: u64,
+    pub(crate) elapsed_cycles: u64,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> Self {
+        NsfPlayer {
+            track: nsf.starting_song,
+            nsf,
+            cycles_until_play: 0,
+            elapsed_cycles: 0,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_micros(self.elapsed_cycles * 1_000_000 / self.nsf.cpu_hz())
+    }
+
+    /// 1.0 while the track plays, then ramping down to 0.0 over the fade.
+    pub fn volume(&self) -> f32 {
+        let length = self.nsf.track_length(self.track);
+        let fade = self.nsf.track_fade(self.track);
+        let elapsed = self.elapsed();
+        if elapsed <= length {
+            1.0
+        } else if fade.is_zero() || elapsed >= length + fade {
+            0.0
+        } else {
+            1.0 - (elapsed - length).as_secs_f32() / fade.as_secs_f32()
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed() >= self.nsf.track_length(self.track) + self.nsf.track_fade(self.track)
+    }
+
+    /// Called once per CPU cycle. PLAY is only started from the idle loop,
+    /// so a slow PLAY routine delays the next call rather than being
+    /// interrupted by it.
+    pub fn clock(&mut self, cpu: &mut CPU<Bus<CpuMemory>>) {
+        self.elapsed_cycles += 1;
+        self.cycles_until_play = self.cycles_until_play.saturating_sub(1);
+        let idle = (DRIVER_IDLE..DRIVER_PLAY).contains(&cpu.pgrm_ctr);
+        if self.cycles_until_play == 0 && idle && cpu.cycles == 0 {
+            cpu.pgrm_ctr = DRIVER_PLAY;
+            self.cycles_until_play = self.nsf.play_period();
+        }
+    }
+}
+
+impl Nes {
+    pub fn load_nsf(&mut self, bytes: &[u8]) -> Result<(), RomError> {
+        let nsf = Nsf::parse(bytes)?;
+        let track = nsf.starting_song;
+        let region = if nsf.plays_pal() {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+        self.set_detected_region(Some(region));
+        self.nsf = Some(NsfPlayer::new(nsf));
+        self.select_nsf_track(track);
+        Ok(())
+    }
+
+    /// Restarts the driver on another song, following the init sequence
+    /// from the NSF spec: clear ram, silence the APU, reload the banks,
+    /// then call INIT with the song in A and the region in X.
+    pub fn select_nsf_track(&mut self, track: u8) {
+        let Some(player) = &mut self.nsf else {
+            return;
+        };
+        let track = track.min(player.nsf.total_songs.saturating_sub(1));
+        player.track = track;
+        player.elapsed_cycles = 0;
+        player.cycles_until_play = player.nsf.play_period();
+        let pal = player.nsf.plays_pal();
+        let mapper = NsfMapper::new(&player.nsf);
+
+        self.insert_mapper(Box::new(mapper));
+        {
+            let mut bus = self.bus.write().unwrap();
+            for addr in 0x0000..0x0800 {
+                bus.write(addr, 0);
+            }
+            for addr in 0x4000..0x4014 {
+                bus.write(addr, 0);
+            }
+            bus.write(0x4015, 0x00);
+            bus.write(0x4015, 0x0F);
+            bus.write(0x4017, 0x40);
+            debug_assert_eq!(bus.read(DRIVER_ADDR, true), 0x20);
+        }
+
+        self.cpu.reset();
+        self.cpu.acc_reg = track;
+        self.cpu.x_reg = pal as u8;
+        self.cpu.pgrm_ctr = DRIVER_ADDR;
+    }
+}
@@ -0,0 +1,402 @@
+//! Soft-patching support. Patches are applied to the raw rom image before
+//! it is parsed, so anything that can be loaded can also be patched.
+//!
+//! - IPS: offset/length records, with RLE records and the truncation
+//!   extension (three extra bytes after "EOF" giving the final size).
+//! - UPS: xor hunks between a source and target of known size, with crc32s
+//!   of both plus the patch itself.
+//! - BPS: copy/read actions against the source and target, same checksums.
+
+use std::path::{Path, PathBuf};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF_OFFSET: usize = 0x454F46;
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+/// Extensions looked for next to a rom, in order, when auto-patching.
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    SourceSize,
+    SourceChecksum,
+    TargetChecksum,
+    PatchChecksum,
+    TooLarge,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+        if patch.starts_with(IPS_MAGIC) {
+            Some(PatchFormat::Ips)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Some(PatchFormat::Ups)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Some(PatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+}
+
+/// Applies a patch of any supported format, sniffing the format from its
+/// magic bytes.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    match PatchFormat::detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(rom, patch),
+        Some(PatchFormat::Ups) => apply_ups(rom, patch),
+        Some(PatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(PatchError::UnknownFormat),
+    }
+}
+
+/// Returns the first patch sitting next to `rom_path` with the same file
+/// stem, e.g. `game.ips` for `game.nes` or `game.zip`.
+pub fn find_patch_for(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| rom_path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = PatchReader::new(patch, IPS_MAGIC)?;
+    let mut out = rom.to_vec();
+
+    loop {
+        let offset = reader.be(3)?;
+        if offset == IPS_EOF_OFFSET {
+            break;
+        }
+        let size = reader.be(2)?;
+        if size == 0 {
+            let count = reader.be(2)?;
+            let value = reader.byte()?;
+            write_at(&mut out, offset, &vec![value; count]);
+        } else {
+            write_at(&mut out, offset, reader.take(size)?);
+        }
+    }
+
+    // lunar ips truncation extension
+    if reader.remaining() == 3 {
+        out.truncate(reader.be(3)?);
+    }
+    Ok(out)
+}
+
+pub fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    check_patch_crc(patch)?;
+    let mut reader = PatchReader::new(patch, UPS_MAGIC)?;
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let source_crc = read_crc(patch, 12);
+    let target_crc = read_crc(patch, 8);
+
+    // ups patches work in both directions, so a patched rom can be reverted
+    let rom_crc = crc32fast::hash(rom);
+    let (in_size, out_size, out_crc) = if rom.len() == source_size && rom_crc == source_crc {
+        (source_size, target_size, target_crc)
+    } else if rom.len() == target_size && rom_crc == target_crc {
+        (target_size, source_size, source_crc)
+    } else if rom.len() != source_size {
+        return Err(PatchError::SourceSize);
+    } else {
+        return Err(PatchError::SourceChecksum);
+    };
+
+    let mut out = rom[..in_size.min(out_size)].to_vec();
+    out.resize(out_size, 0);
+    let mut pos: usize = 0;
+    while reader.remaining() > 12 {
+        pos = pos
+            .checked_add(reader.varint()?)
+            .ok_or(PatchError::TooLarge)?;
+        loop {
+            let xor = reader.byte()?;
+            if xor == 0 {
+                break;
+            }
+            if pos < out_size {
+                out[pos] = rom.get(pos).copied().unwrap_or(0) ^ xor;
+            }
+            pos = pos.checked_add(1).ok_or(PatchError::TooLarge)?;
+        }
+        pos = pos.checked_add(1).ok_or(PatchError::TooLarge)?;
+    }
+
+    if crc32fast::hash(&out) != out_crc {
+        return Err(PatchError::TargetChecksum);
+    }
+    Ok(out)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    check_patch_crc(patch)?;
+    let mut reader = PatchReader::new(patch, BPS_MAGIC)?;
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.take(metadata_size)?;
+
+    if rom.len() != source_size {
+        return Err(PatchError::SourceSize);
+    }
+    if crc32fast::hash(rom) != read_crc(patch, 12) {
+        return Err(PatchError::SourceChecksum);
+    }
+
+    // the header's target size isn't trusted with an allocation, but
+    // nothing may write past it
+    let mut out: Vec<u8> = Vec::new();
+    let mut source_relative: usize = 0;
+    let mut target_relative: usize = 0;
+    while reader.remaining() > 12 {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        out.len()
+            .checked_add(length)
+            .filter(|end| *end <= target_size)
+            .ok_or(PatchError::TooLarge)?;
+        match data & 3 {
+            // SourceRead
+            0 => {
+                let from = out.len();
+                out.extend_from_slice(source_range(rom, from, length)?);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(reader.take(length)?),
+            // SourceCopy
+            2 => {
+                source_relative = source_relative
+                    .checked_add_signed(reader.signed_varint()?)
+                    .ok_or(PatchError::Truncated)?;
+                out.extend_from_slice(source_range(rom, source_relative, length)?);
+                // the range was there, so this can't overflow
+                source_relative += length;
+            }
+            // TargetCopy, which may overlap what it is writing
+            _ => {
+                target_relative = target_relative
+                    .checked_add_signed(reader.signed_varint()?)
+                    .ok_or(PatchError::Truncated)?;
+                for _ in 0..length {
+                    let byte = *out.get(target_relative).ok_or(PatchError::Truncated)?;
+                    out.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size || crc32fast::hash(&out) != read_crc(patch, 8) {
+        return Err(PatchError::TargetChecksum);
+    }
+    Ok(out)
+}
+
+fn source_range(rom: &[u8], from: usize, length: usize) -> Result<&[u8], PatchError> {
+    let end = from.checked_add(length).ok_or(PatchError::Truncated)?;
+    rom.get(from..end).ok_or(PatchError::Truncated)
+}
+
+/// Builds an IPS patch turning `source` into `target`. Runs of a repeated
+/// byte are written as RLE records, and a shorter target gets the
+/// truncation extension.
+pub fn create_ips(source: &[u8], target: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if target.len() > IPS_MAX_OFFSET + 1 {
+        return Err(PatchError::TooLarge);
+    }
+    let mut patch = IPS_MAGIC.to_vec();
+    let differs = |i: usize| source.get(i) != target.get(i);
+
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        // an offset that spells "EOF" would end the patch early, so back
+        // up one byte and rewrite the (unchanged) byte before it
+        let start = if i == IPS_EOF_OFFSET { i - 1 } else { i };
+        let mut end = i;
+        while end < target.len() && end - start < IPS_MAX_RECORD && differs(end) {
+            end += 1;
+        }
+
+        let run = &target[start..end];
+        patch.extend_from_slice(&be_bytes(start, 3));
+        if run.len() > 3 && run.iter().all(|b| *b == run[0]) {
+            patch.extend_from_slice(&[0, 0]);
+            patch.extend_from_slice(&be_bytes(run.len(), 2));
+            patch.push(run[0]);
+        } else {
+            patch.extend_from_slice(&be_bytes(run.len(), 2));
+            patch.extend_from_slice(run);
+        }
+        i = end;
+    }
+
+    patch.extend_from_slice(&be_bytes(IPS_EOF_OFFSET, 3));
+    if target.len() < source.len() {
+        patch.extend_from_slice(&be_bytes(target.len(), 3));
+    }
+    Ok(patch)
+}
+
+/// Builds a BPS patch turning `source` into `target`. This only uses
+/// SourceRead and TargetRead actions, which keeps it simple at the cost of
+/// larger patches when data moves around.
+pub fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = BPS_MAGIC.to_vec();
+    write_varint(&mut patch, source.len());
+    write_varint(&mut patch, target.len());
+    write_varint(&mut patch, 0);
+
+    let same = |i: usize| source.get(i) == target.get(i);
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        if same(i) {
+            while i < target.len() && same(i) {
+                i += 1;
+            }
+            write_varint(&mut patch, (i - start - 1) << 2);
+        } else {
+            while i < target.len() && !same(i) {
+                i += 1;
+            }
+            write_varint(&mut patch, ((i - start - 1) << 2) | 1);
+            patch.extend_from_slice(&target[start..i]);
+        }
+    }
+
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let patch_crc = crc32fast::hash(&patch);
+    patch.extend_from_slice(&patch_crc.to_le_bytes());
+    patch
+}
+
+fn write_at(out: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    if out.len() < offset + data.len() {
+        out.resize(offset + data.len(), 0);
+    }
+    out[offset..offset + data.len()].copy_from_slice(data);
+}
+
+fn be_bytes(value: usize, len: usize) -> Vec<u8> {
+    (0..len)
+        .rev()
+        .map(|i| (value >> (i * 8)) as u8)
+        .collect::<Vec<u8>>()
+}
+
+/// UPS and BPS both end with a crc32 of everything before it.
+fn check_patch_crc(patch: &[u8]) -> Result<(), PatchError> {
+    if patch.len() < 16 {
+        return Err(PatchError::Truncated);
+    }
+    if crc32fast::hash(&patch[..patch.len() - 4]) != read_crc(patch, 4) {
+        return Err(PatchError::PatchChecksum);
+    }
+    Ok(())
+}
+
+/// Reads a little endian crc32 stored `from_end` bytes before the end.
+fn read_crc(patch: &[u8], from_end: usize) -> u32 {
+    let at = patch.len() - from_end;
+    u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]])
+}
+
+/// The variable length integer encoding shared by UPS and BPS.
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let x = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(0x80 | x);
+            break;
+        }
+        out.push(x);
+        value -= 1;
+    }
+}
+
+struct PatchReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(bytes: &'a [u8], magic: &[u8]) -> Result<Self, PatchError> {
+        if !bytes.starts_with(magic) {
+            return Err(PatchError::UnknownFormat);
+        }
+        Ok(PatchReader {
+            bytes,
+            pos: magic.len(),
+        })
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(len).ok_or(PatchError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn be(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as usize))
+    }
+
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let x = self.byte()?;
+            value = value
+                .checked_add(
+                    (x as usize & 0x7F)
+                        .checked_mul(shift)
+                        .ok_or(PatchError::TooLarge)?,
+                )
+                .ok_or(PatchError::TooLarge)?;
+            if x & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::TooLarge)?;
+            value = value.checked_add(shift).ok_or(PatchError::TooLarge)?;
+        }
+    }
+
+    /// BPS copy offsets store their sign in the lowest bit.
+    fn signed_varint(&mut self) -> Result<isize, PatchError> {
+        let data = self.varint()?;
+        let magnitude = (data >> 1) as isize;
+        Ok(if data & 1 != 0 { -magnitude } else { magnitude })
+    }
+}
@@ -0,0 +1,82 @@
+//! A rom database for the regions iNES 1.0 headers can't give. Entries
+//! are keyed by the CRC32 of the PRG and CHR data, header left out, as the
+//! usual databases are. The file has one entry a line, the CRC32 in hex
+//! and then `ntsc`, `pal` or `dendy`, with `#` starting a comment:
+//!
+//! ```text
+//! # Elite (Europe)
+//! 5C4C2F2C pal
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use nesemu_core::Region;
+
+use crate::rom_loader::Rom;
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io(std::io::Error),
+    /// A line that isn't a CRC32 and a region, counting from 1.
+    BadLine(usize),
+}
+
+impl From<std::io::Error> for DatabaseError {
+    fn from(e: std::io::Error) -> Self {
+        DatabaseError::Io(e)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    regions: HashMap<u32, Region>,
+}
+
+impl RomDatabase {
+    pub fn parse(text: &str) -> Result<RomDatabase, DatabaseError> {
+        let mut regions = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = || DatabaseError::BadLine(number + 1);
+            let (crc, region) = line.split_once(char::is_whitespace).ok_or_else(bad)?;
+            let crc = u32::from_str_radix(crc, 16).map_err(|_| bad())?;
+            let region = match region.trim().to_ascii_lowercase().as_str() {
+                "ntsc" => Region::Ntsc,
+                "pal" => Region::Pal,
+                "dendy" => Region::Dendy,
+                _ => return Err(bad()),
+            };
+            regions.insert(crc, region);
+        }
+        Ok(RomDatabase { regions })
+    }
+
+    pub fn load(path: &Path) -> Result<RomDatabase, DatabaseError> {
+        RomDatabase::parse(&String::from_utf8_lossy(&fs::read(path)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn region(&self, rom: &Rom) -> Option<Region> {
+        self.regions.get(&rom_crc(rom)).copied()
+    }
+}
+
+/// The CRC32 a rom is listed under.
+pub fn rom_crc(rom: &Rom) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&rom.prg_rom);
+    hasher.update(&rom.chr_rom);
+    hasher.finalize()
+}
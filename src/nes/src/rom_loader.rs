@@ -1,34 +1,266 @@
 use std::fs;
+use std::path::{Path, PathBuf};
 
-use nesemu_core::{Read, Write};
+use std::sync::{Arc, RwLock};
 
-use crate::Nes;
+use nesemu_core::{Mirroring, Region};
 
+use crate::cartridge::Cartridge;
+use crate::input::DefaultInput;
+use crate::mapper::Mapper;
+use crate::nsf::Nsf;
+use crate::patch::PatchError;
+use crate::{archive, fds, patch, unif, Nes};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+const TRAINER_SIZE: usize = 0x0200;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(std::io::Error),
+    Archive(String),
+    NoRomInArchive,
+    BadHeader,
+    Truncated,
+    Patch(PatchError),
+    UnsupportedMapper(u16),
+    UnsupportedBoard(String),
+    /// FDS images need the disk system BIOS, see `Nes::fds_bios`.
+    MissingBios,
+}
+
+impl From<std::io::Error> for RomError {
+    fn from(e: std::io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+impl From<PatchError> for RomError {
+    fn from(e: PatchError) -> Self {
+        RomError::Patch(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+    Dual,
+    Dendy,
+}
+
+impl TvSystem {
+    /// Dual region games run fine as NTSC.
+    pub fn region(&self) -> Region {
+        match self {
+            TvSystem::Ntsc | TvSystem::Dual => Region::Ntsc,
+            TvSystem::Pal => Region::Pal,
+            TvSystem::Dendy => Region::Dendy,
+        }
+    }
+}
+
+/// A parsed rom image, whatever format it came from. This is what the
+/// cartridge gets built from.
 pub struct Rom {
-    header: Vec<u8>,
-    trainer: Option<Vec<u8>>,
-    prg_rom: Vec<u8>,
-    chr_rom: Vec<u8>,
+    pub header: Vec<u8>,
+    pub trainer: Option<Vec<u8>>,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u16,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    /// UNIF board name, iNES images only have a mapper number.
+    pub board: Option<String>,
+    pub tv_system: Option<TvSystem>,
+    /// UNIF `CTRL` bits: standard pad, zapper, R.O.B., Arkanoid, Power Pad,
+    /// Four Score.
+    pub controllers: Option<u8>,
+    /// The NES 2.0 default expansion device number.
+    pub expansion_device: Option<u8>,
 }
 
 impl Rom {
-    fn build(head: &[u8]) {
+    pub fn build(bytes: &[u8]) -> Result<Rom, RomError> {
+        if bytes.starts_with(unif::UNIF_MAGIC) {
+            return unif::parse(bytes);
+        }
+        if bytes.len() < 0x10 {
+            return Err(RomError::Truncated);
+        }
+        let head = &bytes[0..0x10];
         if !(head[0] == 0x4E && head[1] == 0x45 && head[2] == 0x53 && head[3] == 0x1A) {
-            // header bad
+            return Err(RomError::BadHeader);
+        }
+
+        let nes2 = head[7] & 0x0C == 0x08;
+        let (prg_size, chr_size) = if nes2 {
+            (
+                (((head[9] as usize & 0x0F) << 8) | head[4] as usize) * PRG_BANK_SIZE,
+                (((head[9] as usize & 0xF0) << 4) | head[5] as usize) * CHR_BANK_SIZE,
+            )
+        } else {
+            (
+                head[4] as usize * PRG_BANK_SIZE,
+                head[5] as usize * CHR_BANK_SIZE,
+            )
+        };
+
+        let mut offset = 0x10;
+        let trainer = if head[6] & 0x04 != 0 {
+            let trainer = take(bytes, &mut offset, TRAINER_SIZE)?;
+            Some(trainer.to_vec())
+        } else {
+            None
+        };
+        let prg_rom = take(bytes, &mut offset, prg_size)?.to_vec();
+        let chr_rom = take(bytes, &mut offset, chr_size)?.to_vec();
+
+        // old dumping tools left junk like "DiskDude!" in bytes 7-15, which
+        // would otherwise end up in the high mapper bits
+        let junk = !nes2 && head[12..16].iter().any(|b| *b != 0);
+        let mut mapper = (head[6] >> 4) as u16;
+        if !junk {
+            mapper |= (head[7] & 0xF0) as u16;
+        }
+        if nes2 {
+            mapper |= ((head[8] & 0x0F) as u16) << 8;
+        }
+        let tv_system = if nes2 {
+            match head[12] & 0x03 {
+                0 => Some(TvSystem::Ntsc),
+                1 => Some(TvSystem::Pal),
+                2 => Some(TvSystem::Dual),
+                _ => Some(TvSystem::Dendy),
+            }
+        } else if !junk && head[9] & 0x01 != 0 {
+            // iNES 1.0 can only say PAL, and hardly anything sets it
+            Some(TvSystem::Pal)
+        } else {
+            None
+        };
+        let mirroring = if head[6] & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if head[6] & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        Ok(Rom {
+            header: head.to_vec(),
+            trainer,
+            prg_rom,
+            chr_rom,
+            mapper,
+            mirroring,
+            battery: head[6] & 0x02 != 0,
+            board: None,
+            tv_system,
+            controllers: None,
+            expansion_device: nes2.then_some(head[15] & 0x3F),
+        })
+    }
+
+    /// The input devices the header asks for, if it says and they can be
+    /// emulated.
+    pub fn default_input(&self) -> Option<DefaultInput> {
+        match (self.expansion_device, self.controllers) {
+            (Some(device), _) => DefaultInput::from_nes2(device),
+            (None, Some(controllers)) => DefaultInput::from_unif(controllers),
+            (None, None) => None,
         }
     }
 }
 
+fn take<'a>(bytes: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], RomError> {
+    let slice = bytes
+        .get(*offset..*offset + len)
+        .ok_or(RomError::Truncated)?;
+    *offset += len;
+    Ok(slice)
+}
+
 impl Nes {
-    pub fn load_rom(&mut self, path: &str) -> Result<(), ()> {
-        let mut bytes: Vec<u8> = fs::read(path).expect("prob openin file");
-        bytes.drain(0..0x10);
-        for (k, v) in bytes.iter().enumerate() {
-            if (k) < 0x4000 {
-                self.bus.write().unwrap().write((k + 0x8000) as u16, *v);
-                self.bus.write().unwrap().write((k + 0xC000) as u16, *v);
-            }
+    /// Loads a rom image that is already in memory. This is the only way in
+    /// on the web, where there is no filesystem to read from. Zip archives
+    /// are accepted too, in which case the first rom inside is used.
+    pub fn load_rom_bytes(&mut self, bytes: &[u8]) -> Result<(), RomError> {
+        if archive::is_zip(bytes) {
+            let (_, contents) = archive::extract(bytes, None)?;
+            return self.load_rom_bytes(&contents);
+        }
+        // nothing changes unless the image loads, so a bad one leaves the
+        // current rom running and able to power cycle
+        let default_input = if Nsf::is_nsf(bytes) {
+            self.load_nsf(bytes)?;
+            None
+        } else if fds::is_fds(bytes) {
+            self.load_fds(bytes)?;
+            None
+        } else {
+            let rom = Rom::build(bytes)?;
+            let cartridge = Cartridge::new(&rom)?;
+            self.nsf = None;
+            self.insert_cartridge(cartridge);
+            // only NES 2.0 headers are sure of the region, iNES 1.0 ones
+            // are looked up if there is a database
+            let region = rom
+                .tv_system
+                .map(|tv| tv.region())
+                .or_else(|| self.rom_database.as_ref().and_then(|db| db.region(&rom)));
+            self.set_detected_region(region);
+            rom.default_input()
+        };
+        self.rom_path = None;
+        self.rom_image = Some(bytes.to_vec());
+        self.default_input = default_input;
+        Ok(())
+    }
+
+    /// Same as `load_rom_bytes`, but for archives holding several roms
+    /// where the caller wants a specific one (see `archive::rom_entries`).
+    pub fn load_rom_from_archive(&mut self, bytes: &[u8], entry: &str) -> Result<(), RomError> {
+        let (_, contents) = archive::extract(bytes, Some(entry))?;
+        self.load_rom_bytes(&contents)
+    }
+
+    /// Applies an IPS, UPS or BPS patch to the image before loading it.
+    pub fn load_rom_with_patch(&mut self, bytes: &[u8], patch: &[u8]) -> Result<(), RomError> {
+        let bytes = if archive::is_zip(bytes) {
+            archive::extract(bytes, None)?.1
+        } else {
+            bytes.to_vec()
+        };
+        let patched = patch::apply(&bytes, patch)?;
+        self.load_rom_bytes(&patched)
+    }
+
+    /// Loads a rom from disk. If a patch with the same name sits next to it
+    /// (`game.ips` beside `game.nes`), it is applied first. Disk images
+    /// also pick up the modifications saved by `save_fds_disk`.
+    pub fn load_rom(&mut self, path: &str) -> Result<(), RomError> {
+        let bytes: Vec<u8> = fs::read(path)?;
+        match patch::find_patch_for(Path::new(path)) {
+            Some(patch_path) => self.load_rom_with_patch(&bytes, &fs::read(patch_path)?)?,
+            None => self.load_rom_bytes(&bytes)?,
+        }
+        self.rom_path = Some(PathBuf::from(path));
+        let save_path = fds::save_path_for(Path::new(path));
+        if self.fds_side_count().is_some() && save_path.exists() {
+            self.load_fds_save(&fs::read(save_path)?)?;
         }
         Ok(())
     }
+
+    fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        let cartridge = Arc::new(RwLock::new(cartridge));
+        self.ppu.write().unwrap().cartridge = Some(cartridge.clone());
+        self.bus.write().unwrap().cartridge = Some(cartridge);
+    }
+
+    pub(crate) fn insert_mapper(&mut self, mapper: Box<dyn Mapper>) {
+        self.insert_cartridge(Cartridge::with_mapper(mapper));
+    }
 }
@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use nesemu_ppu::ntsc::{NtscFilter, NTSC_WIDTH};
+use nesemu_ppu::palette::Palette;
+use nesemu_ppu::ppu::{HEIGHT, WIDTH};
+
+use crate::Nes;
+
+/// A frame turned into RGBA pixels.
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn write_png(&self, path: &Path) -> Result<(), png::EncodingError> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.rgba)
+    }
+}
+
+impl Nes {
+    /// The last frame the PPU drew, through the NTSC filter if one is given.
+    pub fn screenshot(&self, palette: &Palette, ntsc: Option<&NtscFilter>) -> Screenshot {
+        let ppu = self.ppu.read().unwrap();
+        match ntsc {
+            Some(filter) => Screenshot {
+                width: NTSC_WIDTH,
+                height: HEIGHT,
+                rgba: filter.apply(&ppu.frame, ppu.frame_count),
+            },
+            None => Screenshot {
+                width: WIDTH,
+                height: HEIGHT,
+                rgba: palette.to_rgba(&ppu.frame),
+            },
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Save states: everything that changes as the console runs, to pick up
+//! from later. Each part serializes itself with serde and the lot is
+//! written with bincode after a magic and a version byte. The rom isn't
+//! in there, only its CRC32, so a state loads over the rom it came from.
+//!
+//! Boards and input devices are behind traits, so they hand over their
+//! state already encoded, through `Mapper::save_state` and the like.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use nesemu_cpu::cpu::CPU;
+use nesemu_ppu::ppu::PPU;
+
+use crate::apu::Apu;
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::dma::Dma;
+use crate::memory::CpuMemory;
+use crate::Nes;
+
+pub const MAGIC: &[u8] = b"NESSTA\x1A";
+const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    /// Not a save state, or one from another version.
+    BadState,
+    /// There is no rom loaded to save or load over.
+    NoRom,
+    /// Saved while another rom was loaded.
+    WrongRom,
+    /// Saved with no movie running, so it has no place in the one that is.
+    NotInMovie,
+}
+
+/// Encodes one part of a state.
+pub(crate) fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    // bincode only fails on types that refuse to serialize
+    bincode::serialize(value).expect("state parts always serialize")
+}
+
+pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, StateError> {
+    bincode::deserialize(bytes).map_err(|_| StateError::BadState)
+}
+
+/// The CPU's registers, including the instruction it is part way into.
+#[derive(Serialize, Deserialize)]
+struct CpuRegisters {
+    acc_reg: u8,
+    x_reg: u8,
+    y_reg: u8,
+    stk_ptr: u8,
+    pgrm_ctr: u16,
+    status: u8,
+    fetched: u8,
+    addr_abs: u16,
+    addr_rel: u16,
+    opcode: u8,
+    cycles: u8,
+}
+
+impl CpuRegisters {
+    fn new(cpu: &CPU<Bus<CpuMemory>>) -> Self {
+        CpuRegisters {
+            acc_reg: cpu.acc_reg,
+            x_reg: cpu.x_reg,
+            y_reg: cpu.y_reg,
+            stk_ptr: cpu.stk_ptr,
+            pgrm_ctr: cpu.pgrm_ctr,
+            status: cpu.status,
+            fetched: cpu.fetched,
+            addr_abs: cpu.addr_abs,
+            addr_rel: cpu.addr_rel,
+            opcode: cpu.opcode,
+            cycles: cpu.cycles,
+        }
+    }
+
+    fn restore(&self, cpu: &mut CPU<Bus<CpuMemory>>) {
+        cpu.acc_reg = self.acc_reg;
+        cpu.x_reg = self.x_reg;
+        cpu.y_reg = self.y_reg;
+        cpu.stk_ptr = self.stk_ptr;
+        cpu.pgrm_ctr = self.pgrm_ctr;
+        cpu.status = self.status;
+        cpu.fetched = self.fetched;
+        cpu.addr_abs = self.addr_abs;
+        cpu.addr_rel = self.addr_rel;
+        cpu.opcode = self.opcode;
+        cpu.cycles = self.cycles;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+    rom_crc: u32,
+    cpu: CpuRegisters,
+    ram: Vec<u8>,
+    ppu: Vec<u8>,
+    apu: Vec<u8>,
+    dma: Vec<u8>,
+    cartridge: Vec<u8>,
+    ports: [Vec<u8>; 2],
+    expansion: Vec<u8>,
+    /// The NSF player's song, cycles until PLAY and cycles played.
+    nsf: Option<(u8, u64, u64)>,
+    oam_dma: Option<u8>,
+    cycles: u64,
+    dot_remainder: u32,
+    irq_line: bool,
+    buttons: [u8; 4],
+    commands: u8,
+    input_frame: Option<u64>,
+    fds_next_side: usize,
+    /// The movie frame it was saved on, if one was running.
+    movie_frame: Option<usize>,
+}
+
+impl Nes {
+    fn rom_crc(&self) -> Result<u32, StateError> {
+        let image = self.rom_image.as_ref().ok_or(StateError::NoRom)?;
+        Ok(crc32fast::hash(image))
+    }
+
+    /// Takes a snapshot of the running console. Settings, what is plugged
+    /// in and the rom itself are left out.
+    pub fn save_state(&self) -> Result<Vec<u8>, StateError> {
+        let rom_crc = self.rom_crc()?;
+        let bus = self.bus.read().unwrap();
+        let ports = bus.ports.lock().unwrap();
+        let state = SaveState {
+            rom_crc,
+            cpu: CpuRegisters::new(&self.cpu),
+            ram: encode(&*self.ram.read().unwrap()),
+            ppu: encode(&*self.ppu.read().unwrap()),
+            apu: encode(&*self.apu.read().unwrap()),
+            dma: encode(&self.dma),
+            cartridge: bus
+                .cartridge
+                .as_ref()
+                .map_or_else(Vec::new, |cartridge| cartridge.read().unwrap().save_state()),
+            ports: [ports[0].save_state(), ports[1].save_state()],
+            expansion: bus
+                .expansion
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map_or_else(Vec::new, |device| device.save_state()),
+            nsf: self.nsf.as_ref().map(|player| {
+                (
+                    player.track,
+                    player.cycles_until_play,
+                    player.elapsed_cycles,
+                )
+            }),
+            oam_dma: bus.oam_dma,
+            cycles: self.cycles,
+            dot_remainder: self.dot_remainder,
+            irq_line: self.irq_line,
+            buttons: self.buttons,
+            commands: self.commands,
+            input_frame: self.input_frame,
+            fds_next_side: self.fds_next_side,
+            movie_frame: self.movie.as_ref().map(|session| session.frame),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend(encode(&state));
+        Ok(bytes)
+    }
+
+    /// Goes back to a state from `save_state`. Nothing changes unless it
+    /// loads. With a movie running, the movie goes back to the frame the
+    /// state was saved on too, see `movie_state_loaded`.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let body = match bytes
+            .strip_prefix(MAGIC)
+            .and_then(|rest| rest.split_first())
+        {
+            Some((&VERSION, body)) => body,
+            _ => return Err(StateError::BadState),
+        };
+        let state: SaveState = decode(body)?;
+        if state.rom_crc != self.rom_crc()? {
+            return Err(StateError::WrongRom);
+        }
+        if self.movie.is_some() && state.movie_frame.is_none() {
+            return Err(StateError::NotInMovie);
+        }
+        let ram: CpuMemory = decode(&state.ram)?;
+        let mut ppu: PPU<Cartridge> = decode(&state.ppu)?;
+        let mut apu: Apu = decode(&state.apu)?;
+        let dma: Dma = decode(&state.dma)?;
+        {
+            let mut bus = self.bus.write().unwrap();
+            // the board checks its own state, so it goes before anything
+            // else is changed
+            if let Some(cartridge) = &bus.cartridge {
+                cartridge.write().unwrap().load_state(&state.cartridge)?;
+            }
+            let mut ports = bus.ports.lock().unwrap();
+            for (port, saved) in ports.iter_mut().zip(&state.ports) {
+                port.load_state(saved);
+            }
+            drop(ports);
+            if let Some(device) = bus.expansion.lock().unwrap().as_mut() {
+                device.load_state(&state.expansion);
+            }
+            bus.oam_dma = state.oam_dma;
+        }
+
+        *self.ram.write().unwrap() = ram;
+        {
+            let mut current = self.ppu.write().unwrap();
+            ppu.cartridge = current.cartridge.take();
+            ppu.region = current.region;
+            ppu.unlimited_sprites = current.unlimited_sprites;
+            ppu.frame = std::mem::take(&mut current.frame);
+            *current = ppu;
+        }
+        {
+            let mut current = self.apu.write().unwrap();
+            apu.region = current.region;
+            *current = apu;
+        }
+        self.dma = dma;
+        state.cpu.restore(&mut self.cpu);
+        if let (Some(player), Some((track, until_play, elapsed))) = (&mut self.nsf, state.nsf) {
+            player.track = track;
+            player.cycles_until_play = until_play;
+            player.elapsed_cycles = elapsed;
+        }
+        self.cycles = state.cycles;
+        self.dot_remainder = state.dot_remainder;
+        self.irq_line = state.irq_line;
+        self.buttons = state.buttons;
+        self.commands = state.commands;
+        self.input_frame = state.input_frame;
+        self.fds_next_side = state.fds_next_side;
+        if let Some(frame) = state.movie_frame {
+            self.movie_state_loaded(frame);
+        }
+        Ok(())
+    }
+}
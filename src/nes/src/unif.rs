@@ -0,0 +1,132 @@
+//! UNIF (.unf) images. Instead of a fixed header these are a list of
+//! tagged chunks, and the board is named by string rather than by an iNES
+//! mapper number.
+
+use nesemu_core::Mirroring;
+
+use crate::rom_loader::{Rom, RomError, TvSystem};
+
+pub const UNIF_MAGIC: &[u8] = b"UNIF";
+const HEADER_SIZE: usize = 0x20;
+
+/// Board names that only differ by maker are the same hardware.
+const BOARD_PREFIXES: [&str; 6] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-"];
+
+pub fn parse(bytes: &[u8]) -> Result<Rom, RomError> {
+    if !bytes.starts_with(UNIF_MAGIC) {
+        return Err(RomError::BadHeader);
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(RomError::Truncated);
+    }
+
+    let mut board = None;
+    let mut prg_chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut chr_chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut mirroring = None;
+    let mut battery = false;
+    let mut tv_system = None;
+    let mut controllers = None;
+
+    let mut offset = HEADER_SIZE;
+    while offset < bytes.len() {
+        let head = bytes.get(offset..offset + 8).ok_or(RomError::Truncated)?;
+        let id = &head[0..4];
+        let len = u32::from_le_bytes([head[4], head[5], head[6], head[7]]) as usize;
+        let data = bytes
+            .get(offset + 8..offset + 8 + len)
+            .ok_or(RomError::Truncated)?;
+        offset += 8 + len;
+
+        match id {
+            b"MAPR" => {
+                let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                board = Some(String::from_utf8_lossy(&data[..end]).trim().to_string());
+            }
+            b"MIRR" => mirroring = data.first().copied(),
+            b"BATR" => battery = !matches!(data.first(), Some(0)),
+            b"TVCI" => {
+                tv_system = match data.first() {
+                    Some(0) => Some(TvSystem::Ntsc),
+                    Some(1) => Some(TvSystem::Pal),
+                    Some(2) => Some(TvSystem::Dual),
+                    _ => None,
+                }
+            }
+            b"CTRL" => controllers = data.first().copied(),
+            _ => {
+                if let Some(n) = chunk_index(id, b"PRG") {
+                    prg_chunks.push((n, data));
+                } else if let Some(n) = chunk_index(id, b"CHR") {
+                    chr_chunks.push((n, data));
+                }
+                // everything else (NAME, READ, DINF, checksums...) is
+                // informational only
+            }
+        }
+    }
+
+    let board = board.ok_or(RomError::BadHeader)?;
+    let mapper =
+        mapper_for_board(&board).ok_or_else(|| RomError::UnsupportedBoard(board.clone()))?;
+    let mirroring = match mirroring {
+        Some(1) => Mirroring::Vertical,
+        Some(2) => Mirroring::SingleScreenLower,
+        Some(3) => Mirroring::SingleScreenUpper,
+        Some(4) => Mirroring::FourScreen,
+        // 0 is horizontal, 5 means the mapper decides
+        _ => Mirroring::Horizontal,
+    };
+
+    Ok(Rom {
+        header: bytes[..HEADER_SIZE].to_vec(),
+        trainer: None,
+        prg_rom: concat_chunks(prg_chunks),
+        chr_rom: concat_chunks(chr_chunks),
+        mapper,
+        mirroring,
+        battery,
+        board: Some(board),
+        tv_system,
+        controllers,
+        expansion_device: None,
+    })
+}
+
+/// Translates a UNIF board name to the iNES mapper implementing it. The
+/// boards known are the NROM, SxROM (including 512k SUROM/SXROM), UxROM,
+/// CNROM and AxROM families; a multicart or bootleg board is only loaded
+/// when its name, less the maker prefix, is one of those.
+pub fn mapper_for_board(name: &str) -> Option<u16> {
+    let name = name.to_ascii_uppercase();
+    let name = BOARD_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(&name);
+
+    match name {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => Some(0),
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM"
+        | "SLROM" | "SL1ROM" | "SNROM" | "SOROM" | "SUROM" | "SXROM" => Some(1),
+        "UNROM" | "UOROM" => Some(2),
+        "CNROM" => Some(3),
+        "ANROM" | "AN1ROM" | "AMROM" | "AOROM" => Some(7),
+        _ => None,
+    }
+}
+
+/// PRG0..PRGF / CHR0..CHRF, the last character being a hex digit.
+fn chunk_index(id: &[u8], prefix: &[u8]) -> Option<u8> {
+    if !id.starts_with(prefix) {
+        return None;
+    }
+    (id[3] as char).to_digit(16).map(|n| n as u8)
+}
+
+fn concat_chunks(mut chunks: Vec<(u8, &[u8])>) -> Vec<u8> {
+    chunks.sort_by_key(|(n, _)| *n);
+    chunks
+        .into_iter()
+        .flat_map(|(_, data)| data.iter().copied())
+        .collect::<Vec<u8>>()
+}
@@ -0,0 +1,147 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::apu::Apu;
+    use nesemu_core::{Read, Write};
+
+    use crate::common::{ines_image, setup};
+
+    fn run(apu: &mut Apu, cycles: u32) {
+        for _ in 0..cycles {
+            apu.clock();
+        }
+    }
+
+    #[test]
+    fn length_counters_show_in_status() {
+        let mut apu = Apu::new();
+        // not loaded while the channel is disabled
+        apu.write(0x4003, 0x08);
+        assert_eq!(apu.peek_status() & 0x01, 0);
+
+        apu.write(0x4015, 0x0F);
+        apu.write(0x4003, 0x08);
+        apu.write(0x400B, 0x08);
+        apu.write(0x400F, 0x08);
+        assert_eq!(apu.peek_status() & 0x0F, 0x0D);
+
+        // counted down on the first half frame
+        run(&mut apu, 14913);
+        assert_eq!(apu.pulse1.length.counter, 253);
+        apu.write(0x4015, 0x01);
+        assert_eq!(apu.peek_status() & 0x0F, 0x01);
+    }
+
+    #[test]
+    fn four_step_mode_raises_a_frame_irq() {
+        let mut apu = Apu::new();
+        run(&mut apu, 29827);
+        assert!(!apu.irq());
+        run(&mut apu, 1);
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+
+        // inhibited, and five step mode never raises it
+        apu.write(0x4017, 0x40);
+        run(&mut apu, 30000);
+        assert!(!apu.irq());
+        apu.write(0x4017, 0x80);
+        run(&mut apu, 40000);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn pulse_follows_its_duty_cycle() {
+        let mut apu = Apu::new();
+        apu.write(0x4015, 0x01);
+        // 50% duty, constant volume 9, period 100
+        apu.write(0x4000, 0xB9);
+        apu.write(0x4002, 100);
+        apu.write(0x4003, 0x08);
+
+        let mut high = 0;
+        for _ in 0..101 * 2 * 8 {
+            apu.clock();
+            match apu.levels()[0] {
+                9 => high += 1,
+                0 => {}
+                level => panic!("unexpected level {}", level),
+            }
+        }
+        assert_eq!(high, 101 * 8);
+
+        // periods under 8 are muted
+        apu.write(0x4002, 7);
+        apu.write(0x4003, 0x08);
+        for _ in 0..64 {
+            apu.clock();
+            assert_eq!(apu.levels()[0], 0);
+        }
+    }
+
+    #[test]
+    fn triangle_and_noise_need_their_counters() {
+        let mut apu = Apu::new();
+        apu.write(0x4015, 0x0C);
+        apu.write(0x4008, 0x7F);
+        apu.write(0x400A, 0x10);
+        apu.write(0x400B, 0x08);
+        apu.write(0x400E, 0x00);
+        apu.write(0x400C, 0x3F);
+        apu.write(0x400F, 0x08);
+
+        // the linear counter is only loaded on the first quarter frame
+        let level = apu.levels()[2];
+        run(&mut apu, 1000);
+        assert_eq!(apu.levels()[2], level);
+
+        run(&mut apu, 7457);
+        let mut triangle = std::collections::HashSet::new();
+        let mut noise = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            apu.clock();
+            let levels = apu.levels();
+            triangle.insert(levels[2]);
+            noise.insert(levels[3]);
+        }
+        assert_eq!(triangle.len(), 16);
+        assert_eq!(noise, [0, 15].into_iter().collect());
+    }
+
+    #[test]
+    fn dmc_fetches_through_dma_and_raises_its_irq() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        image[0x10] = 0xFF;
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+
+        {
+            let mut bus = nes.bus.write().unwrap();
+            // IRQ on, fastest rate, one byte at $C000
+            bus.write(0x4010, 0x8F);
+            bus.write(0x4011, 0x40);
+            bus.write(0x4012, 0x00);
+            bus.write(0x4013, 0x00);
+            bus.write(0x4015, 0x10);
+            assert_eq!(bus.read(0x4015, true) & 0x90, 0x10);
+        }
+        nes.clock();
+        assert!(nes.dma.active());
+        for _ in 0..4 {
+            nes.clock();
+        }
+        assert!(!nes.dma.active());
+        let status = nes.bus.read().unwrap().read(0x4015, false);
+        assert_eq!(status & 0x90, 0x80);
+
+        // the byte is all ones, so the level climbs as it plays out
+        for _ in 0..54 * 20 {
+            nes.clock();
+        }
+        assert_eq!(nes.apu.read().unwrap().levels()[4], 0x40 + 16);
+    }
+}
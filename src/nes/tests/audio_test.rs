@@ -0,0 +1,131 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::audio::blip::{BlipBuffer, KERNEL_WIDTH};
+    use nesemu::audio::{note_name, Mixer, SINK_CAPACITY};
+    use nesemu_core::Write;
+
+    use crate::common::{ines_image, setup};
+
+    const CLOCK: f64 = 1_789_773.0;
+
+    #[test]
+    fn blip_steps_settle_at_their_level() {
+        let mut blip = BlipBuffer::new(CLOCK, 48000.0);
+        blip.add_delta(100, 0.5);
+        blip.end_frame(CLOCK as u32 / 100);
+        // 480 less the rounding
+        assert_eq!(blip.samples_available(), 479);
+
+        let mut out = [0.0; 479];
+        assert_eq!(blip.read_samples(&mut out), 479);
+        // nothing before the step, allowing for the ringing
+        assert!(out[..2].iter().all(|sample| sample.abs() < 0.01));
+        assert!(out[KERNEL_WIDTH + 3..]
+            .iter()
+            .all(|sample| (sample - 0.5).abs() < 1e-4));
+        assert_eq!(blip.samples_available(), 0);
+    }
+
+    #[test]
+    fn square_wave_comes_out_at_its_pitch() {
+        let mut mixer = Mixer::new(CLOCK, 44100);
+        mixer.rate_control = false;
+        let sink = mixer.sink();
+        // 1kHz at full volume on the first pulse, for 50ms
+        let half_period = (CLOCK / 2000.0) as u32;
+        for cycle in 0..CLOCK as u32 / 20 {
+            let level = if (cycle / half_period) & 1 == 0 {
+                15
+            } else {
+                0
+            };
+            mixer.clock([level, 0, 0, 0, 0], &[]);
+        }
+
+        let mut out = vec![0.0; 4096];
+        let count = sink.drain(&mut out);
+        // the last couple of milliseconds are still in the mixer
+        assert!((2100..=2205).contains(&count), "{} samples", count);
+        assert!(sink.is_empty());
+        let crossings = out[..count]
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!((92..=101).contains(&crossings), "{} crossings", crossings);
+    }
+
+    #[test]
+    fn sink_keeps_the_newest_samples() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        let sink = nes.mixer.sink();
+
+        nes.run_frame();
+        nes.run_frame();
+        // about 800 samples a frame at 48kHz
+        assert!((1500..1700).contains(&sink.len()), "{}", sink.len());
+        for _ in 0..10 {
+            nes.run_frame();
+        }
+        assert_eq!(sink.len(), SINK_CAPACITY);
+    }
+
+    #[test]
+    fn muted_channels_drop_out_of_the_mix() {
+        let mut mixer = Mixer::new(CLOCK, 48000);
+        mixer.rate_control = false;
+        let sink = mixer.sink();
+        mixer.apu_volumes[2].muted = true;
+        mixer.expansion_volumes = vec![Default::default(); 1];
+        mixer.expansion_volumes[0].volume = 0.0;
+        for cycle in 0..40960u32 {
+            let level = (cycle / 500 % 16) as u8;
+            mixer.clock([0, 0, level, 0, 0], &[level as f32 / 15.0]);
+        }
+        let mut out = vec![0.0; 2048];
+        let count = sink.drain(&mut out);
+        assert!(count > 1000);
+        assert!(out[..count].iter().all(|sample| *sample == 0.0));
+
+        // the scopes still show what the channels are doing
+        let scope = mixer.scope(2).unwrap();
+        assert_eq!(scope.len(), 1024);
+        assert!(scope.iter().any(|level| *level == 1.0));
+        assert_eq!(mixer.scope(5).unwrap().len(), 1024);
+        assert!(mixer.scope(6).is_none());
+    }
+
+    #[test]
+    fn notes_are_named_with_cents() {
+        assert_eq!(note_name(440.0), "A4 +0c");
+        assert_eq!(note_name(261.63), "C4 +0c");
+        assert_eq!(note_name(452.0), "A4 +47c");
+        assert_eq!(note_name(27.0), "A0 -32c");
+    }
+
+    #[test]
+    fn channels_report_what_they_play() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        {
+            let mut bus = nes.bus.write().unwrap();
+            bus.write(0x4015, 0x01);
+            // A4: 1789773 / (16 * 440) - 1 = 253
+            bus.write(0x4000, 0x9F);
+            bus.write(0x4002, 253);
+            bus.write(0x4003, 0x08);
+        }
+        let channels = nes.audio_channels();
+        assert_eq!(channels.len(), 5);
+        let pulse = &channels[0];
+        assert_eq!(pulse.name, "Pulse 1");
+        assert_eq!((pulse.period, pulse.volume, pulse.duty), (253, 15, Some(2)));
+        assert_eq!(note_name(pulse.frequency.unwrap()), "A4 +2c");
+        assert_eq!(channels[1].frequency, None);
+    }
+}
@@ -0,0 +1,19 @@
+#![allow(dead_code)]
+
+use nesemu::Nes;
+
+pub fn setup() -> Nes {
+    Nes::new()
+}
+
+/// Builds a bare iNES image with `prg_banks` 16k banks, each filled with
+/// its own bank number so tests can tell them apart.
+pub fn ines_image(prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+    let mut bytes = vec![0x4E, 0x45, 0x53, 0x1A, prg_banks, chr_banks];
+    bytes.resize(0x10, 0);
+    for bank in 0..prg_banks {
+        bytes.resize(bytes.len() + 0x4000, bank);
+    }
+    bytes.resize(bytes.len() + chr_banks as usize * 0x2000, 0xCC);
+    bytes
+}
@@ -0,0 +1,98 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::Nes;
+    use nesemu_core::Write;
+
+    use crate::common::{ines_image, setup};
+
+    /// A console running a rom full of NOPs, with a recognisable page of
+    /// data at $0200.
+    fn nes() -> Nes {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        let mut bus = nes.bus.write().unwrap();
+        for i in 0..0x100 {
+            bus.write(0x0200 + i, i as u8 ^ 0x5A);
+        }
+        drop(bus);
+        nes
+    }
+
+    /// Starts a copy from $0200 and returns how many cycles it took.
+    fn run_dma(nes: &mut Nes) -> u32 {
+        nes.bus.write().unwrap().write(0x4014, 0x02);
+        nes.clock();
+        let mut cycles = 1;
+        while nes.dma.active() {
+            nes.clock();
+            cycles += 1;
+        }
+        cycles
+    }
+
+    #[test]
+    fn copies_a_page_to_oam() {
+        let mut nes = nes();
+        nes.bus.write().unwrap().write(0x2003, 0x00);
+        run_dma(&mut nes);
+        let ppu = nes.ppu.read().unwrap();
+        for i in 0..0x100 {
+            assert_eq!(ppu.oam[i], i as u8 ^ 0x5A);
+        }
+    }
+
+    #[test]
+    fn starts_at_oamaddr_and_wraps() {
+        let mut nes = nes();
+        nes.bus.write().unwrap().write(0x2003, 0x10);
+        run_dma(&mut nes);
+        let ppu = nes.ppu.read().unwrap();
+        assert_eq!(ppu.oam[0x10], 0x5A);
+        assert_eq!(ppu.oam[0x0F], 0xFF ^ 0x5A);
+    }
+
+    #[test]
+    fn stalls_513_or_514_cycles() {
+        let mut nes = nes();
+        nes.cycles = 1;
+        assert_eq!(run_dma(&mut nes), 513);
+
+        let mut nes = self::nes();
+        nes.cycles = 0;
+        assert_eq!(run_dma(&mut nes), 514);
+    }
+
+    #[test]
+    fn dmc_fetches_steal_cycles() {
+        let mut nes = nes();
+        nes.cycles = 1;
+        nes.bus.write().unwrap().write(0x4014, 0x02);
+        for _ in 0..100 {
+            nes.clock();
+        }
+        nes.dma.request_dmc(0x0203);
+        let mut cycles = 100;
+        while nes.dma.active() {
+            nes.clock();
+            cycles += 1;
+        }
+        assert_eq!(cycles, 513 + 2);
+        assert_eq!(nes.dma.take_dmc_sample(), Some(0x03 ^ 0x5A));
+        assert_eq!(nes.ppu.read().unwrap().oam[0xFF], 0xFF ^ 0x5A);
+
+        // on its own it halts the CPU for four cycles
+        nes.dma.request_dmc(0x0204);
+        let mut cycles = 0;
+        while nes.dma.active() {
+            nes.clock();
+            cycles += 1;
+        }
+        assert_eq!(cycles, 4);
+        assert_eq!(nes.dma.take_dmc_sample(), Some(0x04 ^ 0x5A));
+    }
+}
@@ -0,0 +1,72 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::events::{register_name, EventKind};
+    use nesemu_core::{Read, Write};
+
+    use crate::common::{ines_image, setup};
+
+    #[test]
+    fn logs_register_accesses_with_the_ppu_position() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        {
+            let mut bus = nes.bus.write().unwrap();
+            bus.pc = 0xC123;
+            // nothing is kept until asked for
+            bus.write(0x2001, 0x00);
+            bus.events.lock().unwrap().enabled = true;
+            bus.write(0x2000, 0x80);
+            bus.read(0x2002, false);
+            // peeks from the debugger don't count
+            bus.read(0x2002, true);
+            bus.write(0x8000, 0x01);
+            bus.write(0x6000, 0x01);
+        }
+        let bus = nes.bus.read().unwrap();
+        let log = bus.events.lock().unwrap();
+        let events = log.current_frame();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].kind, EventKind::PpuWrite);
+        assert_eq!(
+            (events[0].addr, events[0].value, events[0].pc),
+            (0x2000, 0x80, 0xC123)
+        );
+        assert_eq!(events[1].kind, EventKind::PpuRead);
+        assert_eq!(register_name(events[1].addr), Some("PPUSTATUS"));
+        assert_eq!(
+            (events[2].kind, events[2].addr),
+            (EventKind::MapperWrite, 0x8000)
+        );
+    }
+
+    #[test]
+    fn logs_nmis_and_keeps_whole_frames() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        // a bank of NOPs with the NMI vector pointing into it
+        image[0x10..0x4010].fill(0xEA);
+        image[0x400A] = 0x00;
+        image[0x400B] = 0xC0;
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        {
+            let mut bus = nes.bus.write().unwrap();
+            bus.events.lock().unwrap().enabled = true;
+            bus.write(0x2000, 0x80);
+            // keep the APU frame IRQ out of the log
+            bus.write(0x4017, 0x40);
+        }
+        nes.run_frame();
+        nes.run_frame();
+
+        let bus = nes.bus.read().unwrap();
+        let log = bus.events.lock().unwrap();
+        let events = log.last_frame();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Nmi);
+        assert_eq!((events[0].scanline, events[0].dot), (241, 1));
+        assert!(log.current_frame().is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::fds::adapter::FdsAdapter;
+    use nesemu::fds::{self, FdsDisk, BIOS_SIZE, SIDE_SIZE};
+    use nesemu::mapper::Mapper;
+    use nesemu::movie::{COMMAND_FDS_INSERT, COMMAND_FDS_SELECT};
+    use nesemu::rom_loader::RomError;
+    use nesemu_core::Mirroring;
+
+    use crate::common::setup;
+
+    /// One side holding the disk info block, a file count, and a single
+    /// four byte file.
+    fn side() -> Vec<u8> {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(56, 0);
+        side.extend_from_slice(&[2, 1]);
+        let mut header = vec![3, 0, 0];
+        header.extend_from_slice(b"FILENAME");
+        header.extend_from_slice(&[0x00, 0x60, 4, 0, 0]);
+        side.extend(header);
+        side.extend_from_slice(&[4, 0xDE, 0xAD, 0xBE, 0xEF]);
+        side.resize(SIDE_SIZE, 0);
+        side
+    }
+
+    fn image(sides: usize, header: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if header {
+            bytes.extend_from_slice(fds::FDS_MAGIC);
+            bytes.push(sides as u8);
+            bytes.resize(0x10, 0);
+        }
+        for _ in 0..sides {
+            bytes.extend(side());
+        }
+        bytes
+    }
+
+    fn adapter() -> FdsAdapter {
+        let mut bios = vec![0xEA; BIOS_SIZE];
+        bios[0x1FFC] = 0x00;
+        bios[0x1FFD] = 0xE0;
+        FdsAdapter::new(bios, FdsDisk::parse(&image(2, true)).unwrap())
+    }
+
+    #[test]
+    fn parses_images_with_and_without_header() {
+        for header in [true, false] {
+            let bytes = image(2, header);
+            assert!(fds::is_fds(&bytes));
+            let disk = FdsDisk::parse(&bytes).unwrap();
+            assert_eq!(disk.side_count(), 2);
+            assert_eq!(disk.image(), image(2, false));
+        }
+        assert!(matches!(
+            FdsDisk::parse(b"NES\x1A"),
+            Err(RomError::BadHeader)
+        ));
+    }
+
+    #[test]
+    fn raw_sides_round_trip() {
+        let raw = fds::to_raw(&side());
+        // lead-in, then the start mark of the disk info block
+        let start = raw.iter().position(|b| *b != 0).unwrap();
+        assert_eq!(raw[start], 0x80);
+        assert_eq!(raw[start + 1], 0x01);
+        assert_eq!(fds::from_raw(&raw), side());
+    }
+
+    #[test]
+    fn needs_a_bios() {
+        let mut nes = setup();
+        assert!(matches!(
+            nes.load_rom_bytes(&image(1, true)),
+            Err(RomError::MissingBios)
+        ));
+
+        let mut bios = vec![0; BIOS_SIZE];
+        bios[0x1FFC] = 0x34;
+        bios[0x1FFD] = 0xE2;
+        nes.fds_bios = Some(bios);
+        nes.load_rom_bytes(&image(2, true)).unwrap();
+        assert_eq!(nes.cpu.pgrm_ctr, 0xE234);
+        assert_eq!(nes.fds_side_count(), Some(2));
+        assert_eq!(nes.fds_current_side(), Some(0));
+        nes.fds_eject();
+        assert_eq!(nes.fds_current_side(), None);
+    }
+
+    #[test]
+    fn changing_sides_goes_through_movie_commands() {
+        let mut nes = setup();
+        let mut bios = vec![0xEA; BIOS_SIZE];
+        bios[0x1FFC] = 0x00;
+        bios[0x1FFD] = 0xE0;
+        nes.fds_bios = Some(bios);
+        nes.load_rom_bytes(&image(3, true)).unwrap();
+        nes.record_movie(None).unwrap();
+        nes.fds_change_side(2);
+        for _ in 0..6 {
+            nes.run_frame();
+        }
+        let commands = nes.movie.as_ref().unwrap().movie.frames[..6]
+            .iter()
+            .map(|frame| frame.commands)
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            commands,
+            [
+                COMMAND_FDS_INSERT,
+                COMMAND_FDS_SELECT,
+                COMMAND_FDS_SELECT,
+                COMMAND_FDS_INSERT,
+                0,
+                0
+            ]
+        );
+        // and it goes in once the drive is done
+        for _ in 0..60 {
+            nes.run_frame();
+        }
+        assert_eq!(nes.fds_current_side(), Some(2));
+    }
+
+    #[test]
+    fn timer_irq_fires_and_is_acknowledged() {
+        let mut fds = adapter();
+        fds.cpu_write(0x4023, 0x01);
+        fds.cpu_write(0x4020, 10);
+        fds.cpu_write(0x4021, 0);
+        fds.cpu_write(0x4022, 0x02);
+        for _ in 0..10 {
+            fds.clock();
+        }
+        assert!(!fds.irq());
+        fds.clock();
+        assert!(fds.irq());
+
+        // reading doesn't acknowledge unless it's the CPU doing it
+        assert_eq!(fds.cpu_read(0x4030) & 0x01, 0x01);
+        assert!(fds.irq());
+        assert_eq!(fds.cpu_read_mut(0x4030) & 0x01, 0x01);
+        assert!(!fds.irq());
+
+        // no repeat flag, so it stays quiet
+        for _ in 0..100 {
+            fds.clock();
+        }
+        assert!(!fds.irq());
+    }
+
+    #[test]
+    fn drive_reads_blocks_and_sets_mirroring() {
+        let mut fds = adapter();
+        fds.cpu_write(0x4023, 0x01);
+        assert_eq!(fds.mirroring(), Mirroring::Vertical);
+        // motor on, read mode, horizontal, ready, IRQ on transfer
+        fds.cpu_write(0x4025, 0xCD);
+        assert_eq!(fds.mirroring(), Mirroring::Horizontal);
+
+        let mut cycles = 0;
+        while !fds.irq() {
+            fds.clock();
+            cycles += 1;
+            assert!(cycles < 1_000_000, "no disk IRQ");
+        }
+        // the first byte handed over is the block type after the start mark
+        assert_eq!(fds.cpu_read_mut(0x4031), 0x01);
+        assert!(!fds.irq());
+        while !fds.irq() {
+            fds.clock();
+        }
+        assert_eq!(fds.cpu_read_mut(0x4031), b'*');
+    }
+
+    #[test]
+    fn modifications_are_saved_separately() {
+        let path = std::env::temp_dir().join("nesemu_fds_save_test.fds");
+        let original = image(1, true);
+        std::fs::write(&path, &original).unwrap();
+        let save_path = fds::save_path_for(&path);
+        let _ = std::fs::remove_file(&save_path);
+
+        let mut nes = setup();
+        nes.fds_bios = Some(vec![0; BIOS_SIZE]);
+        nes.load_rom(path.to_str().unwrap()).unwrap();
+        nes.save_fds_disk().unwrap();
+        assert!(!save_path.exists(), "nothing written, nothing saved");
+
+        let mut disk = FdsDisk::parse(&original).unwrap();
+        let raw = fds::to_raw(&side());
+        let data = raw.windows(4).position(|w| w == [0xDE, 0xAD, 0xBE, 0xEF]);
+        disk.write(0, data.unwrap(), 0x42);
+        assert!(disk.modified());
+        let save = disk.save_data().unwrap();
+        std::fs::write(&save_path, save).unwrap();
+
+        let mut nes = setup();
+        nes.fds_bios = Some(vec![0; BIOS_SIZE]);
+        nes.load_rom(path.to_str().unwrap()).unwrap();
+        let mut expected = side();
+        // first byte of the file, after its block type
+        expected[56 + 2 + 16 + 1] = 0x42;
+        assert_eq!(nes.fds_disk_image(), Some(expected));
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+
+        std::fs::remove_file(&save_path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
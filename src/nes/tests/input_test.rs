@@ -0,0 +1,341 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::input::arkanoid::{Arkanoid, FamicomArkanoid};
+    use nesemu::input::controller::StandardController;
+    use nesemu::input::keyboard::{FamilyKeyboard, KEYBOARD_KEYS};
+    use nesemu::input::power_pad::{FamilyTrainer, PowerPad};
+    use nesemu::input::zapper::Zapper;
+    use nesemu::input::{Button, DefaultInput, ExpansionDevice, InputDevice, Pointer};
+    use nesemu::Nes;
+    use nesemu_core::{Read, Region, Write};
+    use nesemu_ppu::ppu::WIDTH;
+
+    use crate::common::{ines_image, setup};
+
+    fn read_port(nes: &Nes, addr: u16, count: usize) -> Vec<u8> {
+        let bus = nes.bus.read().unwrap();
+        (0..count).map(|_| bus.read(addr, false)).collect()
+    }
+
+    fn strobe(nes: &Nes) {
+        let mut bus = nes.bus.write().unwrap();
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+    }
+
+    #[test]
+    fn controllers_shift_out_their_buttons() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        nes.set_buttons(
+            0,
+            Button::A.bit() | Button::Start.bit() | Button::Left.bit(),
+        );
+        nes.set_buttons(1, Button::B.bit());
+        strobe(&nes);
+
+        // the top bits are open bus, left over from the address
+        assert_eq!(
+            read_port(&nes, 0x4016, 10),
+            [0x41, 0x40, 0x40, 0x41, 0x40, 0x40, 0x41, 0x40, 0x41, 0x41]
+        );
+        assert_eq!(read_port(&nes, 0x4017, 3), [0x40, 0x41, 0x40]);
+        // peeking moves nothing on
+        let bus = nes.bus.read().unwrap();
+        assert_eq!(bus.read(0x4017, true), 0x40);
+        assert_eq!(bus.read(0x4017, true), 0x40);
+    }
+
+    #[test]
+    fn strobe_held_high_keeps_reading_a() {
+        let mut controller = StandardController::default();
+        controller.set_buttons(0, Button::A.bit());
+        controller.write(1);
+        assert_eq!([controller.read(), controller.read()], [1, 1]);
+        controller.set_buttons(0, Button::B.bit());
+        assert_eq!(controller.read(), 0);
+        controller.write(0);
+        assert_eq!([controller.read(), controller.read()], [0, 1]);
+    }
+
+    /// Reads `count` bits from each port, taken from bit `line`.
+    fn read_bits(nes: &Nes, line: u8, count: usize) -> [Vec<u8>; 2] {
+        [0x4016, 0x4017].map(|addr| {
+            read_port(nes, addr, count)
+                .iter()
+                .map(|data| data >> line & 0x01)
+                .collect()
+        })
+    }
+
+    fn plug_four_players(nes: &mut Nes, input: DefaultInput) {
+        nes.plug_input(input);
+        for (player, button) in [Button::A, Button::B, Button::Select, Button::Right]
+            .iter()
+            .enumerate()
+        {
+            nes.set_buttons(player, button.bit());
+        }
+        strobe(nes);
+    }
+
+    #[test]
+    fn four_score_adds_players_and_a_signature() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        plug_four_players(&mut nes, DefaultInput::FourScore);
+        let [port0, port1] = read_bits(&nes, 0, 25);
+        // player 1 and 3, then the signature
+        assert_eq!(port0[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(port0[8..16], [0, 0, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(port0[16..], [0, 0, 0, 1, 0, 0, 0, 0, 1]);
+        // player 2 and 4
+        assert_eq!(port1[..8], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(port1[8..16], [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(port1[16..], [0, 0, 1, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn hori_adapter_adds_players_on_d1() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        plug_four_players(&mut nes, DefaultInput::Hori);
+        // players 1 and 2 stay on the controllers
+        let [port0, port1] = read_bits(&nes, 0, 9);
+        assert_eq!(port0, [1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(port1, [0, 1, 0, 0, 0, 0, 0, 0, 1]);
+
+        strobe(&nes);
+        let [port0, port1] = read_bits(&nes, 1, 25);
+        // nothing in its own first jacks, then players 3 and 4
+        assert_eq!(
+            port0[..16],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(port0[16..], [0, 0, 1, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(
+            port1[..16],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(port1[16..], [0, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+
+    /// Always reads the same, to show devices can be swapped in.
+    struct Constant(u8);
+
+    impl InputDevice for Constant {
+        fn write(&mut self, _data: u8) {}
+
+        fn read(&mut self) -> u8 {
+            self.0
+        }
+
+        fn peek(&self) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn ports_take_other_devices() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        nes.plug(1, Box::new(Constant(0xFF)));
+        assert_eq!(read_port(&nes, 0x4017, 2), [0x5F, 0x5F]);
+        assert_eq!(read_port(&nes, 0x4016, 1), [0x40]);
+    }
+
+    /// Halts the CPU for a DMC fetch in the middle of an LDA $4016, after
+    /// the first of the eight reads.
+    fn dmc_during_read(region: Region) -> Vec<u8> {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.set_region(Some(region));
+        nes.set_buttons(0, Button::A.bit() | Button::Select.bit());
+        strobe(&nes);
+
+        let mut bits = read_port(&nes, 0x4016, 1);
+        nes.cpu.opcode = 0xAD;
+        nes.cpu.addr_abs = 0x4016;
+        nes.cpu.cycles = 2;
+        nes.dma.request_dmc(0xC000);
+        nes.clock();
+        bits.extend(read_port(&nes, 0x4016, 7));
+        bits.iter().map(|bit| bit & 0x01).collect()
+    }
+
+    #[test]
+    fn dmc_fetches_lose_a_bit_on_ntsc() {
+        assert_eq!(dmc_during_read(Region::Ntsc), [1, 1, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(dmc_during_read(Region::Pal), [1, 0, 1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn zapper_sees_what_was_just_drawn_under_it() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        nes.plug(1, Box::new(Zapper::new(nes.ppu.clone(), 2)));
+        nes.set_pointer(
+            1,
+            Pointer {
+                position: Some((100.0, 100.0)),
+                pressed: false,
+            },
+        );
+        let light_at = |scanline: u16, dot: u16| {
+            {
+                let mut ppu = nes.ppu.write().unwrap();
+                ppu.frame.fill(0x0F);
+                ppu.frame[102 * WIDTH + 100] = 0x30;
+                ppu.scanline = scanline;
+                ppu.dot = dot;
+            }
+            read_port(&nes, 0x4017, 1)[0]
+        };
+
+        assert_eq!(light_at(101, 0), 0x48);
+        assert_eq!(light_at(102, 101), 0x48);
+        // bit 3 goes low while the white pixel is lit
+        assert_eq!(light_at(102, 102), 0x40);
+        assert_eq!(light_at(120, 0), 0x40);
+        assert_eq!(light_at(130, 0), 0x48);
+
+        nes.set_pointer(
+            1,
+            Pointer {
+                position: None,
+                pressed: true,
+            },
+        );
+        assert_eq!(light_at(110, 0), 0x58);
+    }
+
+    #[test]
+    fn arkanoid_sends_its_knob_inverted() {
+        let pointer = Pointer {
+            position: Some((128.0, 50.0)),
+            pressed: true,
+        };
+        // halfway is 0x62 + 0x48 = 0xAA, which goes out as 0x55
+        let expected = [0, 1, 0, 1, 0, 1, 0, 1, 1];
+
+        let mut nes_vaus = Arkanoid::default();
+        nes_vaus.set_pointer(pointer);
+        nes_vaus.write(1);
+        nes_vaus.write(0);
+        let bits: Vec<u8> = (0..9).map(|_| nes_vaus.read()).collect();
+        assert!(bits.iter().all(|data| data & 0x10 != 0));
+        assert_eq!(
+            bits.iter().map(|data| data >> 3 & 1).collect::<Vec<_>>(),
+            expected
+        );
+
+        let mut famicom_vaus = FamicomArkanoid::default();
+        famicom_vaus.set_pointer(pointer);
+        famicom_vaus.write(1);
+        famicom_vaus.write(0);
+        assert_eq!(famicom_vaus.read(0), 0x02);
+        let bits: Vec<u8> = (0..9).map(|_| famicom_vaus.read(1) >> 1).collect();
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn power_pad_reads_out_on_two_lines() {
+        let mut pad = PowerPad::default();
+        let mut keys = [false; 12];
+        // buttons 1, 3 and 12
+        keys[0] = true;
+        keys[2] = true;
+        keys[11] = true;
+        pad.set_keys(&keys);
+        pad.write(1);
+        pad.write(0);
+        let reads: Vec<u8> = (0..9).map(|_| pad.read()).collect();
+        let d3: Vec<u8> = reads.iter().map(|data| data >> 3 & 1).collect();
+        let d4: Vec<u8> = reads.iter().map(|data| data >> 4 & 1).collect();
+        assert_eq!(d3, [0, 1, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(d4, [0, 1, 1, 0, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn family_trainer_is_scanned_by_row() {
+        let mut mat = FamilyTrainer::default();
+        let mut keys = [false; 12];
+        keys[1] = true;
+        keys[8] = true;
+        mat.set_keys(&keys);
+        mat.write(0x06);
+        assert_eq!(mat.read(1), 0x1A);
+        mat.write(0x05);
+        assert_eq!(mat.read(1), 0x1E);
+        mat.write(0x03);
+        assert_eq!(mat.read(1), 0x1C);
+        assert_eq!(mat.read(0), 0);
+    }
+
+    #[test]
+    fn family_keyboard_is_scanned_through_4016() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        nes.plug_expansion(Some(Box::new(FamilyKeyboard::new())));
+        let mut keys = vec![false; KEYBOARD_KEYS.len()];
+        for key in ["Return", "Stop", "K", "Space"] {
+            keys[KEYBOARD_KEYS.iter().position(|name| *name == key).unwrap()] = true;
+        }
+        nes.set_expansion_keys(&keys);
+
+        // reset to row 0, then read both columns of every row
+        let mut rows = Vec::new();
+        let mut bus = nes.bus.write().unwrap();
+        bus.write(0x4016, 0x05);
+        for _ in 0..10 {
+            bus.write(0x4016, 0x04);
+            let left = bus.read(0x4017, false) & 0x1E;
+            bus.write(0x4016, 0x06);
+            let right = bus.read(0x4017, false) & 0x1E;
+            rows.push((left, right));
+        }
+        assert_eq!(rows[0], (0x1A, 0x0E));
+        assert_eq!(rows[1], (0x1E, 0x1E));
+        assert_eq!(rows[2], (0x0E, 0x1E));
+        assert_eq!(rows[8], (0x1E, 0x1A));
+        // past the last row nothing is held
+        assert_eq!(rows[9], (0x1E, 0x1E));
+
+        // and with OUT2 low the keyboard lets go of the lines
+        bus.write(0x4016, 0x00);
+        assert_eq!(bus.read(0x4017, false) & 0x1E, 0);
+    }
+
+    #[test]
+    fn nes2_headers_pick_the_devices() {
+        assert_eq!(DefaultInput::from_nes2(0x08), Some(DefaultInput::Zapper));
+        assert_eq!(DefaultInput::from_nes2(0x03), Some(DefaultInput::Hori));
+        assert_eq!(
+            DefaultInput::from_nes2(0x23),
+            Some(DefaultInput::FamilyKeyboard)
+        );
+        assert_eq!(DefaultInput::from_nes2(0x00), None);
+        assert_eq!(DefaultInput::from_unif(0x03), Some(DefaultInput::Zapper));
+
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[7] |= 0x08;
+        image[15] = 0x0F;
+        nes.load_rom_bytes(&image).unwrap();
+        assert_eq!(nes.default_input, Some(DefaultInput::Arkanoid));
+        // loading leaves the controllers in
+        strobe(&nes);
+        assert_eq!(read_port(&nes, 0x4017, 1), [0x40]);
+        nes.plug_input(DefaultInput::Arkanoid);
+        strobe(&nes);
+        // the knob starts at the left, 0x62, whose top bit inverted is 1
+        assert_eq!(read_port(&nes, 0x4017, 1), [0x48]);
+
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        assert_eq!(nes.default_input, None);
+    }
+}
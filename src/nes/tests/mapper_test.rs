@@ -0,0 +1,114 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::cartridge::Cartridge;
+    use nesemu::rom_loader::{Rom, RomError};
+    use nesemu_core::{Mirroring, Read, Write};
+    use nesemu_ppu::ppu::PpuBus;
+
+    use crate::common::ines_image;
+
+    fn cartridge(mapper: u8, prg_banks: u8, chr_banks: u8) -> Cartridge {
+        let mut image = ines_image(prg_banks, chr_banks);
+        image[6] = mapper << 4;
+        // give each 4k of CHR its own number
+        for (i, byte) in image[0x10 + prg_banks as usize * 0x4000..]
+            .iter_mut()
+            .enumerate()
+        {
+            *byte = (i / 0x1000) as u8;
+        }
+        Cartridge::new(&Rom::build(&image).unwrap()).unwrap()
+    }
+
+    fn mmc1_write(cart: &mut Cartridge, addr: u16, value: u8) {
+        for bit in 0..5 {
+            cart.write(addr, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn uxrom_switches_the_low_bank() {
+        let mut cart = cartridge(2, 8, 0);
+        assert_eq!(cart.read(0x8000, false), 0);
+        assert_eq!(cart.read(0xC000, false), 7);
+        cart.write(0x8000, 3);
+        assert_eq!(cart.read(0x8000, false), 3);
+        assert_eq!(cart.read(0xC000, false), 7);
+
+        // no CHR rom means CHR ram
+        cart.ppu_write(0x0123, 0x44);
+        assert_eq!(cart.ppu_read(0x0123), 0x44);
+    }
+
+    #[test]
+    fn cnrom_switches_chr() {
+        let mut cart = cartridge(3, 2, 4);
+        assert_eq!(cart.ppu_read(0x0000), 0);
+        cart.write(0x8000, 2);
+        assert_eq!(cart.ppu_read(0x0000), 4);
+        assert_eq!(cart.ppu_read(0x1000), 5);
+    }
+
+    #[test]
+    fn axrom_selects_single_screen() {
+        let mut cart = cartridge(7, 8, 0);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreenLower);
+        cart.write(0x8000, 0x11);
+        assert_eq!(cart.read(0x8000, false), 2);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn mmc1_serial_registers() {
+        let mut cart = cartridge(1, 8, 4);
+        // power on: last bank fixed at $C000
+        assert_eq!(cart.read(0xC000, false), 7);
+
+        mmc1_write(&mut cart, 0xE000, 2);
+        assert_eq!(cart.read(0x8000, false), 2);
+
+        // 4k CHR mode, vertical mirroring
+        mmc1_write(&mut cart, 0x8000, 0x1E);
+        mmc1_write(&mut cart, 0xA000, 3);
+        mmc1_write(&mut cart, 0xC000, 1);
+        assert_eq!(cart.ppu_read(0x0000), 3);
+        assert_eq!(cart.ppu_read(0x1000), 1);
+        assert_eq!(cart.mirroring(), Mirroring::Vertical);
+
+        // a write with bit 7 set resets the shift register mid-load
+        cart.write(0xE000, 1);
+        cart.write(0xE000, 0x80);
+        mmc1_write(&mut cart, 0xE000, 4);
+        assert_eq!(cart.read(0x8000, false), 4);
+
+        // prg ram
+        cart.write(0x6000, 0x99);
+        assert_eq!(cart.read(0x6000, false), 0x99);
+    }
+
+    #[test]
+    fn mmc1_surom_selects_the_prg_half() {
+        let mut cart = cartridge(1, 32, 0);
+        assert_eq!(cart.read(0xC000, false), 15);
+        mmc1_write(&mut cart, 0xE000, 3);
+        assert_eq!(cart.read(0x8000, false), 3);
+
+        // the first CHR register moves both windows to the upper 256k
+        mmc1_write(&mut cart, 0xA000, 0x10);
+        assert_eq!(cart.read(0x8000, false), 19);
+        assert_eq!(cart.read(0xC000, false), 31);
+    }
+
+    #[test]
+    fn unknown_mappers_are_refused() {
+        let mut image = ines_image(1, 1);
+        image[6] = 0xF0;
+        image[7] = 0xF0;
+        assert!(matches!(
+            Cartridge::new(&Rom::build(&image).unwrap()),
+            Err(RomError::UnsupportedMapper(255))
+        ));
+    }
+}
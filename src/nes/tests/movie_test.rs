@@ -0,0 +1,298 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::input::{Button, DefaultInput};
+    use nesemu::movie::{Movie, MovieError, MovieFormat, MovieFrame, COMMAND_POWER, COMMAND_RESET};
+    use nesemu::state::StateError;
+    use nesemu::Nes;
+    use nesemu_core::{Read, Region, Write};
+
+    use crate::common::{ines_image, setup};
+
+    const FM2: &str = "version 3\n\
+        emuVersion 22020\n\
+        rerecordCount 7\n\
+        palFlag 0\n\
+        romFilename Game\n\
+        romChecksum base64:AAECAwQFBgcICQoLDA0ODw==\n\
+        guid 01234567-89AB-CDEF-0123-456789ABCDEF\n\
+        fourscore 0\n\
+        port0 1\n\
+        port1 1\n\
+        port2 0\n\
+        comment author someone\n\
+        savestate base64:AQID\n\
+        |0|........|........||\n\
+        |1|R......A|.L..T...||\n\
+        |2|...U....|........||\n";
+
+    fn load_nops(nes: &mut Nes) {
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+    }
+
+    /// What the game would read from the first controller now.
+    fn pad(nes: &Nes) -> u8 {
+        let mut bus = nes.bus.write().unwrap();
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        (0..8).fold(0, |buttons, bit| {
+            buttons | (bus.read(0x4016, false) & 1) << bit
+        })
+    }
+
+    #[test]
+    fn fm2_frames_and_header_are_read() {
+        let movie = Movie::parse(FM2.as_bytes()).unwrap();
+        assert_eq!(movie.rerecord_count, 7);
+        assert_eq!(movie.rom_filename, "Game");
+        assert_eq!(movie.comments, ["author someone"]);
+        assert_eq!(movie.savestate, Some(vec![1, 2, 3]));
+        assert_eq!(movie.frames.len(), 3);
+        assert_eq!(
+            movie.frames[1],
+            MovieFrame {
+                commands: COMMAND_RESET,
+                buttons: [
+                    Button::Right.bit() | Button::A.bit(),
+                    Button::Left.bit() | Button::Start.bit(),
+                    0,
+                    0
+                ],
+            }
+        );
+        assert_eq!(movie.frames[2].commands, COMMAND_POWER);
+        assert_eq!(movie.frames[2].buttons[0], Button::Up.bit());
+
+        // and come back out the same
+        let text = String::from_utf8(movie.to_bytes(MovieFormat::Fm2)).unwrap();
+        assert!(text.contains("|1|R......A|.L..T...||\n"));
+        assert!(text.contains("savestate base64:AQID\n"));
+        assert_eq!(Movie::parse(text.as_bytes()).unwrap(), movie);
+    }
+
+    #[test]
+    fn fm2_with_other_devices_is_refused() {
+        let zapper = FM2.replace("port1 1", "port1 2");
+        assert!(matches!(
+            Movie::parse(zapper.as_bytes()),
+            Err(MovieError::Unsupported(_))
+        ));
+        let bad = FM2.replace("|2|...U....|", "|2|...U...|");
+        assert!(matches!(
+            Movie::parse(bad.as_bytes()),
+            Err(MovieError::BadLine(16))
+        ));
+        assert!(matches!(
+            Movie::parse(b"not a movie"),
+            Err(MovieError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn native_format_keeps_everything() {
+        let mut movie = Movie::parse(FM2.as_bytes()).unwrap();
+        movie.frames[0].buttons[3] = Button::B.bit();
+        let bytes = movie.to_bytes(MovieFormat::Native);
+        let read = Movie::parse(&bytes).unwrap();
+        // players 3 and 4 need the Four Score
+        assert!(read.four_score);
+        assert_eq!(read.frames, movie.frames);
+        assert_eq!(read.guid, movie.guid);
+        assert_eq!(read.savestate, movie.savestate);
+        assert!(matches!(
+            Movie::parse(&bytes[..bytes.len() - 1]),
+            Err(MovieError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn playback_repeats_the_recording() {
+        let mut nes = setup();
+        load_nops(&mut nes);
+        nes.record_movie(None).unwrap();
+        let mut pads = Vec::new();
+        for frame in 0..12 {
+            let buttons = match frame {
+                3..=6 => Button::A.bit(),
+                9 => Button::Start.bit() | Button::Left.bit(),
+                _ => 0,
+            };
+            nes.set_buttons(0, buttons);
+            if frame == 8 {
+                nes.queue_commands(COMMAND_POWER);
+            }
+            nes.run_frame();
+            pads.push(pad(&nes));
+        }
+        let movie = nes.stop_movie().unwrap();
+        let cycles = nes.cycles;
+        assert_eq!(movie.frames.len(), 12);
+        assert_eq!(movie.frames[8].commands, COMMAND_POWER);
+        assert_eq!(pads[4], Button::A.bit());
+
+        // whatever is pressed meanwhile is ignored
+        nes.play_movie(movie).unwrap();
+        let mut played = Vec::new();
+        for _ in 0..12 {
+            nes.set_buttons(0, Button::B.bit());
+            nes.run_frame();
+            played.push(pad(&nes));
+        }
+        assert!(nes.movie.as_ref().unwrap().finished());
+        assert_eq!(played, pads);
+        assert_eq!(nes.cycles, cycles);
+    }
+
+    #[test]
+    fn loading_a_state_counts_a_rerecord() {
+        let mut nes = setup();
+        load_nops(&mut nes);
+        let outside = nes.save_state().unwrap();
+        nes.record_movie(None).unwrap();
+        for _ in 0..3 {
+            nes.run_frame();
+        }
+        let state = nes.save_state().unwrap();
+        nes.set_buttons(0, Button::A.bit());
+        nes.run_frame();
+        nes.run_frame();
+
+        nes.load_state(&state).unwrap();
+        let session = nes.movie.as_ref().unwrap();
+        assert_eq!(session.frame, 3);
+        assert_eq!(session.movie.frames.len(), 3);
+        assert_eq!(session.movie.rerecord_count, 1);
+        // a state from before recording has no frame to go back to
+        assert_eq!(nes.load_state(&outside), Err(StateError::NotInMovie));
+
+        nes.set_buttons(0, Button::B.bit());
+        nes.run_frame();
+        let movie = nes.stop_movie().unwrap();
+        assert_eq!(movie.frames.len(), 4);
+        assert_eq!(movie.frames[3].buttons[0], Button::B.bit());
+        assert_eq!(movie.rerecord_count, 1);
+    }
+
+    #[test]
+    fn movies_start_from_their_save_state() {
+        let mut nes = setup();
+        load_nops(&mut nes);
+        for _ in 0..5 {
+            nes.run_frame();
+        }
+        nes.bus.write().unwrap().write(0x0010, 0x42);
+        let state = nes.save_state().unwrap();
+        nes.record_movie(Some(state.clone())).unwrap();
+        let start = nes.cycles;
+        let mut pads = Vec::new();
+        for frame in 0..3 {
+            nes.set_buttons(0, if frame == 1 { Button::A.bit() } else { 0 });
+            nes.run_frame();
+            pads.push(pad(&nes));
+        }
+        let movie = nes.stop_movie().unwrap();
+        let cycles = nes.cycles;
+        assert_eq!(movie.savestate, Some(state));
+        assert!(start > 0);
+
+        // played back from the state, not from power on
+        let movie = Movie::parse(&movie.to_bytes(MovieFormat::Fm2)).unwrap();
+        nes.power_cycle().unwrap();
+        nes.play_movie(movie).unwrap();
+        assert_eq!(nes.cycles, start);
+        assert_eq!(nes.bus.write().unwrap().read(0x0010, false), 0x42);
+        let mut played = Vec::new();
+        for _ in 0..3 {
+            nes.run_frame();
+            played.push(pad(&nes));
+        }
+        assert_eq!(played, pads);
+        assert_eq!(nes.cycles, cycles);
+    }
+
+    #[test]
+    fn playback_follows_the_movie() {
+        let mut nes = setup();
+        load_nops(&mut nes);
+        let with_state = Movie::parse(FM2.as_bytes()).unwrap();
+        // FCEUX's own states don't load here
+        assert!(matches!(
+            nes.play_movie(with_state),
+            Err(MovieError::State(StateError::BadState))
+        ));
+        assert!(nes.movie.is_none());
+
+        let fm2 = FM2
+            .replace("palFlag 0", "palFlag 1")
+            .replace("savestate base64:AQID\n", "");
+        let mut movie = Movie::parse(fm2.as_bytes()).unwrap();
+        movie.frames[0].buttons[2] = Button::A.bit();
+        nes.play_movie(movie).unwrap();
+        assert_eq!(nes.region(), Region::Pal);
+        nes.run_frame();
+        // player 3 comes after player 1 on the Four Score
+        let mut bus = nes.bus.write().unwrap();
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        let bits = (0..24)
+            .map(|_| bus.read(0x4016, false) & 1)
+            .collect::<Vec<u8>>();
+        assert_eq!(bits[8..16], [1, 0, 0, 0, 0, 0, 0, 0]);
+        // and the signature says it is a Four Score
+        assert_eq!(bits[16..24], [0, 0, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn only_controllers_are_recorded() {
+        let mut nes = setup();
+        load_nops(&mut nes);
+        nes.plug_input(DefaultInput::Zapper);
+        assert!(matches!(
+            nes.record_movie(None),
+            Err(MovieError::Unsupported(_))
+        ));
+        nes.plug_input(DefaultInput::FamilyKeyboard);
+        assert!(nes.record_movie(None).is_err());
+
+        nes.plug_input(DefaultInput::FourScore);
+        nes.record_movie(None).unwrap();
+        nes.run_frame();
+        let text = String::from_utf8(nes.stop_movie().unwrap().to_bytes(MovieFormat::Fm2)).unwrap();
+        assert!(text.contains("fourscore 1\n"));
+        assert!(text.contains("|0|........|........|........|........||\n"));
+    }
+
+    #[test]
+    fn hori_games_record_and_play_as_four_score() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        image[7] = 0x08;
+        image[15] = 0x03;
+        nes.load_rom_bytes(&image).unwrap();
+        nes.plug_input(nes.default_input.unwrap());
+        nes.record_movie(None).unwrap();
+        nes.set_buttons(3, Button::Up.bit());
+        nes.run_frame();
+        let movie = nes.stop_movie().unwrap();
+        assert!(movie.four_score);
+        assert_eq!(movie.frames[0].buttons[3], Button::Up.bit());
+
+        // played back on the Hori adapter, player 4 on D1 of $4017
+        nes.plug_input(DefaultInput::Controllers);
+        nes.set_buttons(3, 0);
+        nes.play_movie(movie).unwrap();
+        nes.run_frame();
+        let mut bus = nes.bus.write().unwrap();
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        let bits = (0..16)
+            .map(|_| bus.read(0x4017, false) >> 1 & 1)
+            .collect::<Vec<u8>>();
+        assert_eq!(bits[8..], [0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,216 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nesemu::nsf::{Nsf, DRIVER_ADDR};
+    use nesemu_core::{Read, Write};
+
+    use crate::common::setup;
+
+    fn nsf_image(banks: [u8; 8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"NESM\x1A".to_vec();
+        bytes.extend_from_slice(&[1, 3, 2]);
+        bytes.extend_from_slice(&0x8000u16.to_le_bytes());
+        bytes.extend_from_slice(&0x8003u16.to_le_bytes());
+        bytes.extend_from_slice(&0x8006u16.to_le_bytes());
+        for field in ["Song", "Composer", "1987 Someone"] {
+            let mut field = field.as_bytes().to_vec();
+            field.resize(32, 0);
+            bytes.extend(field);
+        }
+        bytes.extend_from_slice(&16_639u16.to_le_bytes());
+        bytes.extend_from_slice(&banks);
+        bytes.extend_from_slice(&19_997u16.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0x04, 0, 0, 0, 0]);
+        assert_eq!(bytes.len(), 0x80);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn nsfe_chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = (data.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn parses_the_header() {
+        let nsf = Nsf::parse(&nsf_image([0; 8], &[0xEA; 16])).unwrap();
+        assert_eq!(nsf.total_songs, 3);
+        assert_eq!(nsf.starting_song, 1);
+        assert_eq!(nsf.init_addr, 0x8003);
+        assert_eq!(nsf.play_addr, 0x8006);
+        assert_eq!(nsf.title, "Song");
+        assert_eq!(nsf.artist, "Composer");
+        assert!(nsf.expansion.fds());
+        assert_eq!(nsf.expansion.names(), vec!["FDS"]);
+        assert!(!nsf.is_bankswitched());
+        // ~60Hz on NTSC
+        assert_eq!(nsf.play_period(), 29_780);
+    }
+
+    #[test]
+    fn parses_nsfe_metadata() {
+        let info = vec![0x00, 0x80, 0x00, 0x80, 0x03, 0x80, 0x00, 0x00, 2, 0];
+        let mut bytes = b"NSFE".to_vec();
+        bytes.extend(nsfe_chunk(b"INFO", &info));
+        bytes.extend(nsfe_chunk(b"DATA", &[0x60]));
+        bytes.extend(nsfe_chunk(b"auth", b"Game\0Artist\0(c)\0Ripper\0"));
+        bytes.extend(nsfe_chunk(b"tlbl", b"Intro\0Boss\0"));
+        let mut times = 90_000i32.to_le_bytes().to_vec();
+        times.extend_from_slice(&(-1i32).to_le_bytes());
+        bytes.extend(nsfe_chunk(b"time", &times));
+        bytes.extend(nsfe_chunk(b"fade", &2_000i32.to_le_bytes()));
+        bytes.extend(nsfe_chunk(b"NEND", &[]));
+
+        let nsf = Nsf::parse(&bytes).unwrap();
+        assert_eq!(nsf.total_songs, 2);
+        assert_eq!(nsf.title, "Game");
+        assert_eq!(nsf.ripper.as_deref(), Some("Ripper"));
+        assert_eq!(nsf.track_name(1), Some("Boss"));
+        assert_eq!(nsf.track_length(0), Duration::from_secs(90));
+        assert_eq!(nsf.track_fade(0), Duration::from_secs(2));
+        assert_eq!(nsf.track_length(1), nesemu::nsf::DEFAULT_TRACK_LENGTH);
+
+        let mut unknown = b"NSFE".to_vec();
+        unknown.extend(nsfe_chunk(b"INFO", &info));
+        unknown.extend(nsfe_chunk(b"DATA", &[0x60]));
+        unknown.extend(nsfe_chunk(b"WHAT", &[]));
+        assert!(Nsf::parse(&unknown).is_err());
+    }
+
+    #[test]
+    fn installs_the_driver_and_initial_track() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&nsf_image([0; 8], &[0xEA; 16])).unwrap();
+        assert_eq!(nes.cpu.pgrm_ctr, DRIVER_ADDR);
+        assert_eq!(nes.cpu.acc_reg, 1);
+        assert_eq!(nes.cpu.x_reg, 0);
+
+        let bus = nes.bus.read().unwrap();
+        let driver = (0..6)
+            .map(|i| bus.read(DRIVER_ADDR + i, false))
+            .collect::<Vec<u8>>();
+        assert_eq!(driver, vec![0x20, 0x03, 0x80, 0x4C, 0x03, 0x41]);
+        assert_eq!(bus.read(0x8000, false), 0xEA);
+    }
+
+    #[test]
+    fn switches_4k_banks() {
+        let mut data = vec![0; 0x3000];
+        data[0x1000] = 0x11;
+        data[0x2000] = 0x22;
+        let mut nes = setup();
+        nes.load_rom_bytes(&nsf_image([0, 1, 2, 0, 0, 0, 0, 0], &data))
+            .unwrap();
+
+        let mut bus = nes.bus.write().unwrap();
+        assert_eq!(bus.read(0x9000, false), 0x11);
+        assert_eq!(bus.read(0xA000, false), 0x22);
+        bus.write(0x5FF9, 2);
+        assert_eq!(bus.read(0x9000, false), 0x22);
+        // the FDS flag is set, so $8000-$DFFF is ram
+        bus.write(0x8000, 0x77);
+        assert_eq!(bus.read(0x8000, false), 0x77);
+    }
+
+    #[test]
+    fn fds_rips_get_the_fds_channel() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&nsf_image([0; 8], &[0xEA; 16])).unwrap();
+        {
+            let mut bus = nes.bus.write().unwrap();
+            // wave ram only takes writes while $4089 bit 7 is set
+            bus.write(0x4089, 0x80);
+            bus.write(0x4040, 0x3F);
+            assert_eq!(bus.read(0x4040, false), 0x7F);
+        }
+        let channels = nes.audio_channels();
+        assert_eq!(channels.last().unwrap().name, "FDS");
+
+        let mut header = nsf_image([0; 8], &[0xEA; 16]);
+        header[0x7B] = 0x05;
+        let nsf = Nsf::parse(&header).unwrap();
+        assert_eq!(nsf.expansion.names(), ["VRC6", "FDS"]);
+        assert_eq!(nsf.expansion.unsupported_names(), ["VRC6"]);
+    }
+
+    #[test]
+    fn calls_play_from_the_idle_loop() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&nsf_image([0; 8], &[0xEA; 16])).unwrap();
+        let period = nes.nsf.as_ref().unwrap().nsf.play_period();
+
+        // pretend INIT has returned to the idle loop
+        nes.cpu.pgrm_ctr = DRIVER_ADDR + 3;
+        let player = nes.nsf.as_mut().unwrap();
+        for _ in 0..period - 1 {
+            player.clock(&mut nes.cpu);
+            assert_eq!(nes.cpu.pgrm_ctr, DRIVER_ADDR + 3);
+        }
+        player.clock(&mut nes.cpu);
+        assert_eq!(nes.cpu.pgrm_ctr, DRIVER_ADDR + 6);
+
+        nes.select_nsf_track(2);
+        assert_eq!(nes.cpu.acc_reg, 2);
+        assert_eq!(nes.nsf.as_ref().unwrap().elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn nsfe_tracks_fade_out() {
+        let info = vec![0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x00, 1, 0];
+        let mut bytes = b"NSFE".to_vec();
+        bytes.extend(nsfe_chunk(b"INFO", &info));
+        bytes.extend(nsfe_chunk(b"DATA", &[0xEA; 0x8000]));
+        bytes.extend(nsfe_chunk(b"time", &20i32.to_le_bytes()));
+        bytes.extend(nsfe_chunk(b"fade", &300i32.to_le_bytes()));
+        bytes.extend(nsfe_chunk(b"NEND", &[]));
+        let mut nes = setup();
+        nes.load_rom_bytes(&bytes).unwrap();
+        nes.mixer.rate_control = false;
+        let sink = nes.mixer.sink();
+        // stay out of the driver, running through the NOPs instead
+        nes.cpu.pgrm_ctr = 0x8000;
+
+        // a square wave on the first pulse, at full volume
+        for (addr, data) in [
+            (0x4015, 0x01),
+            (0x4000, 0xBF),
+            (0x4002, 0xFD),
+            (0x4003, 0x00),
+        ] {
+            nes.bus.write().unwrap().write(addr, data);
+        }
+        let mut out = vec![0.0; 8192];
+        let mut loudness = || {
+            // only NOPs and BRKs run, so keep the stack from running out
+            nes.cpu.stk_ptr = 0xFD;
+            nes.run_frame();
+            let count = sink.drain(&mut out);
+            out[..count]
+                .iter()
+                .fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        let playing = loudness();
+        for _ in 0..8 {
+            loudness();
+        }
+        // halfway through the fade
+        let fading = loudness();
+        for _ in 0..10 {
+            loudness();
+        }
+        let faded = loudness();
+        assert!(playing > 0.1, "{}", playing);
+        assert!(
+            fading < playing * 0.7 && fading > playing * 0.3,
+            "{} {}",
+            playing,
+            fading
+        );
+        assert!(faded < playing * 0.05, "{} {}", playing, faded);
+    }
+}
@@ -0,0 +1,219 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use nesemu::patch::{self, PatchError};
+    use nesemu_core::Read;
+
+    use crate::common::{ines_image, setup};
+
+    fn varint(out: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let x = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(0x80 | x);
+                break;
+            }
+            out.push(x);
+            value -= 1;
+        }
+    }
+
+    fn with_crcs(mut body: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        body.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        body.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let crc = crc32fast::hash(&body);
+        body.extend_from_slice(&crc.to_le_bytes());
+        body
+    }
+
+    #[test]
+    fn ips_records_rle_and_truncation() {
+        let source = vec![0u8; 16];
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        ips.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0x77]);
+        ips.extend_from_slice(b"EOF");
+        ips.extend_from_slice(&[0x00, 0x00, 0x0A]);
+
+        let out = patch::apply(&source, &ips).unwrap();
+        assert_eq!(out, vec![0, 0, 0xAA, 0xBB, 0, 0, 0, 0, 0x77, 0x77]);
+    }
+
+    #[test]
+    fn create_ips_round_trips() {
+        let source: Vec<u8> = (0..=255u8).cycle().take(0x1000).collect();
+        let mut grown = source.clone();
+        grown[0x10] = 0xFF;
+        grown[0x200..0x280].fill(0x55);
+        grown.extend_from_slice(&[1, 2, 3]);
+        let ips = patch::create_ips(&source, &grown).unwrap();
+        assert_eq!(patch::apply(&source, &ips).unwrap(), grown);
+
+        let shrunk = source[..0x800].to_vec();
+        let ips = patch::create_ips(&source, &shrunk).unwrap();
+        assert_eq!(patch::apply(&source, &ips).unwrap(), shrunk);
+    }
+
+    #[test]
+    fn ups_applies_both_ways() {
+        let source = vec![1u8, 2, 3, 4, 5, 6];
+        let target = vec![1u8, 9, 3, 4, 5, 6, 7];
+        let mut ups = b"UPS1".to_vec();
+        varint(&mut ups, source.len());
+        varint(&mut ups, target.len());
+        varint(&mut ups, 1);
+        ups.extend_from_slice(&[2 ^ 9, 0x00]);
+        varint(&mut ups, 3);
+        ups.extend_from_slice(&[7, 0x00]);
+        let ups = with_crcs(ups, &source, &target);
+
+        assert_eq!(patch::apply(&source, &ups).unwrap(), target);
+        assert_eq!(patch::apply(&target, &ups).unwrap(), source);
+        assert_eq!(
+            patch::apply(&[0u8; 6], &ups),
+            Err(PatchError::SourceChecksum)
+        );
+    }
+
+    #[test]
+    fn bps_copies_from_source_and_target() {
+        let source = b"hello world".to_vec();
+        let target = b"world hello!!!!".to_vec();
+        let mut bps = b"BPS1".to_vec();
+        varint(&mut bps, source.len());
+        varint(&mut bps, target.len());
+        varint(&mut bps, 0);
+        // SourceCopy "world" from offset 6
+        varint(&mut bps, ((5 - 1) << 2) | 2);
+        varint(&mut bps, 6 << 1);
+        // TargetRead " "
+        varint(&mut bps, 1);
+        bps.push(b' ');
+        // SourceCopy "hello" from offset 0, which is 11 bytes back
+        varint(&mut bps, ((5 - 1) << 2) | 2);
+        varint(&mut bps, (11 << 1) | 1);
+        // TargetRead "!" then TargetCopy it three more times
+        varint(&mut bps, 1);
+        bps.push(b'!');
+        varint(&mut bps, ((3 - 1) << 2) | 3);
+        varint(&mut bps, 11 << 1);
+        let bps = with_crcs(bps, &source, &target);
+
+        assert_eq!(patch::apply(&source, &bps).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_sizes_are_not_trusted() {
+        let source = b"hello".to_vec();
+        let bps_for = |target_size: usize, copy: usize| {
+            let mut bps = b"BPS1".to_vec();
+            varint(&mut bps, source.len());
+            varint(&mut bps, target_size);
+            varint(&mut bps, 0);
+            varint(&mut bps, 1);
+            bps.push(b'!');
+            varint(&mut bps, ((copy - 1) << 2) | 3);
+            varint(&mut bps, 1);
+            with_crcs(bps, &source, b"!!!!")
+        };
+        // a huge size in the header allocates nothing up front
+        assert_eq!(
+            patch::apply(&source, &bps_for(1 << 40, 3)),
+            Err(PatchError::TargetChecksum)
+        );
+        // and nothing may write past it
+        assert_eq!(
+            patch::apply(&source, &bps_for(4, 1 << 30)),
+            Err(PatchError::TooLarge)
+        );
+        assert_eq!(patch::apply(&source, &bps_for(4, 3)).unwrap(), b"!!!!");
+    }
+
+    #[test]
+    fn overflowing_varints_are_refused() {
+        let source = b"hello".to_vec();
+        // a UPS offset that runs pos off the end of usize
+        let mut ups = b"UPS1".to_vec();
+        varint(&mut ups, source.len());
+        varint(&mut ups, source.len());
+        varint(&mut ups, usize::MAX);
+        ups.push(0);
+        let ups = with_crcs(ups, &source, &source);
+        assert_eq!(patch::apply(&source, &ups), Err(PatchError::TooLarge));
+
+        let bps_with = |actions: &dyn Fn(&mut Vec<u8>), metadata: usize| {
+            let mut bps = b"BPS1".to_vec();
+            varint(&mut bps, source.len());
+            varint(&mut bps, 2);
+            varint(&mut bps, metadata);
+            actions(&mut bps);
+            with_crcs(bps, &source, b"oo")
+        };
+        // metadata longer than memory
+        let bps = bps_with(&|_| {}, usize::MAX);
+        assert_eq!(patch::apply(&source, &bps), Err(PatchError::Truncated));
+        // a SourceCopy past the end of the source, then one far past that
+        let far = bps_with(
+            &|bps| {
+                varint(bps, 2);
+                varint(bps, 4 << 1);
+                varint(bps, 2);
+                varint(bps, (isize::MAX as usize) << 1);
+            },
+            0,
+        );
+        assert_eq!(patch::apply(&source, &far), Err(PatchError::Truncated));
+        // and a TargetCopy from before the start
+        let before = bps_with(
+            &|bps| {
+                varint(bps, 3);
+                varint(bps, (isize::MAX as usize) << 1 | 1);
+            },
+            0,
+        );
+        assert_eq!(patch::apply(&source, &before), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn create_bps_round_trips_and_is_checked() {
+        let source = ines_image(1, 1);
+        let mut target = source.clone();
+        target[0x4010] = 0x12;
+        target.truncate(0x5000);
+        let mut bps = patch::create_bps(&source, &target);
+        assert_eq!(patch::apply(&source, &bps).unwrap(), target);
+
+        assert_eq!(
+            patch::apply(&source[1..], &bps),
+            Err(PatchError::SourceSize)
+        );
+        let len = bps.len();
+        bps[len - 20] ^= 1;
+        assert_eq!(patch::apply(&source, &bps), Err(PatchError::PatchChecksum));
+    }
+
+    #[test]
+    fn load_rom_applies_a_patch_beside_the_rom() {
+        let dir = std::env::temp_dir().join("nesemu_patch_test");
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.nes");
+        let image = ines_image(1, 0);
+        let mut patched = image.clone();
+        patched[0x10] = 0x99;
+        fs::write(&rom_path, &image).unwrap();
+        fs::write(
+            dir.join("game.ips"),
+            patch::create_ips(&image, &patched).unwrap(),
+        )
+        .unwrap();
+
+        let mut nes = setup();
+        nes.load_rom(rom_path.to_str().unwrap()).unwrap();
+        assert_eq!(nes.bus.read().unwrap().read(0x8000, false), 0x99);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu_core::Write;
+
+    use crate::common::{ines_image, setup};
+
+    #[test]
+    fn cpu_reaches_the_ppu_through_its_mirrors() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        {
+            let mut bus = nes.bus.write().unwrap();
+            bus.write(0x2006, 0x21);
+            // $3FFE is a mirror of $2006
+            bus.write(0x3FFE, 0x00);
+            bus.write(0x2007, 0x42);
+        }
+        assert_eq!(nes.ppu.read().unwrap().read(0x2100), 0x42);
+        // pattern tables come from the cartridge
+        assert_eq!(nes.ppu.read().unwrap().read(0x0000), 0xCC);
+    }
+
+    #[test]
+    fn vblank_raises_an_nmi() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        // a bank of NOPs with the NMI vector pointing into it
+        image[0x10..0x4010].fill(0xEA);
+        image[0x400A] = 0x00;
+        image[0x400B] = 0xC0;
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        nes.bus.write().unwrap().write(0x2000, 0x80);
+        nes.run_frame();
+        assert_eq!(nes.ppu.read().unwrap().frame_count, 1);
+        // pushed PC and status
+        assert_eq!(nes.cpu.stk_ptr, 0xFD - 3);
+        assert_eq!(nes.cpu.get_flag_data().I, 1);
+    }
+}
@@ -0,0 +1,78 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu_core::Region;
+
+    use nesemu::rom_db::{rom_crc, DatabaseError, RomDatabase};
+    use nesemu::rom_loader::Rom;
+
+    use crate::common::{ines_image, setup};
+
+    fn nes2_image(region: u8) -> Vec<u8> {
+        let mut image = ines_image(1, 1);
+        image[7] = 0x08;
+        image[12] = region;
+        image
+    }
+
+    #[test]
+    fn region_comes_from_the_header_unless_forced() {
+        let mut nes = setup();
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        assert_eq!(nes.region(), Region::Ntsc);
+
+        nes.load_rom_bytes(&nes2_image(1)).unwrap();
+        assert_eq!(nes.region(), Region::Pal);
+        assert_eq!(nes.ppu.read().unwrap().region, Region::Pal);
+        nes.load_rom_bytes(&nes2_image(3)).unwrap();
+        assert_eq!(nes.region(), Region::Dendy);
+
+        nes.set_region(Some(Region::Ntsc));
+        assert_eq!(nes.ppu.read().unwrap().region, Region::Ntsc);
+        nes.load_rom_bytes(&nes2_image(1)).unwrap();
+        assert_eq!(nes.region(), Region::Ntsc);
+        nes.set_region(None);
+        assert_eq!(nes.region(), Region::Pal);
+    }
+
+    #[test]
+    fn ines1_regions_come_from_the_database() {
+        let image = ines_image(1, 1);
+        let crc = rom_crc(&Rom::build(&image).unwrap());
+        let text = format!("# a comment\n\n{:08X} pal # Europe\n", crc);
+        let mut nes = setup();
+        nes.rom_database = Some(RomDatabase::parse(&text).unwrap());
+        nes.load_rom_bytes(&image).unwrap();
+        assert_eq!(nes.region(), Region::Pal);
+
+        // a NES 2.0 header is trusted over the database
+        nes.rom_database = Some(RomDatabase::parse(&format!("{:08x} pal", crc)).unwrap());
+        nes.load_rom_bytes(&nes2_image(0)).unwrap();
+        assert_eq!(nes.region(), Region::Ntsc);
+
+        assert!(matches!(
+            RomDatabase::parse("12345678 pal\nnot a crc"),
+            Err(DatabaseError::BadLine(2))
+        ));
+    }
+
+    #[test]
+    fn pal_runs_sixteen_dots_every_five_cycles() {
+        let mut nes = setup();
+        let mut image = nes2_image(1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        for _ in 0..5 {
+            nes.clock();
+        }
+        assert_eq!(nes.ppu.read().unwrap().dot, 16);
+
+        nes.set_region(Some(Region::Dendy));
+        for _ in 0..5 {
+            nes.clock();
+        }
+        assert_eq!(nes.ppu.read().unwrap().dot, 31);
+    }
+}
@@ -0,0 +1,103 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write as ioWrite};
+
+    use nesemu::archive;
+    use nesemu::rom_loader::{Rom, RomError};
+    use nesemu_core::Read;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use crate::common::{ines_image, setup};
+
+    fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn build_splits_prg_and_chr() {
+        let rom = Rom::build(&ines_image(2, 1)).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!(rom.chr_rom.len(), 0x2000);
+        assert_eq!(rom.prg_rom[0x4000], 1);
+        assert!(rom.trainer.is_none());
+    }
+
+    #[test]
+    fn build_rejects_bad_images() {
+        assert!(matches!(Rom::build(b"NOPE"), Err(RomError::Truncated)));
+        assert!(matches!(Rom::build(&[0u8; 0x10]), Err(RomError::BadHeader)));
+        let mut short = ines_image(1, 0);
+        short.truncate(0x100);
+        assert!(matches!(Rom::build(&short), Err(RomError::Truncated)));
+    }
+
+    #[test]
+    fn load_rom_bytes_mirrors_a_single_bank() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 0);
+        image[0x10] = 0xAB;
+        nes.load_rom_bytes(&image).unwrap();
+        assert_eq!(nes.bus.read().unwrap().read(0x8000, false), 0xAB);
+        assert_eq!(nes.bus.read().unwrap().read(0xC000, false), 0xAB);
+    }
+
+    #[test]
+    fn failed_loads_keep_the_current_rom() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 0);
+        image[0x10] = 0xAB;
+        nes.load_rom_bytes(&image).unwrap();
+        let mut unsupported = ines_image(1, 0);
+        unsupported[6] = 0xF0;
+        unsupported[7] = 0xF0;
+        assert!(nes.load_rom_bytes(&unsupported).is_err());
+        assert!(nes.load_rom_bytes(b"NES\x1A").is_err());
+
+        nes.power_cycle().unwrap();
+        assert_eq!(nes.bus.read().unwrap().read(0x8000, false), 0xAB);
+    }
+
+    #[test]
+    fn load_rom_bytes_opens_zips() {
+        let mut nes = setup();
+        let zipped = zip_of(&[("readme.txt", b"hi"), ("game.nes", &ines_image(2, 0))]);
+        assert_eq!(archive::rom_entries(&zipped).unwrap(), vec!["game.nes"]);
+        nes.load_rom_bytes(&zipped).unwrap();
+        assert_eq!(nes.bus.read().unwrap().read(0x8000, false), 0);
+        assert_eq!(nes.bus.read().unwrap().read(0xC000, false), 1);
+    }
+
+    #[test]
+    fn load_rom_from_archive_picks_the_named_entry() {
+        let mut nes = setup();
+        let mut second = ines_image(1, 0);
+        second[0x10] = 0x42;
+        let zipped = zip_of(&[("a.nes", &ines_image(1, 0)), ("b.NES", &second)]);
+        nes.load_rom_from_archive(&zipped, "b.NES").unwrap();
+        assert_eq!(nes.bus.read().unwrap().read(0x8000, false), 0x42);
+        assert!(matches!(
+            nes.load_rom_from_archive(&zipped, "c.nes"),
+            Err(RomError::NoRomInArchive)
+        ));
+    }
+
+    #[test]
+    fn rom_entries_keep_the_stored_order() {
+        let names = ["z.nes", "m.nsf", "a.fds", "k.unf", "b.nes", "y.nes"];
+        let image = ines_image(1, 0);
+        let entries = names
+            .iter()
+            .map(|name| (*name, image.as_slice()))
+            .collect::<Vec<(&str, &[u8])>>();
+        let zipped = zip_of(&entries);
+        assert_eq!(archive::rom_entries(&zipped).unwrap(), names);
+    }
+}
@@ -0,0 +1,41 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu_ppu::ntsc::{NtscFilter, NTSC_WIDTH};
+    use nesemu_ppu::palette::Palette;
+
+    use crate::common::{ines_image, setup};
+
+    #[test]
+    fn screenshots_the_last_frame() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        nes.ppu.write().unwrap().palette[0] = 0x21;
+        nes.run_frame();
+
+        let palette = Palette::default();
+        let plain = nes.screenshot(&palette, None);
+        assert_eq!((plain.width, plain.height), (256, 240));
+        assert_eq!(plain.rgba[..3], palette.rgb(0x21));
+
+        let filtered = nes.screenshot(&palette, Some(&NtscFilter::default()));
+        assert_eq!((filtered.width, filtered.height), (NTSC_WIDTH, 240));
+        assert_eq!(filtered.rgba.len(), NTSC_WIDTH * 240 * 4);
+    }
+
+    #[test]
+    fn writes_png_files() {
+        let nes = setup();
+        let path = std::env::temp_dir().join("nesemu_screenshot_test.png");
+        nes.screenshot(&Palette::default(), None)
+            .write_png(&path)
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes[..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+    }
+}
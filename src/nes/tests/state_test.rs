@@ -0,0 +1,51 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::state::StateError;
+    use nesemu_core::{Read, Write};
+
+    use crate::common::{ines_image, setup};
+
+    #[test]
+    fn loading_goes_back_to_the_saved_state() {
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        nes.run_frame();
+        nes.bus.write().unwrap().write(0x0200, 0x12);
+        let state = nes.save_state().unwrap();
+        let cycles = nes.cycles;
+        let pc = nes.cpu.pgrm_ctr;
+
+        nes.bus.write().unwrap().write(0x0200, 0x34);
+        nes.run_frame();
+        nes.run_frame();
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.cycles, cycles);
+        assert_eq!(nes.cpu.pgrm_ctr, pc);
+        assert_eq!(nes.bus.write().unwrap().read(0x0200, false), 0x12);
+        // and saving again gives the same state
+        assert_eq!(nes.save_state().unwrap(), state);
+    }
+
+    #[test]
+    fn states_only_load_over_their_rom() {
+        let mut nes = setup();
+        assert_eq!(nes.save_state(), Err(StateError::NoRom));
+        nes.load_rom_bytes(&ines_image(1, 1)).unwrap();
+        let state = nes.save_state().unwrap();
+        assert_eq!(nes.load_state(b"not a state"), Err(StateError::BadState));
+        assert_eq!(
+            nes.load_state(&state[..state.len() - 1]),
+            Err(StateError::BadState)
+        );
+
+        nes.load_rom_bytes(&ines_image(2, 1)).unwrap();
+        let cycles = nes.cycles;
+        assert_eq!(nes.load_state(&state), Err(StateError::WrongRom));
+        assert_eq!(nes.cycles, cycles);
+    }
+}
@@ -0,0 +1,94 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use nesemu::rom_loader::{Rom, RomError, TvSystem};
+    use nesemu::unif;
+    use nesemu_core::{Mirroring, Read};
+
+    use crate::common::setup;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn unif_image(board: &str, chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"UNIF".to_vec();
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.resize(0x20, 0);
+        bytes.extend(chunk(b"MAPR", format!("{}\0", board).as_bytes()));
+        for c in chunks {
+            bytes.extend_from_slice(c);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_chunks_in_order() {
+        let image = unif_image(
+            "NES-UNROM",
+            &[
+                chunk(b"PRG1", &[0x22; 0x4000]),
+                chunk(b"PRG0", &[0x11; 0x4000]),
+                chunk(b"MIRR", &[1]),
+                chunk(b"BATR", &[1]),
+                chunk(b"TVCI", &[1]),
+                chunk(b"CTRL", &[0b10]),
+                chunk(b"NAME", b"test\0"),
+            ],
+        );
+        let rom = Rom::build(&image).unwrap();
+        assert_eq!(rom.board.as_deref(), Some("NES-UNROM"));
+        assert_eq!(rom.mapper, 2);
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!(rom.prg_rom[0], 0x11);
+        assert_eq!(rom.prg_rom[0x4000], 0x22);
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        assert!(rom.battery);
+        assert_eq!(rom.tv_system, Some(TvSystem::Pal));
+        assert_eq!(rom.controllers, Some(0b10));
+    }
+
+    #[test]
+    fn maps_board_names() {
+        assert_eq!(unif::mapper_for_board("NES-NROM-256"), Some(0));
+        assert_eq!(unif::mapper_for_board("HVC-SLROM"), Some(1));
+        assert_eq!(unif::mapper_for_board("aorom"), Some(7));
+        assert_eq!(unif::mapper_for_board("NES-SUROM"), Some(1));
+        // no MMC3 yet
+        assert_eq!(unif::mapper_for_board("NES-TLROM"), None);
+        assert_eq!(unif::mapper_for_board("UNL-SOMETHING"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_boards_and_short_chunks() {
+        let unknown = unif_image("UNL-SOMETHING", &[chunk(b"PRG0", &[0; 0x4000])]);
+        assert!(matches!(
+            Rom::build(&unknown),
+            Err(RomError::UnsupportedBoard(name)) if name == "UNL-SOMETHING"
+        ));
+
+        let mut short = unif_image("NES-NROM-128", &[chunk(b"PRG0", &[0; 0x4000])]);
+        short.truncate(short.len() - 1);
+        assert!(matches!(Rom::build(&short), Err(RomError::Truncated)));
+    }
+
+    #[test]
+    fn loads_through_the_cartridge() {
+        let mut nes = setup();
+        let image = unif_image(
+            "NES-NROM-128",
+            &[
+                chunk(b"PRG0", &[0x5A; 0x4000]),
+                chunk(b"CHR0", &[0; 0x2000]),
+            ],
+        );
+        nes.load_rom_bytes(&image).unwrap();
+        assert_eq!(nes.bus.read().unwrap().read(0x8000, false), 0x5A);
+        assert_eq!(nes.bus.read().unwrap().read(0xFFFF, false), 0x5A);
+    }
+}
@@ -0,0 +1,149 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use nesemu::audio::wav::{WavFormat, WavWriter};
+    use nesemu::audio::{track_path, Mixer, SINK_CAPACITY};
+    use nesemu_core::Write;
+
+    use crate::common::{ines_image, setup};
+
+    const CLOCK: f64 = 1_789_773.0;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn headers_describe_the_samples() {
+        let dir = std::env::temp_dir().join("nesemu_wav_header_test");
+        fs::create_dir_all(&dir).unwrap();
+        for (format, tag, bytes) in [(WavFormat::Pcm16, 1, 2), (WavFormat::Float32, 3, 4)] {
+            let path = dir.join("out.wav");
+            let mut wav = WavWriter::create(&path, 44100, format).unwrap();
+            wav.write(&[0.0, 0.5, -2.0]).unwrap();
+            wav.write(&[1.0]).unwrap();
+            wav.finish().unwrap();
+
+            let file = fs::read(&path).unwrap();
+            assert_eq!(file.len(), 44 + 4 * bytes);
+            assert_eq!(&file[0..4], b"RIFF");
+            assert_eq!(u32_at(&file, 4) as usize, file.len() - 8);
+            assert_eq!(&file[8..16], b"WAVEfmt ");
+            assert_eq!(u16_at(&file, 20), tag);
+            assert_eq!(u16_at(&file, 22), 1);
+            assert_eq!(u32_at(&file, 24), 44100);
+            assert_eq!(u32_at(&file, 28), 44100 * bytes as u32);
+            assert_eq!(u16_at(&file, 34), 8 * bytes as u16);
+            assert_eq!(&file[36..40], b"data");
+            assert_eq!(u32_at(&file, 40) as usize, 4 * bytes);
+            if format == WavFormat::Pcm16 {
+                // the -2.0 is clipped
+                assert_eq!(u16_at(&file, 46) as i16, 16383);
+                assert_eq!(u16_at(&file, 48) as i16, -32767);
+            } else {
+                assert_eq!(f32::from_le_bytes(file[52..56].try_into().unwrap()), -2.0);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tracks_ignore_the_mix_volumes() {
+        let dir = std::env::temp_dir().join("nesemu_wav_track_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.wav");
+        let mut mixer = Mixer::new(CLOCK, 48000);
+        mixer.apu_volumes[0].muted = true;
+        mixer
+            .start_recording(&path, WavFormat::Float32, &["Pulse 1", "Pulse 2"])
+            .unwrap();
+        assert!(mixer.is_recording());
+        for cycle in 0..CLOCK as u32 / 10 {
+            let level = if (cycle / 900) & 1 == 0 { 15 } else { 0 };
+            mixer.clock([level, 0, 0, 0, 0], &[]);
+        }
+        mixer.stop_recording().unwrap();
+        assert!(!mixer.is_recording());
+
+        let samples = |path| {
+            let file = fs::read(path).unwrap();
+            file[44..]
+                .chunks(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect::<Vec<f32>>()
+        };
+        let mix = samples(path.clone());
+        let pulse1 = samples(dir.join("song - Pulse 1.wav"));
+        let pulse2 = samples(track_path(&path, "Pulse 2"));
+        // everything the mixer was clocked with, 100ms
+        assert!((4790..=4800).contains(&mix.len()), "{}", mix.len());
+        assert_eq!(pulse1.len(), mix.len());
+        assert_eq!(pulse2.len(), mix.len());
+        assert!(mix.iter().all(|sample| *sample == 0.0));
+        assert!(pulse1.iter().any(|sample| sample.abs() > 0.05));
+        assert!(pulse2.iter().all(|sample| *sample == 0.0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recordings_keep_their_rate() {
+        let dir = std::env::temp_dir().join("nesemu_wav_rate_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.wav");
+        let mut mixer = Mixer::new(CLOCK, 48000);
+        let sink = mixer.sink();
+        let mut out = vec![0.0; SINK_CAPACITY];
+        mixer
+            .start_recording(&path, WavFormat::Float32, &["Pulse 1"])
+            .unwrap();
+        // a sink always running dry would otherwise speed the mix up
+        for cycle in 0..CLOCK as u32 / 10 {
+            if cycle % 4096 == 0 {
+                sink.drain(&mut out);
+            }
+            mixer.clock([15, 0, 0, 0, 0], &[]);
+        }
+        mixer.stop_recording().unwrap();
+
+        for path in [path.clone(), track_path(&path, "Pulse 1")] {
+            let samples = u32_at(&fs::read(path).unwrap(), 40) / 4;
+            assert!((4790..=4800).contains(&samples), "{}", samples);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nes_records_every_channel() {
+        let dir = std::env::temp_dir().join("nesemu_wav_nes_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.wav");
+        let mut nes = setup();
+        let mut image = ines_image(1, 1);
+        image[0x10..0x4010].fill(0xEA);
+        nes.load_rom_bytes(&image).unwrap();
+        nes.cpu.reset();
+        nes.bus.write().unwrap().write(0x4017, 0x40);
+
+        nes.start_recording(&path, WavFormat::Pcm16, true).unwrap();
+        nes.run_frame();
+        nes.run_frame();
+        nes.mixer.stop_recording().unwrap();
+
+        let mix = fs::read(&path).unwrap();
+        // about 800 samples a frame at 48kHz
+        let samples = u32_at(&mix, 40) / 2;
+        assert!((1500..1700).contains(&samples), "{}", samples);
+        for channel in ["Pulse 1", "Pulse 2", "Triangle", "Noise", "DMC"] {
+            let track = fs::read(track_path(&path, channel)).unwrap();
+            assert_eq!(track.len(), mix.len());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,4 @@
+pub mod ntsc;
+pub mod palette;
+pub mod ppu;
+pub mod viewer;
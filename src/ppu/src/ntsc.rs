@@ -0,0 +1,151 @@
+use crate::palette::{carrier_angle, composite_level, yiq_to_rgb, NtscPaletteParams};
+use crate::ppu::{HEIGHT, WIDTH};
+
+/// Each PPU pixel lasts 8 ticks of the 12-phase colour carrier.
+const SAMPLES_PER_PIXEL: usize = 8;
+const LINE_SAMPLES: usize = WIDTH * SAMPLES_PER_PIXEL;
+/// One output pixel for every 3 samples, which keeps the 8:7 pixel aspect.
+const SAMPLES_PER_OUTPUT: usize = 3;
+/// How far the decoder looks either side of a sample.
+const PADDING: usize = 12;
+
+/// How wide `NtscFilter::apply` makes a frame.
+pub const NTSC_WIDTH: usize = LINE_SAMPLES / SAMPLES_PER_OUTPUT;
+
+/// Encodes frames as a composite signal and decodes them again like a
+/// TV would, so the picture gets the colour bleed and dot crawl of the
+/// real thing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscFilter {
+    /// -1 is blurry, 1 is oversharpened.
+    pub sharpness: f32,
+    /// How much of the colour carrier gets into the brightness (0..1).
+    pub artifacts: f32,
+    /// How much sharp changes in brightness get decoded as colour (0..1).
+    pub fringing: f32,
+    /// Blends the two phases the carrier alternates between from frame to
+    /// frame, which hides the crawl the way a slow phosphor would.
+    pub merge_fields: bool,
+    pub picture: NtscPaletteParams,
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        NtscFilter {
+            sharpness: 0.0,
+            artifacts: 1.0,
+            fringing: 1.0,
+            merge_fields: false,
+            picture: NtscPaletteParams::default(),
+        }
+    }
+}
+
+impl NtscFilter {
+    /// Filters a PPU frame into `NTSC_WIDTH` x `HEIGHT` RGBA bytes.
+    /// `frame_count` decides the carrier phase, since it moves on every
+    /// frame.
+    pub fn apply(&self, frame: &[u16], frame_count: u64) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(NTSC_WIDTH * HEIGHT * 4);
+        // the odd frames are a dot short, so the phase flips between two
+        // values rather than cycling through all three
+        let phase = if frame_count & 1 == 0 { 0 } else { 4 };
+        let mut line = Line::default();
+        let mut other = Line::default();
+        for (y, pixels) in frame.chunks(WIDTH).take(HEIGHT).enumerate() {
+            // each scanline is 341 * 8 samples long, 4 more than a multiple of 12
+            let start = phase + y * 4;
+            line.decode(self, pixels, start);
+            if self.merge_fields {
+                other.decode(self, pixels, (4 - phase) + y * 4);
+            }
+            for x in 0..NTSC_WIDTH {
+                let mut yiq = line.sample(x * SAMPLES_PER_OUTPUT);
+                if self.merge_fields {
+                    let [y, i, q] = other.sample(x * SAMPLES_PER_OUTPUT);
+                    yiq = [(yiq[0] + y) / 2.0, (yiq[1] + i) / 2.0, (yiq[2] + q) / 2.0];
+                }
+                let [r, g, b] = yiq_to_rgb(yiq[0], yiq[1], yiq[2], &self.picture);
+                rgba.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+        rgba
+    }
+}
+
+/// One decoded scanline. The sums are running totals, so any window of
+/// samples can be averaged in constant time.
+#[derive(Default)]
+struct Line {
+    signal: Vec<f32>,
+    luma: Vec<f32>,
+    i: Vec<f32>,
+    q: Vec<f32>,
+}
+
+impl Line {
+    fn decode(&mut self, filter: &NtscFilter, pixels: &[u16], start: usize) {
+        let angles: Vec<f32> = (0..12)
+            .map(|phase| carrier_angle(phase, filter.picture.hue))
+            .collect();
+        let length = LINE_SAMPLES + PADDING * 2;
+
+        // the edges repeat the first and last pixels
+        let index =
+            |n: usize| (n.saturating_sub(PADDING) / SAMPLES_PER_PIXEL).min(pixels.len() - 1);
+        let levels: Vec<f32> = (0..length)
+            .map(|n| composite_level(pixels[index(n)], (start + n) % 12))
+            .collect();
+        // what an ideal decoder would take out before looking for colour
+        let flat: Vec<f32> = pixels
+            .iter()
+            .map(|&p| (0..12).map(|phase| composite_level(p, phase)).sum::<f32>() / 12.0)
+            .collect();
+        self.signal = running_sum(levels.iter().copied());
+
+        // a full carrier cycle averages the colour out, leaving brightness
+        let luma: Vec<f32> = (0..length).map(|n| window(&self.signal, n, 12)).collect();
+        let crawl: Vec<f32> = (0..length)
+            .map(|n| {
+                let wide = luma[n];
+                let blurred = window(&self.signal, n, 24);
+                let narrow = window(&self.signal, n, 4);
+                wide + filter.sharpness * (wide - blurred) + filter.artifacts * (narrow - wide)
+            })
+            .collect();
+        self.luma = running_sum(crawl.iter().copied());
+
+        // any brightness left in the signal beats against the carrier and
+        // comes out as colour fringes
+        let chroma = |n: usize| levels[n] - (1.0 - filter.fringing) * flat[index(n)];
+        let angle = |n: usize| angles[(start + n) % 12];
+        self.i = running_sum((0..length).map(|n| chroma(n) * angle(n).cos()));
+        self.q = running_sum((0..length).map(|n| chroma(n) * angle(n).sin()));
+    }
+
+    fn sample(&self, n: usize) -> [f32; 3] {
+        let n = n + PADDING;
+        [
+            window(&self.luma, n, 1),
+            window(&self.i, n, 12),
+            window(&self.q, n, 12),
+        ]
+    }
+}
+
+fn running_sum(values: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut total = 0.0;
+    let mut sums = vec![0.0];
+    for value in values {
+        total += value;
+        sums.push(total);
+    }
+    sums
+}
+
+/// The average of `width` samples centred on `n`, cut short at the edges.
+fn window(sums: &[f32], n: usize, width: usize) -> f32 {
+    let start = n.saturating_sub(width / 2);
+    let end = (start + width).min(sums.len() - 1);
+    (sums[end] - sums[start]) / (end - start) as f32
+}
@@ -0,0 +1,246 @@
+use std::f32::consts::PI;
+
+/// 64 colours, then the same again for each of the 7 emphasis settings.
+pub const PALETTE_SIZE: usize = 64 * 8;
+
+/// The usual 2C02 palette, without emphasis.
+const DEFAULT_COLOURS: [[u8; 3]; 64] = [
+    [84, 84, 84],
+    [0, 30, 116],
+    [8, 16, 144],
+    [48, 0, 136],
+    [68, 0, 100],
+    [92, 0, 48],
+    [84, 4, 0],
+    [60, 24, 0],
+    [32, 42, 0],
+    [8, 58, 0],
+    [0, 64, 0],
+    [0, 60, 0],
+    [0, 50, 60],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [152, 150, 152],
+    [8, 76, 196],
+    [48, 50, 236],
+    [92, 30, 228],
+    [136, 20, 176],
+    [160, 20, 100],
+    [152, 34, 32],
+    [120, 60, 0],
+    [84, 90, 0],
+    [40, 114, 0],
+    [8, 124, 0],
+    [0, 118, 40],
+    [0, 102, 120],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [236, 238, 236],
+    [76, 154, 236],
+    [120, 124, 236],
+    [176, 98, 236],
+    [228, 84, 236],
+    [236, 88, 180],
+    [236, 106, 100],
+    [212, 136, 32],
+    [160, 170, 0],
+    [116, 196, 0],
+    [76, 208, 32],
+    [56, 204, 108],
+    [56, 180, 204],
+    [60, 60, 60],
+    [0, 0, 0],
+    [0, 0, 0],
+    [236, 238, 236],
+    [168, 204, 236],
+    [188, 188, 236],
+    [212, 178, 236],
+    [236, 174, 236],
+    [236, 174, 212],
+    [236, 180, 176],
+    [228, 196, 144],
+    [204, 210, 120],
+    [180, 222, 120],
+    [168, 226, 144],
+    [152, 226, 180],
+    [160, 214, 228],
+    [160, 162, 160],
+    [0, 0, 0],
+    [0, 0, 0],
+];
+
+/// How much emphasis dims the channels it doesn't emphasise.
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+#[derive(Debug)]
+pub enum PaletteError {
+    /// .pal files are 64 colours (192 bytes) or 512 with emphasis (1536).
+    BadSize(usize),
+}
+
+/// Knobs for `Palette::generate`. The defaults give a neutral picture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscPaletteParams {
+    /// Degrees.
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl Default for NtscPaletteParams {
+    fn default() -> Self {
+        NtscPaletteParams {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+/// Maps what the PPU outputs (a 6-bit colour with the three emphasis bits
+/// above it) to RGB.
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    colours: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::with_emphasis(&DEFAULT_COLOURS)
+    }
+}
+
+impl Palette {
+    /// Loads a .pal file. Files with only 64 colours get the emphasis
+    /// variants worked out from them.
+    pub fn from_pal(bytes: &[u8]) -> Result<Palette, PaletteError> {
+        if bytes.len() != 64 * 3 && bytes.len() != PALETTE_SIZE * 3 {
+            return Err(PaletteError::BadSize(bytes.len()));
+        }
+        let colours: Vec<[u8; 3]> = bytes.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+        if colours.len() == 64 {
+            Ok(Palette::with_emphasis(&colours))
+        } else {
+            Ok(Palette { colours })
+        }
+    }
+
+    fn with_emphasis(base: &[[u8; 3]]) -> Palette {
+        let mut colours = Vec::with_capacity(PALETTE_SIZE);
+        for emphasis in 0..8 {
+            for colour in base {
+                let mut colour = *colour;
+                // red, green and blue on NTSC; each one dims the other two
+                for (channel, value) in colour.iter_mut().enumerate() {
+                    let dimmed = (0..3).any(|bit| emphasis & (1 << bit) != 0 && bit != channel);
+                    if dimmed {
+                        *value = (*value as f32 * EMPHASIS_ATTENUATION) as u8;
+                    }
+                }
+                colours.push(colour);
+            }
+        }
+        Palette { colours }
+    }
+
+    /// Builds a palette the way the console makes colours: a square wave
+    /// between two voltages, its phase giving the hue, decoded as YIQ.
+    pub fn generate(params: NtscPaletteParams) -> Palette {
+        let colours = (0..PALETTE_SIZE)
+            .map(|pixel| {
+                let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+                for phase in 0..12 {
+                    let level = composite_level(pixel as u16, phase);
+                    let angle = carrier_angle(phase, params.hue);
+                    y += level;
+                    i += level * angle.cos();
+                    q += level * angle.sin();
+                }
+                yiq_to_rgb(y / 12.0, i / 12.0, q / 12.0, &params)
+            })
+            .collect();
+        Palette { colours }
+    }
+
+    /// The colour for one frame buffer entry.
+    pub fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colours[pixel as usize % PALETTE_SIZE]
+    }
+
+    /// A whole frame as RGBA bytes, ready for a texture or an image file.
+    pub fn to_rgba(&self, frame: &[u16]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(frame.len() * 4);
+        for pixel in frame {
+            let [r, g, b] = self.rgb(*pixel);
+            rgba.extend_from_slice(&[r, g, b, 0xFF]);
+        }
+        rgba
+    }
+
+    /// The palette as a 1536 byte .pal file.
+    pub fn to_pal(&self) -> Vec<u8> {
+        self.colours.iter().flatten().copied().collect()
+    }
+}
+
+/// The composite signal for a PPU pixel at one of the 12 phases of the
+/// colour carrier, scaled so black is 0 and white is 1.
+pub(crate) fn composite_level(pixel: u16, phase: usize) -> f32 {
+    const LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+    const HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+    const BLACK: f32 = 0.518;
+    const WHITE: f32 = 1.962;
+    const ATTENUATION: f32 = 0.746;
+
+    let colour = (pixel & 0x0F) as usize;
+    let level = if colour > 0x0D {
+        1
+    } else {
+        (pixel as usize >> 4) & 0x03
+    };
+    let emphasis = (pixel >> 6) & 0x07;
+    let low = if colour == 0 { HIGH[level] } else { LOW[level] };
+    let high = if colour > 0x0C {
+        LOW[level]
+    } else {
+        HIGH[level]
+    };
+
+    let in_phase = |colour: usize| (colour + phase) % 12 < 6;
+    let mut signal = if in_phase(colour) { high } else { low };
+    let emphasised = (emphasis & 1 != 0 && in_phase(0x0C))
+        || (emphasis & 2 != 0 && in_phase(0x04))
+        || (emphasis & 4 != 0 && in_phase(0x08));
+    if emphasised && colour < 0x0E {
+        signal *= ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+/// Where the decoder's reference carrier is at `phase`.
+pub(crate) fn carrier_angle(phase: usize, hue: f32) -> f32 {
+    PI * (phase as f32 + 3.9 + hue / 30.0) / 6.0
+}
+
+/// Turns a decoded YIQ sample into RGB, with the picture controls applied.
+pub(crate) fn yiq_to_rgb(y: f32, i: f32, q: f32, params: &NtscPaletteParams) -> [u8; 3] {
+    let y = y * params.contrast + params.brightness;
+    let i = i * params.saturation * params.contrast;
+    let q = q * params.saturation * params.contrast;
+
+    let gamma = |value: f32| {
+        let value = value.max(0.0).powf(2.2 / params.gamma);
+        (value * 255.0).clamp(0.0, 255.0) as u8
+    };
+    [
+        gamma(y + 0.946882 * i + 0.623557 * q),
+        gamma(y - 0.274788 * i - 0.635691 * q),
+        gamma(y - 1.108545 * i + 1.709007 * q),
+    ]
+}
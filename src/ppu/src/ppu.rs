@@ -0,0 +1,764 @@
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use nesemu_core::{Mirroring, Region};
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+pub const DOTS: u16 = 341;
+
+/// What the PPU sees of the cartridge: the pattern tables at $0000-$1FFF
+/// and how the nametables are wired up.
+pub trait PpuBus: Send + Sync {
+    fn ppu_read(&self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, data: u8);
+    fn mirroring(&self) -> Mirroring;
+}
+
+pub enum PpuCtrl {
+    NametableX,
+    NametableY,
+    Increment32,
+    SpriteTable,
+    BackgroundTable,
+    SpriteSize,
+    NmiEnable,
+}
+
+impl PpuCtrl {
+    pub fn bit(&self) -> u8 {
+        match self {
+            PpuCtrl::NametableX => 1 << 0,
+            PpuCtrl::NametableY => 1 << 1,
+            PpuCtrl::Increment32 => 1 << 2,
+            PpuCtrl::SpriteTable => 1 << 3,
+            PpuCtrl::BackgroundTable => 1 << 4,
+            PpuCtrl::SpriteSize => 1 << 5,
+            PpuCtrl::NmiEnable => 1 << 7,
+        }
+    }
+}
+
+pub enum PpuMask {
+    Greyscale,
+    BackgroundLeft,
+    SpritesLeft,
+    Background,
+    Sprites,
+}
+
+impl PpuMask {
+    pub fn bit(&self) -> u8 {
+        match self {
+            PpuMask::Greyscale => 1 << 0,
+            PpuMask::BackgroundLeft => 1 << 1,
+            PpuMask::SpritesLeft => 1 << 2,
+            PpuMask::Background => 1 << 3,
+            PpuMask::Sprites => 1 << 4,
+        }
+    }
+}
+
+pub enum PpuStatus {
+    SpriteOverflow,
+    SpriteZeroHit,
+    VBlank,
+}
+
+impl PpuStatus {
+    pub fn bit(&self) -> u8 {
+        match self {
+            PpuStatus::SpriteOverflow => 1 << 5,
+            PpuStatus::SpriteZeroHit => 1 << 6,
+            PpuStatus::VBlank => 1 << 7,
+        }
+    }
+}
+
+/// Serializes to what a save state needs. The cartridge, the region, the
+/// sprite setting and the picture are left out, for whoever loads it to
+/// carry over from the PPU it replaces.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PPU<Bus: PpuBus> {
+    #[serde(skip)]
+    pub cartridge: Option<Arc<RwLock<Bus>>>,
+
+    /// 2k inside the console, plus the 2k four-screen boards add.
+    #[serde(with = "nesemu_core::array_serde")]
+    vram: [u8; 0x1000],
+    pub palette: [u8; 0x20],
+    #[serde(with = "nesemu_core::array_serde")]
+    pub oam: [u8; 0x100],
+
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+
+    /// Current VRAM address, also the scroll position while rendering.
+    pub v: u16,
+    /// Temporary VRAM address: the scroll position for the next frame.
+    pub t: u16,
+    /// Fine X scroll.
+    pub x: u8,
+    /// First/second write toggle shared by $2005 and $2006.
+    pub w: bool,
+    read_buffer: u8,
+    /// The last value put on the PPU's data bus, what reads of write-only
+    /// registers return.
+    io_latch: u8,
+
+    /// Decides how many lines a frame has and where vblank falls.
+    #[serde(skip)]
+    pub region: Region,
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame_count: u64,
+    nmi_pending: bool,
+    fetch: TileFetch,
+    shifters: Shifters,
+    /// Sprites found for the next line, in OAM order.
+    sprites: Vec<LineSprite>,
+    /// Draws every sprite on a line instead of the first eight, which gets
+    /// rid of most flicker. The overflow flag still behaves as on hardware.
+    #[serde(skip)]
+    pub unlimited_sprites: bool,
+
+    /// One entry per pixel: the 6-bit colour index, with the PPUMASK
+    /// emphasis bits above it.
+    #[serde(skip)]
+    pub frame: Vec<u16>,
+}
+
+impl<Bus: PpuBus> Default for PPU<Bus> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Bus: PpuBus> PPU<Bus> {
+    pub fn new() -> Self {
+        PPU {
+            cartridge: None,
+            vram: [0; 0x1000],
+            palette: [0; 0x20],
+            oam: [0; 0x100],
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            region: Region::Ntsc,
+            scanline: 0,
+            dot: 0,
+            frame_count: 0,
+            nmi_pending: false,
+            fetch: TileFetch::default(),
+            shifters: Shifters::default(),
+            sprites: Vec::with_capacity(64),
+            unlimited_sprites: false,
+            frame: vec![0; WIDTH * HEIGHT],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.w = false;
+        self.x = 0;
+        self.t = 0;
+        self.read_buffer = 0;
+        self.scanline = 0;
+        self.dot = 0;
+        self.nmi_pending = false;
+    }
+
+    fn ctrl(&self, flag: PpuCtrl) -> bool {
+        self.ctrl & flag.bit() != 0
+    }
+
+    fn mask(&self, flag: PpuMask) -> bool {
+        self.mask & flag.bit() != 0
+    }
+
+    pub fn rendering(&self) -> bool {
+        self.mask(PpuMask::Background) || self.mask(PpuMask::Sprites)
+    }
+
+    /// Whether the PPU has asked for an NMI since the last call.
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// Same as `take_nmi`, but leaves the request where it is.
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    /// A CPU read of $2000-$2007 (mirrored up to $3FFF), with all the side
+    /// effects that come with it.
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        let data = self.peek_register(addr);
+        match addr & 0x0007 {
+            2 => {
+                self.status &= !PpuStatus::VBlank.bit();
+                self.w = false;
+            }
+            7 => {
+                let addr = self.v & 0x3FFF;
+                // palette reads aren't delayed, but still fill the buffer
+                // with the nametable byte underneath
+                self.read_buffer = if addr >= 0x3F00 {
+                    self.read(addr - 0x1000)
+                } else {
+                    self.read(addr)
+                };
+                self.increment_v();
+            }
+            _ => {}
+        }
+        self.io_latch = data;
+        data
+    }
+
+    /// What a read would return, without changing anything.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr & 0x0007 {
+            2 => (self.status & 0xE0) | (self.io_latch & 0x1F),
+            4 => self.oam[self.oam_addr as usize],
+            7 => {
+                let addr = self.v & 0x3FFF;
+                if addr >= 0x3F00 {
+                    (self.read(addr) & 0x3F) | (self.io_latch & 0xC0)
+                } else {
+                    self.read_buffer
+                }
+            }
+            _ => self.io_latch,
+        }
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.io_latch = data;
+        match addr & 0x0007 {
+            0 => {
+                let was_enabled = self.ctrl(PpuCtrl::NmiEnable);
+                self.ctrl = data;
+                self.t = (self.t & 0xF3FF) | ((data as u16 & 0x03) << 10);
+                // turning NMIs on during vblank fires one straight away
+                if !was_enabled
+                    && self.ctrl(PpuCtrl::NmiEnable)
+                    && self.status & PpuStatus::VBlank.bit() != 0
+                {
+                    self.nmi_pending = true;
+                }
+            }
+            1 => self.mask = data,
+            3 => self.oam_addr = data,
+            4 => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if !self.w {
+                    self.t = (self.t & 0xFFE0) | (data as u16 >> 3);
+                    self.x = data & 0x07;
+                } else {
+                    self.t = (self.t & 0x8C1F)
+                        | ((data as u16 & 0x07) << 12)
+                        | ((data as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | data as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            }
+            7 => {
+                self.write(self.v & 0x3FFF, data);
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    fn increment_v(&mut self) {
+        // during rendering the increment goes through the scroll logic
+        // instead, bumping coarse X and Y at once
+        let rendering_line = self.scanline < 240 || self.scanline == self.pre_render_line();
+        if self.rendering() && rendering_line {
+            self.v = increment_coarse_x(self.v);
+            self.increment_y();
+            return;
+        }
+        let step = if self.ctrl(PpuCtrl::Increment32) {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match &self.cartridge {
+            Some(cartridge) => cartridge.read().unwrap().mirroring(),
+            None => Mirroring::Horizontal,
+        }
+    }
+
+    /// Maps $2000-$3EFF onto VRAM according to the cartridge's wiring.
+    fn nametable_index(&self, addr: u16) -> usize {
+        let addr = addr as usize & 0x0FFF;
+        let table = addr / 0x0400;
+        let physical = match self.mirroring() {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        physical * 0x0400 + (addr & 0x03FF)
+    }
+
+    /// $3F10/$3F14/$3F18/$3F1C are the same bytes as $3F00/$3F04/...
+    fn palette_index(addr: u16) -> usize {
+        let index = addr as usize & 0x1F;
+        if index & 0x13 == 0x10 {
+            index & 0x0F
+        } else {
+            index
+        }
+    }
+
+    /// A read from the PPU's own address space.
+    pub fn read(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => match &self.cartridge {
+                Some(cartridge) => cartridge.read().unwrap().ppu_read(addr),
+                None => 0,
+            },
+            0x2000..=0x3EFF => self.vram[self.nametable_index(addr)],
+            _ => self.palette[Self::palette_index(addr)],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    cartridge.write().unwrap().ppu_write(addr, data);
+                }
+            }
+            0x2000..=0x3EFF => self.vram[self.nametable_index(addr)] = data,
+            _ => self.palette[Self::palette_index(addr)] = data & 0x3F,
+        }
+    }
+
+    /// The last line of the frame, which gets the next one's first tiles.
+    fn pre_render_line(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
+    /// One PPU dot. There are three of these per CPU cycle on NTSC.
+    pub fn clock(&mut self) {
+        let vblank_line = self.region.vblank_line();
+        let pre_render_line = self.pre_render_line();
+        match self.scanline {
+            0..=239 => self.render_dot(),
+            line if line == vblank_line && self.dot == 1 => {
+                self.status |= PpuStatus::VBlank.bit();
+                if self.ctrl(PpuCtrl::NmiEnable) {
+                    self.nmi_pending = true;
+                }
+            }
+            line if line == pre_render_line => {
+                if self.dot == 1 {
+                    self.status &= !(PpuStatus::VBlank.bit()
+                        | PpuStatus::SpriteZeroHit.bit()
+                        | PpuStatus::SpriteOverflow.bit());
+                }
+                self.render_dot();
+                if self.dot == 257 {
+                    // nothing is evaluated here, so line 0 has no sprites
+                    self.sprites.clear();
+                }
+                if self.rendering() && (280..=304).contains(&self.dot) {
+                    self.copy_y();
+                }
+                // odd frames are a dot shorter while rendering
+                if self.region.skips_odd_dot()
+                    && self.rendering()
+                    && self.dot == 339
+                    && self.frame_count % 2 == 1
+                {
+                    self.dot = 340;
+                }
+            }
+            _ => {}
+        }
+
+        if self.scanline < 240 && (1..=256).contains(&self.dot) {
+            self.output_pixel();
+        }
+
+        self.dot += 1;
+        if self.dot >= DOTS {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline >= self.region.scanlines() {
+                self.scanline = 0;
+                self.frame_count += 1;
+            }
+        }
+    }
+
+    /// The background fetch pipeline, shared by the visible lines and the
+    /// pre-render line (which fetches the first two tiles of the frame).
+    /// Each tile takes eight dots: nametable byte, attribute byte, then the
+    /// two pattern bytes, after which v moves one tile right.
+    fn render_dot(&mut self) {
+        if !self.rendering() {
+            return;
+        }
+        match self.dot {
+            2..=257 | 322..=337 => {
+                self.shift_background();
+                self.fetch_background();
+            }
+            338 | 340 => {
+                // unused nametable fetches, which some mappers count
+                self.fetch.tile = self.read(0x2000 | (self.v & 0x0FFF));
+            }
+            321 => self.fetch_background(),
+            _ => {}
+        }
+        match self.dot {
+            256 => self.increment_y(),
+            257 => {
+                self.load_background();
+                self.copy_x();
+                if self.scanline < 240 {
+                    self.evaluate_sprites();
+                }
+            }
+            320 => self.fetch_sprites(),
+            _ => {}
+        }
+        if (257..=320).contains(&self.dot) {
+            self.oam_addr = 0;
+        }
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl(PpuCtrl::SpriteSize) {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Finds the sprites on the next line. After eight the hardware keeps
+    /// looking only to set the overflow flag, and gets it wrong: it also
+    /// steps through the bytes within each entry, so it compares tile
+    /// numbers, attributes and X positions as if they were Y.
+    fn evaluate_sprites(&mut self) {
+        self.sprites.clear();
+        let height = self.sprite_height();
+        let in_range = |y: u8| self.scanline.wrapping_sub(y as u16) < height;
+
+        let mut found = 0;
+        let mut overflow = false;
+        let mut m = 0;
+        for n in 0..64 {
+            if found < 8 {
+                let entry = &self.oam[n * 4..n * 4 + 4];
+                if in_range(entry[0]) {
+                    found += 1;
+                    self.sprites.push(LineSprite::new(entry, n == 0));
+                }
+            } else {
+                if !overflow && in_range(self.oam[n * 4 + m]) {
+                    overflow = true;
+                }
+                if !overflow {
+                    m = (m + 1) & 0x03;
+                }
+                if self.unlimited_sprites {
+                    let entry = &self.oam[n * 4..n * 4 + 4];
+                    if in_range(entry[0]) {
+                        self.sprites.push(LineSprite::new(entry, false));
+                    }
+                }
+            }
+        }
+        if overflow {
+            self.status |= PpuStatus::SpriteOverflow.bit();
+        }
+    }
+
+    /// Loads the pattern bytes of the sprites found. Empty slots still
+    /// fetch tile $FF, as the hardware does.
+    fn fetch_sprites(&mut self) {
+        let height = self.sprite_height();
+        let slots = self.sprites.len().max(8);
+        for slot in 0..slots {
+            let (row, tile, attribute) = match self.sprites.get(slot) {
+                Some(sprite) => {
+                    let row = self.scanline.wrapping_sub(sprite.y as u16);
+                    (row, sprite.tile, sprite.attribute)
+                }
+                None => (0, 0xFF, 0),
+            };
+            let row = if attribute & 0x80 != 0 {
+                height - 1 - row
+            } else {
+                row
+            };
+            let addr = if height == 16 {
+                let table = (tile as u16 & 0x01) * 0x1000;
+                let tile = (tile as u16 & 0xFE) + row / 8;
+                table + tile * 16 + row % 8
+            } else {
+                let table = if self.ctrl(PpuCtrl::SpriteTable) {
+                    0x1000
+                } else {
+                    0
+                };
+                table + tile as u16 * 16 + row
+            };
+            let (mut low, mut high) = (self.read(addr), self.read(addr + 8));
+            if attribute & 0x40 != 0 {
+                low = low.reverse_bits();
+                high = high.reverse_bits();
+            }
+            if let Some(sprite) = self.sprites.get_mut(slot) {
+                sprite.low = low;
+                sprite.high = high;
+            }
+        }
+    }
+
+    /// The first opaque sprite pixel at `x`: its palette entry, whether it
+    /// goes behind the background, and whether it is sprite 0.
+    fn sprite_pixel(&self, x: u16) -> Option<(u8, bool, bool)> {
+        if !self.mask(PpuMask::Sprites) || (x < 8 && !self.mask(PpuMask::SpritesLeft)) {
+            return None;
+        }
+        self.sprites.iter().find_map(|sprite| {
+            let column = x.wrapping_sub(sprite.x as u16);
+            if column >= 8 {
+                return None;
+            }
+            let bit = 7 - column;
+            let pixel = ((sprite.low >> bit) & 1) | ((sprite.high >> bit) & 1) << 1;
+            if pixel == 0 {
+                return None;
+            }
+            let entry = 0x10 | (sprite.attribute & 0x03) << 2 | pixel;
+            Some((entry, sprite.attribute & 0x20 != 0, sprite.zero))
+        })
+    }
+
+    fn fetch_background(&mut self) {
+        match (self.dot - 1) % 8 {
+            0 => {
+                self.load_background();
+                self.fetch.tile = self.read(0x2000 | (self.v & 0x0FFF));
+            }
+            2 => {
+                let v = self.v;
+                let attribute =
+                    self.read(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+                let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                self.fetch.attribute = (attribute >> shift) & 0x03;
+            }
+            4 => self.fetch.low = self.read(self.background_pattern_addr()),
+            6 => self.fetch.high = self.read(self.background_pattern_addr() + 8),
+            7 => self.v = increment_coarse_x(self.v),
+            _ => {}
+        }
+    }
+
+    fn background_pattern_addr(&self) -> u16 {
+        let table = if self.ctrl(PpuCtrl::BackgroundTable) {
+            0x1000
+        } else {
+            0
+        };
+        table + self.fetch.tile as u16 * 16 + ((self.v >> 12) & 0x07)
+    }
+
+    /// Puts the fetched tile into the low byte of the shift registers,
+    /// behind the one being drawn.
+    fn load_background(&mut self) {
+        let shifters = &mut self.shifters;
+        shifters.pattern_low = (shifters.pattern_low & 0xFF00) | self.fetch.low as u16;
+        shifters.pattern_high = (shifters.pattern_high & 0xFF00) | self.fetch.high as u16;
+        let attribute = self.fetch.attribute;
+        shifters.attribute_low =
+            (shifters.attribute_low & 0xFF00) | if attribute & 1 != 0 { 0xFF } else { 0 };
+        shifters.attribute_high =
+            (shifters.attribute_high & 0xFF00) | if attribute & 2 != 0 { 0xFF } else { 0 };
+    }
+
+    fn shift_background(&mut self) {
+        if self.mask(PpuMask::Background) {
+            self.shifters.pattern_low <<= 1;
+            self.shifters.pattern_high <<= 1;
+            self.shifters.attribute_low <<= 1;
+            self.shifters.attribute_high <<= 1;
+        }
+    }
+
+    /// The background's palette entry at the current dot: palette in the
+    /// top two bits, colour in the bottom two, 0 for transparent.
+    fn background_pixel(&self) -> u8 {
+        let x = self.dot - 1;
+        if !self.mask(PpuMask::Background) || (x < 8 && !self.mask(PpuMask::BackgroundLeft)) {
+            return 0;
+        }
+        let bit = 0x8000 >> self.x;
+        let select = |shifter: u16| (shifter & bit != 0) as u8;
+        let pixel = select(self.shifters.pattern_low) | select(self.shifters.pattern_high) << 1;
+        if pixel == 0 {
+            return 0;
+        }
+        let palette =
+            select(self.shifters.attribute_low) | select(self.shifters.attribute_high) << 1;
+        (palette << 2) | pixel
+    }
+
+    fn output_pixel(&mut self) {
+        let x = self.dot - 1;
+        let background = self.background_pixel();
+        let entry = match self.sprite_pixel(x) {
+            Some((sprite, behind, zero)) => {
+                // no hit at x=255, and the left column clipping applies to
+                // both sides of the comparison
+                if zero && background != 0 && x != 255 {
+                    self.status |= PpuStatus::SpriteZeroHit.bit();
+                }
+                if behind && background != 0 {
+                    background
+                } else {
+                    sprite
+                }
+            }
+            None => background,
+        };
+        let colour = if !self.rendering() && self.v & 0x3F00 == 0x3F00 {
+            // with rendering off, pointing v into the palette shows that
+            // colour instead of the backdrop
+            self.output_colour((self.v & 0x1F) as u8)
+        } else {
+            self.output_colour(entry)
+        };
+        self.frame[self.scanline as usize * WIDTH + x as usize] = colour;
+    }
+
+    /// Moves v down a pixel row, wrapping into the next nametable down
+    /// after row 29 (rows 30 and 31 are attribute data).
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_x(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_y(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    /// Turns a palette entry into what goes in the frame buffer.
+    fn output_colour(&self, entry: u8) -> u16 {
+        let mut colour = self.palette[Self::palette_index(entry as u16)];
+        if self.mask(PpuMask::Greyscale) {
+            colour &= 0x30;
+        }
+        colour as u16 | ((self.mask as u16 & 0xE0) << 1)
+    }
+}
+
+/// Moves a VRAM address one tile right, into the next nametable across
+/// after the 32nd.
+fn increment_coarse_x(v: u16) -> u16 {
+    if v & 0x001F == 31 {
+        (v & !0x001F) ^ 0x0400
+    } else {
+        v + 1
+    }
+}
+
+/// A sprite picked for the coming line, with its row of pattern data.
+#[derive(Serialize, Deserialize)]
+struct LineSprite {
+    y: u8,
+    tile: u8,
+    attribute: u8,
+    x: u8,
+    low: u8,
+    high: u8,
+    zero: bool,
+}
+
+impl LineSprite {
+    fn new(entry: &[u8], zero: bool) -> Self {
+        LineSprite {
+            y: entry[0],
+            tile: entry[1],
+            attribute: entry[2],
+            x: entry[3],
+            low: 0,
+            high: 0,
+            zero,
+        }
+    }
+}
+
+/// The bytes fetched for the next tile, waiting to go into the shifters.
+#[derive(Default, Serialize, Deserialize)]
+struct TileFetch {
+    tile: u8,
+    attribute: u8,
+    low: u8,
+    high: u8,
+}
+
+/// Two tiles worth of background: the high byte is being drawn, the low
+/// byte is the next tile.
+#[derive(Default, Serialize, Deserialize)]
+struct Shifters {
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+}
@@ -0,0 +1,157 @@
+//! Pictures of the PPU's memory for the debugger: the pattern tables, the
+//! nametables, the sprites in OAM and the palette. Like `PPU::frame`, they
+//! are palette indexes, to be coloured with a `Palette`.
+
+use crate::ppu::{PpuBus, PpuCtrl, PPU};
+
+/// Both pattern tables side by side.
+pub const PATTERN_WIDTH: usize = 256;
+pub const PATTERN_HEIGHT: usize = 128;
+/// All four nametables, in a 2x2 grid.
+pub const NAMETABLE_WIDTH: usize = 512;
+pub const NAMETABLE_HEIGHT: usize = 480;
+
+/// One OAM entry, decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteInfo {
+    pub index: u8,
+    pub x: u8,
+    /// One less than the line the sprite starts on.
+    pub y: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    /// For 8x16 sprites, the address of the top tile.
+    pub pattern_addr: u16,
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl<Bus: PpuBus> PPU<Bus> {
+    fn tall_sprites(&self) -> bool {
+        self.ctrl & PpuCtrl::SpriteSize.bit() != 0
+    }
+
+    /// The palette entry a pixel of `tile` uses, 0 being transparent.
+    fn tile_pixel(&self, table: u16, tile: u8, x: u16, y: u16) -> u8 {
+        let addr = table + tile as u16 * 16 + y;
+        let low = self.read(addr) >> (7 - x) & 1;
+        let high = self.read(addr + 8) >> (7 - x) & 1;
+        high << 1 | low
+    }
+
+    /// The colour a palette entry shows, transparent ones falling through
+    /// to the backdrop.
+    fn entry_colour(&self, palette: u8, pixel: u8) -> u16 {
+        let addr = if pixel == 0 {
+            0x3F00
+        } else {
+            0x3F00 + palette as u16 * 4 + pixel as u16
+        };
+        self.read(addr) as u16
+    }
+
+    /// `$0000` on the left and `$1000` on the right, drawn with one of the
+    /// eight palettes (4-7 are the sprite ones).
+    pub fn pattern_table_view(&self, palette: u8) -> Vec<u16> {
+        let mut view = vec![0; PATTERN_WIDTH * PATTERN_HEIGHT];
+        for (n, pixel) in view.iter_mut().enumerate() {
+            let (x, y) = (n % PATTERN_WIDTH, n / PATTERN_WIDTH);
+            let table = (x / 128) as u16 * 0x1000;
+            let tile = ((y / 8) * 16 + (x % 128) / 8) as u8;
+            let entry = self.tile_pixel(table, tile, (x % 8) as u16, (y % 8) as u16);
+            *pixel = self.entry_colour(palette & 0x07, entry);
+        }
+        view
+    }
+
+    /// The four nametables as the background would draw them.
+    pub fn nametable_view(&self) -> Vec<u16> {
+        let table = if self.ctrl & PpuCtrl::BackgroundTable.bit() != 0 {
+            0x1000
+        } else {
+            0
+        };
+        let mut view = vec![0; NAMETABLE_WIDTH * NAMETABLE_HEIGHT];
+        for (n, pixel) in view.iter_mut().enumerate() {
+            let (x, y) = (n % NAMETABLE_WIDTH, n / NAMETABLE_WIDTH);
+            let base = 0x2000 + (y / 240) as u16 * 0x800 + (x / 256) as u16 * 0x400;
+            let (x, y) = (x % 256, y % 240);
+            let tile = self.read(base + (y / 8 * 32 + x / 8) as u16);
+            let attribute = self.read(base + 0x3C0 + (y / 32 * 8 + x / 32) as u16);
+            let palette = attribute >> ((y & 0x10) >> 2 | (x & 0x10) >> 3) & 0x03;
+            let entry = self.tile_pixel(table, tile, (x % 8) as u16, (y % 8) as u16);
+            *pixel = self.entry_colour(palette, entry);
+        }
+        view
+    }
+
+    /// Where the top left of the screen is within `nametable_view`, going
+    /// by the scroll the CPU last set.
+    pub fn scroll_position(&self) -> (usize, usize) {
+        let t = self.t as usize;
+        let x = (t & 0x1F) << 3 | self.x as usize;
+        let y = (t >> 5 & 0x1F) << 3 | t >> 12 & 0x07;
+        (x + (t >> 10 & 1) * 256, y + (t >> 11 & 1) * 240)
+    }
+
+    pub fn sprites(&self) -> Vec<SpriteInfo> {
+        self.oam
+            .chunks(4)
+            .enumerate()
+            .map(|(index, entry)| {
+                let (tile, attributes) = (entry[1], entry[2]);
+                let pattern_addr = if self.tall_sprites() {
+                    (tile as u16 & 1) * 0x1000 + (tile as u16 & 0xFE) * 16
+                } else if self.ctrl & PpuCtrl::SpriteTable.bit() != 0 {
+                    0x1000 + tile as u16 * 16
+                } else {
+                    tile as u16 * 16
+                };
+                SpriteInfo {
+                    index: index as u8,
+                    x: entry[3],
+                    y: entry[0],
+                    tile,
+                    attributes,
+                    pattern_addr,
+                    palette: attributes & 0x03,
+                    behind_background: attributes & 0x20 != 0,
+                    flip_horizontal: attributes & 0x40 != 0,
+                    flip_vertical: attributes & 0x80 != 0,
+                }
+            })
+            .collect()
+    }
+
+    /// One sprite as it would appear on screen: 8 wide, and 8 or 16 high.
+    /// Transparent pixels show the backdrop.
+    pub fn sprite_view(&self, sprite: &SpriteInfo) -> Vec<u16> {
+        let height = if self.tall_sprites() { 16 } else { 8 };
+        let mut view = vec![0; 8 * height];
+        for (n, pixel) in view.iter_mut().enumerate() {
+            let (mut x, mut y) = ((n % 8) as u16, (n / 8) as u16);
+            if sprite.flip_horizontal {
+                x = 7 - x;
+            }
+            if sprite.flip_vertical {
+                y = height as u16 - 1 - y;
+            }
+            // the bottom half of a tall sprite is the next tile along
+            let addr = sprite.pattern_addr + (y / 8) * 16;
+            let entry = self.tile_pixel(addr & 0x1000, (addr >> 4) as u8, x, y % 8);
+            *pixel = self.entry_colour(4 + sprite.palette, entry);
+        }
+        view
+    }
+
+    /// The 32 bytes of palette RAM, as they read back.
+    pub fn palette_ram(&self) -> [u8; 32] {
+        let mut ram = [0; 32];
+        for (n, entry) in ram.iter_mut().enumerate() {
+            *entry = self.read(0x3F00 + n as u16);
+        }
+        ram
+    }
+}
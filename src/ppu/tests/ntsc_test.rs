@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use nesemu_ppu::ntsc::{NtscFilter, NTSC_WIDTH};
+    use nesemu_ppu::palette::{NtscPaletteParams, Palette};
+    use nesemu_ppu::ppu::{HEIGHT, WIDTH};
+
+    fn pixel(rgba: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let n = (y * NTSC_WIDTH + x) * 4;
+        [rgba[n], rgba[n + 1], rgba[n + 2]]
+    }
+
+    #[test]
+    fn makes_a_wider_frame() {
+        let frame = vec![0x0F; WIDTH * HEIGHT];
+        let rgba = NtscFilter::default().apply(&frame, 0);
+        assert_eq!(NTSC_WIDTH, 682);
+        assert_eq!(rgba.len(), NTSC_WIDTH * HEIGHT * 4);
+    }
+
+    #[test]
+    fn flat_colours_match_the_generated_palette() {
+        let filter = NtscFilter {
+            artifacts: 0.0,
+            ..Default::default()
+        };
+        let palette = Palette::generate(NtscPaletteParams::default());
+        for colour in [0x16, 0x2A, 0x30, 0x12 | 0x01 << 6] {
+            let frame = vec![colour; WIDTH * HEIGHT];
+            let rgba = filter.apply(&frame, 0);
+            let expected = palette.rgb(colour);
+            for (got, want) in pixel(&rgba, 300, 100).iter().zip(expected) {
+                assert!(
+                    got.abs_diff(want) <= 2,
+                    "{:02X}: {} vs {}",
+                    colour,
+                    got,
+                    want
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edges_get_colour_fringes() {
+        // white and black stripes, 2 pixels each
+        let frame: Vec<u16> = (0..WIDTH * HEIGHT)
+            .map(|n| if n & 2 == 0 { 0x30 } else { 0x0F })
+            .collect();
+        let fringed = NtscFilter::default().apply(&frame, 0);
+        let [r, g, b] = pixel(&fringed, 300, 100);
+        assert!(r.abs_diff(g) > 8 || g.abs_diff(b) > 8);
+
+        let clean = NtscFilter {
+            fringing: 0.0,
+            artifacts: 0.0,
+            ..Default::default()
+        }
+        .apply(&frame, 0);
+        let [r, g, b] = pixel(&clean, 300, 100);
+        assert!(r.abs_diff(g) <= 2 && g.abs_diff(b) <= 2);
+    }
+
+    #[test]
+    fn merging_fields_stops_the_crawl() {
+        let frame: Vec<u16> = (0..WIDTH * HEIGHT)
+            .map(|n| if n & 1 == 0 { 0x30 } else { 0x0F })
+            .collect();
+        let filter = NtscFilter::default();
+        assert_ne!(filter.apply(&frame, 0), filter.apply(&frame, 1));
+
+        let merged = NtscFilter {
+            merge_fields: true,
+            ..Default::default()
+        };
+        assert_eq!(merged.apply(&frame, 0), merged.apply(&frame, 1));
+    }
+}
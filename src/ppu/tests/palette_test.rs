@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use nesemu_ppu::palette::{NtscPaletteParams, Palette, PaletteError};
+
+    #[test]
+    fn default_palette_has_emphasis() {
+        let palette = Palette::default();
+        assert_eq!(palette.rgb(0x0F), [0, 0, 0]);
+        assert_eq!(palette.rgb(0x30), [236, 238, 236]);
+        // red emphasis dims green and blue
+        let [r, g, b] = palette.rgb(0x30 | 0x01 << 6);
+        assert_eq!(r, 236);
+        assert!(g < 238 && b < 236);
+    }
+
+    #[test]
+    fn loads_pal_files() {
+        let mut bytes: Vec<u8> = (0..64).flat_map(|c| [c, c, c]).collect();
+        let palette = Palette::from_pal(&bytes).unwrap();
+        assert_eq!(palette.rgb(0x21), [0x21, 0x21, 0x21]);
+        assert_eq!(palette.to_pal().len(), 1536);
+
+        let full = Palette::from_pal(&palette.to_pal()).unwrap();
+        assert!(full == palette);
+
+        bytes.pop();
+        assert!(matches!(
+            Palette::from_pal(&bytes),
+            Err(PaletteError::BadSize(191))
+        ));
+    }
+
+    #[test]
+    fn generated_palette_looks_right() {
+        let palette = Palette::generate(NtscPaletteParams::default());
+        let [r, g, b] = palette.rgb(0x0F);
+        assert!(r < 16 && g < 16 && b < 16);
+        let [r, g, b] = palette.rgb(0x30);
+        assert!(r > 230 && g > 230 && b > 230);
+        // $16 is red, $1A green, $12 blue
+        let [r, g, b] = palette.rgb(0x16);
+        assert!(r > g && r > b);
+        let [r, g, b] = palette.rgb(0x1A);
+        assert!(g > r && g > b);
+        let [r, g, b] = palette.rgb(0x12);
+        assert!(b > r && b > g);
+
+        // no saturation, no colour
+        let grey = Palette::generate(NtscPaletteParams {
+            saturation: 0.0,
+            ..Default::default()
+        });
+        let [r, g, b] = grey.rgb(0x16);
+        assert!(r.abs_diff(g) < 2 && g.abs_diff(b) < 2);
+    }
+
+    #[test]
+    fn converts_frames_to_rgba() {
+        let palette = Palette::default();
+        let rgba = palette.to_rgba(&[0x0F, 0x30]);
+        assert_eq!(rgba, vec![0, 0, 0, 255, 236, 238, 236, 255]);
+    }
+}
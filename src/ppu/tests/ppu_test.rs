@@ -0,0 +1,384 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use nesemu_core::{Mirroring, Region};
+    use nesemu_ppu::ppu::{PpuBus, PPU};
+
+    struct ChrRam {
+        chr: Vec<u8>,
+        mirroring: Mirroring,
+    }
+
+    impl PpuBus for ChrRam {
+        fn ppu_read(&self, addr: u16) -> u8 {
+            self.chr[addr as usize]
+        }
+
+        fn ppu_write(&mut self, addr: u16, data: u8) {
+            self.chr[addr as usize] = data;
+        }
+
+        fn mirroring(&self) -> Mirroring {
+            self.mirroring
+        }
+    }
+
+    fn setup(mirroring: Mirroring) -> PPU<ChrRam> {
+        let mut ppu = PPU::new();
+        ppu.cartridge = Some(Arc::new(RwLock::new(ChrRam {
+            chr: vec![0; 0x2000],
+            mirroring,
+        })));
+        ppu
+    }
+
+    fn set_addr(ppu: &mut PPU<ChrRam>, addr: u16) {
+        ppu.cpu_write(0x2006, (addr >> 8) as u8);
+        ppu.cpu_write(0x2006, addr as u8);
+    }
+
+    fn run_to(ppu: &mut PPU<ChrRam>, scanline: u16, dot: u16) {
+        while ppu.scanline != scanline || ppu.dot != dot {
+            ppu.clock();
+        }
+    }
+
+    #[test]
+    fn ppudata_reads_are_buffered_except_palette() {
+        let mut ppu = setup(Mirroring::Horizontal);
+        set_addr(&mut ppu, 0x2000);
+        ppu.cpu_write(0x2007, 0x11);
+        ppu.cpu_write(0x2007, 0x22);
+
+        set_addr(&mut ppu, 0x2000);
+        ppu.cpu_read(0x2007);
+        assert_eq!(ppu.cpu_read(0x2007), 0x11);
+        assert_eq!(ppu.cpu_read(0x2007), 0x22);
+
+        set_addr(&mut ppu, 0x3F10);
+        ppu.cpu_write(0x2007, 0x2A);
+        set_addr(&mut ppu, 0x3F00);
+        assert_eq!(ppu.cpu_read(0x2007) & 0x3F, 0x2A);
+    }
+
+    #[test]
+    fn increments_by_32_when_asked() {
+        let mut ppu = setup(Mirroring::Horizontal);
+        ppu.cpu_write(0x2000, 0x04);
+        set_addr(&mut ppu, 0x2000);
+        ppu.cpu_write(0x2007, 0x01);
+        ppu.cpu_write(0x2007, 0x02);
+        assert_eq!(ppu.read(0x2000), 0x01);
+        assert_eq!(ppu.read(0x2020), 0x02);
+    }
+
+    #[test]
+    fn nametables_follow_the_cartridge_mirroring() {
+        let mut ppu = setup(Mirroring::Horizontal);
+        ppu.write(0x2000, 0x55);
+        assert_eq!(ppu.read(0x2400), 0x55);
+        assert_eq!(ppu.read(0x2800), 0x00);
+
+        let mut ppu = setup(Mirroring::Vertical);
+        ppu.write(0x2000, 0x55);
+        assert_eq!(ppu.read(0x2800), 0x55);
+        assert_eq!(ppu.read(0x2400), 0x00);
+        // $3000-$3EFF mirrors the nametables
+        assert_eq!(ppu.read(0x3000), 0x55);
+    }
+
+    #[test]
+    fn scroll_and_address_share_the_write_toggle() {
+        let mut ppu = setup(Mirroring::Horizontal);
+        ppu.cpu_write(0x2005, 0x7D);
+        assert_eq!(ppu.x, 0x05);
+        assert_eq!(ppu.t & 0x001F, 0x0F);
+        // reading PPUSTATUS resets the toggle
+        ppu.cpu_read(0x2002);
+        ppu.cpu_write(0x2005, 0x00);
+        assert!(ppu.w);
+        ppu.cpu_write(0x2005, 0x5E);
+        assert_eq!(ppu.t, 0x6160);
+        assert!(!ppu.w);
+    }
+
+    #[test]
+    fn vblank_sets_status_and_nmi() {
+        let mut ppu = setup(Mirroring::Horizontal);
+        ppu.cpu_write(0x2000, 0x80);
+        run_to(&mut ppu, 241, 2);
+        assert!(ppu.take_nmi());
+        assert!(!ppu.take_nmi());
+        assert_eq!(ppu.peek_register(0x2002) & 0x80, 0x80);
+
+        // reading clears it
+        assert_eq!(ppu.cpu_read(0x2002) & 0x80, 0x80);
+        assert_eq!(ppu.cpu_read(0x2002) & 0x80, 0x00);
+
+        // and re-enabling NMI during vblank fires one if the flag is up
+        run_to(&mut ppu, 261, 0);
+        run_to(&mut ppu, 241, 2);
+        ppu.take_nmi();
+        ppu.cpu_write(0x2000, 0x00);
+        ppu.cpu_write(0x2000, 0x80);
+        assert!(ppu.take_nmi());
+
+        run_to(&mut ppu, 261, 2);
+        assert_eq!(ppu.peek_register(0x2002) & 0x80, 0x00);
+    }
+
+    /// Counts the dots from one frame start to the next, with rendering on
+    /// so the NTSC odd frame skip applies.
+    fn frame_lengths(region: Region) -> (u32, u32) {
+        let mut ppu = setup(Mirroring::Horizontal);
+        ppu.region = region;
+        ppu.cpu_write(0x2001, 0x08);
+        let mut lengths = [0; 2];
+        for length in lengths.iter_mut() {
+            let frame = ppu.frame_count;
+            while ppu.frame_count == frame {
+                ppu.clock();
+                *length += 1;
+            }
+        }
+        (lengths[0], lengths[1])
+    }
+
+    #[test]
+    fn regions_change_the_frame() {
+        assert_eq!(frame_lengths(Region::Ntsc), (341 * 262, 341 * 262 - 1));
+        assert_eq!(frame_lengths(Region::Pal), (341 * 312, 341 * 312));
+        assert_eq!(frame_lengths(Region::Dendy), (341 * 312, 341 * 312));
+
+        // the Dendy's vblank starts 50 lines later
+        let mut ppu = setup(Mirroring::Horizontal);
+        ppu.region = Region::Dendy;
+        run_to(&mut ppu, 241, 2);
+        assert_eq!(ppu.peek_register(0x2002) & 0x80, 0x00);
+        run_to(&mut ppu, 291, 2);
+        assert_eq!(ppu.peek_register(0x2002) & 0x80, 0x80);
+        run_to(&mut ppu, 311, 2);
+        assert_eq!(ppu.peek_register(0x2002) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn draws_the_background() {
+        let mut ppu = setup(Mirroring::Horizontal);
+        // tile 1 is solid colour 3
+        for row in 0..16 {
+            ppu.write(0x0010 + row, 0xFF);
+        }
+        ppu.write(0x2001, 0x01);
+        ppu.write(0x3F00, 0x0F);
+        ppu.write(0x3F03, 0x16);
+        // show the background everywhere, with red emphasis
+        ppu.cpu_write(0x2001, 0x2A);
+        ppu.cpu_read(0x2002);
+        ppu.cpu_write(0x2005, 0);
+        ppu.cpu_write(0x2005, 0);
+
+        // the pre-render line fetches the first two tiles
+        run_to(&mut ppu, 261, 0);
+        run_to(&mut ppu, 1, 0);
+        let emphasis = 0x20 << 1;
+        assert_eq!(ppu.frame[7], 0x0F | emphasis);
+        assert_eq!(ppu.frame[8], 0x16 | emphasis);
+        assert_eq!(ppu.frame[16], 0x0F | emphasis);
+    }
+
+    #[test]
+    fn fine_x_scroll_shifts_the_picture() {
+        let mut ppu = setup(Mirroring::Vertical);
+        for row in 0..8 {
+            ppu.write(0x0010 + row, 0xFF);
+        }
+        ppu.write(0x2001, 0x01);
+        // the next nametable across, seen once coarse X wraps
+        ppu.write(0x2400, 0x01);
+        ppu.write(0x3F01, 0x16);
+        ppu.cpu_write(0x2001, 0x0A);
+        ppu.cpu_write(0x2005, 3);
+        ppu.cpu_write(0x2005, 0);
+
+        run_to(&mut ppu, 261, 0);
+        run_to(&mut ppu, 1, 0);
+        assert_eq!(ppu.frame[4], 0x00);
+        assert_eq!(ppu.frame[5], 0x16);
+        assert_eq!(ppu.frame[12], 0x16);
+        assert_eq!(ppu.frame[13], 0x00);
+        assert_eq!(ppu.frame[252], 0x00);
+        assert_eq!(ppu.frame[253], 0x16);
+    }
+
+    #[test]
+    fn scroll_changes_take_effect_on_the_next_line() {
+        let mut ppu = setup(Mirroring::Vertical);
+        for row in 0..8 {
+            ppu.write(0x0010 + row, 0xFF);
+        }
+        ppu.write(0x2400, 0x01);
+        ppu.write(0x3F01, 0x16);
+        ppu.cpu_write(0x2001, 0x0A);
+
+        run_to(&mut ppu, 261, 0);
+        run_to(&mut ppu, 4, 100);
+        // a mid-frame split: switch to the right nametable
+        ppu.cpu_write(0x2000, 0x01);
+        run_to(&mut ppu, 6, 0);
+        assert_eq!(ppu.frame[4 * 256], 0x00);
+        assert_eq!(ppu.frame[5 * 256], 0x16);
+    }
+
+    /// Tile 1 solid colour 1, tile 2 only its leftmost column, backdrop
+    /// $0F, background colour $16 and sprite colour $2A.
+    fn sprite_setup() -> PPU<ChrRam> {
+        let mut ppu = setup(Mirroring::Horizontal);
+        for row in 0..8 {
+            ppu.write(0x0010 + row, 0xFF);
+            ppu.write(0x0020 + row, 0x80);
+        }
+        ppu.write(0x3F00, 0x0F);
+        ppu.write(0x3F01, 0x16);
+        ppu.write(0x3F11, 0x2A);
+        ppu
+    }
+
+    fn sprite(ppu: &mut PPU<ChrRam>, index: usize, y: u8, tile: u8, attribute: u8, x: u8) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, tile, attribute, x]);
+    }
+
+    fn render_frame(ppu: &mut PPU<ChrRam>) {
+        run_to(ppu, 261, 0);
+        run_to(ppu, 240, 0);
+    }
+
+    #[test]
+    fn sprites_appear_a_line_below_their_y() {
+        let mut ppu = sprite_setup();
+        sprite(&mut ppu, 0, 10, 1, 0x00, 20);
+        ppu.cpu_write(0x2001, 0x1E);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.frame[10 * 256 + 20], 0x0F);
+        assert_eq!(ppu.frame[11 * 256 + 19], 0x0F);
+        assert_eq!(ppu.frame[11 * 256 + 20], 0x2A);
+        assert_eq!(ppu.frame[18 * 256 + 27], 0x2A);
+        assert_eq!(ppu.frame[19 * 256 + 20], 0x0F);
+    }
+
+    #[test]
+    fn sprites_can_go_behind_the_background() {
+        let mut ppu = sprite_setup();
+        for row in 0..4 {
+            ppu.write(0x2000 + row * 32, 0x01);
+        }
+        // one in front and one behind, both over tile 0
+        sprite(&mut ppu, 0, 0, 1, 0x00, 0);
+        sprite(&mut ppu, 1, 20, 1, 0x20, 0);
+        // and one behind a transparent part of the background
+        sprite(&mut ppu, 2, 20, 1, 0x20, 40);
+        ppu.cpu_write(0x2001, 0x1E);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.frame[256], 0x2A);
+        assert_eq!(ppu.frame[21 * 256], 0x16);
+        assert_eq!(ppu.frame[21 * 256 + 40], 0x2A);
+    }
+
+    #[test]
+    fn sprite_zero_hit() {
+        let mut ppu = sprite_setup();
+        for tile in 0..32 {
+            ppu.write(0x2000 + tile, 0x01);
+        }
+        sprite(&mut ppu, 0, 2, 2, 0x00, 100);
+        ppu.cpu_write(0x2001, 0x1E);
+        run_to(&mut ppu, 261, 0);
+        run_to(&mut ppu, 3, 100);
+        assert_eq!(ppu.status & 0x40, 0x00);
+        run_to(&mut ppu, 3, 102);
+        assert_eq!(ppu.status & 0x40, 0x40);
+        // cleared on the pre-render line
+        run_to(&mut ppu, 261, 2);
+        assert_eq!(ppu.status & 0x40, 0x00);
+
+        // never at x=255
+        sprite(&mut ppu, 0, 2, 2, 0x00, 255);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.status & 0x40, 0x00);
+
+        // nor in the left column when either side is clipped there
+        sprite(&mut ppu, 0, 2, 2, 0x00, 0);
+        ppu.cpu_write(0x2001, 0x1A);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.status & 0x40, 0x00);
+        ppu.cpu_write(0x2001, 0x1E);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.status & 0x40, 0x40);
+    }
+
+    #[test]
+    fn eight_sprites_per_line_unless_told_otherwise() {
+        let mut ppu = sprite_setup();
+        for index in 0..10 {
+            sprite(&mut ppu, index, 30, 1, 0x00, index as u8 * 10);
+        }
+        ppu.cpu_write(0x2001, 0x1E);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.frame[31 * 256 + 70], 0x2A);
+        assert_eq!(ppu.frame[31 * 256 + 80], 0x0F);
+        assert_eq!(ppu.status & 0x20, 0x20);
+
+        ppu.unlimited_sprites = true;
+        render_frame(&mut ppu);
+        assert_eq!(ppu.frame[31 * 256 + 90], 0x2A);
+        assert_eq!(ppu.status & 0x20, 0x20);
+    }
+
+    #[test]
+    fn overflow_checks_the_wrong_bytes() {
+        let mut ppu = sprite_setup();
+        for index in 0..8 {
+            sprite(&mut ppu, index, 30, 1, 0x00, 0);
+        }
+        // the ninth sprite is out of range, so the tenth gets its tile
+        // number compared instead of its Y
+        sprite(&mut ppu, 8, 200, 0, 0x00, 0);
+        sprite(&mut ppu, 9, 200, 30, 0x00, 0);
+        for index in 10..64 {
+            sprite(&mut ppu, index, 0xF0, 0, 0x00, 0);
+        }
+        ppu.cpu_write(0x2001, 0x18);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.status & 0x20, 0x20);
+
+        // while a real ninth sprite further down is missed, as its X gets
+        // checked instead
+        sprite(&mut ppu, 9, 200, 0, 0x00, 0);
+        sprite(&mut ppu, 11, 30, 0, 0x00, 0);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.status & 0x20, 0x00);
+    }
+
+    #[test]
+    fn tall_sprites_use_two_tiles() {
+        let mut ppu = sprite_setup();
+        ppu.cpu_write(0x2000, 0x20);
+        // tiles 2 and 3, from the table picked by bit 0
+        sprite(&mut ppu, 0, 50, 0x02, 0x00, 60);
+        ppu.cpu_write(0x2001, 0x1E);
+        for row in 0..8 {
+            ppu.write(0x0030 + row, 0x01);
+        }
+        render_frame(&mut ppu);
+        assert_eq!(ppu.frame[51 * 256 + 60], 0x2A);
+        assert_eq!(ppu.frame[59 * 256 + 60], 0x0F);
+        assert_eq!(ppu.frame[59 * 256 + 67], 0x2A);
+
+        // flipped both ways
+        sprite(&mut ppu, 0, 50, 0x02, 0xC0, 60);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.frame[51 * 256 + 60], 0x2A);
+        assert_eq!(ppu.frame[59 * 256 + 67], 0x2A);
+        assert_eq!(ppu.frame[59 * 256 + 60], 0x0F);
+    }
+}
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use nesemu_core::Mirroring;
+    use nesemu_ppu::ppu::{PpuBus, PPU};
+    use nesemu_ppu::viewer::{NAMETABLE_WIDTH, PATTERN_WIDTH};
+
+    struct ChrRam {
+        chr: Vec<u8>,
+    }
+
+    impl PpuBus for ChrRam {
+        fn ppu_read(&self, addr: u16) -> u8 {
+            self.chr[addr as usize]
+        }
+
+        fn ppu_write(&mut self, addr: u16, data: u8) {
+            self.chr[addr as usize] = data;
+        }
+
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Vertical
+        }
+    }
+
+    /// Tile 1 of each table is solid colour 1, tile 2 has a single colour 3
+    /// pixel in its top left corner.
+    fn setup() -> PPU<ChrRam> {
+        let mut chr = vec![0; 0x2000];
+        for table in [0x0000, 0x1000] {
+            chr[table + 0x10..table + 0x18].fill(0xFF);
+            chr[table + 0x20] = 0x80;
+            chr[table + 0x28] = 0x80;
+        }
+        let mut ppu = PPU::new();
+        ppu.cartridge = Some(Arc::new(RwLock::new(ChrRam { chr })));
+        // $3F10/$3F14/... would overwrite $3F00/$3F04/...
+        for n in (0..32).filter(|n| n & 0x13 != 0x10) {
+            ppu.write(0x3F00 + n, n as u8);
+        }
+        ppu
+    }
+
+    #[test]
+    fn pattern_tables_use_the_chosen_palette() {
+        let ppu = setup();
+        let view = ppu.pattern_table_view(2);
+        // tile 0 is empty, so it shows the backdrop
+        assert_eq!(view[0], 0x00);
+        assert_eq!(view[8], 0x09);
+        assert_eq!(view[16], 0x0B);
+        assert_eq!(view[17], 0x00);
+        // the second table starts half way across
+        assert_eq!(view[128 + 8], 0x09);
+        assert_eq!(view[PATTERN_WIDTH + 8], 0x09);
+    }
+
+    #[test]
+    fn nametables_use_their_attributes() {
+        let mut ppu = setup();
+        // tile 1 at the top left of the right hand table, bottom right
+        // quadrant of its attribute byte set to palette 3
+        ppu.write(0x2400, 0x01);
+        ppu.write(0x2400 + 2 * 32 + 2, 0x01);
+        ppu.write(0x27C0, 0b11_00_00_00);
+        let view = ppu.nametable_view();
+        assert_eq!(view[256], 0x01);
+        assert_eq!(view[16 * NAMETABLE_WIDTH + 256 + 16], 0x0D);
+        // vertical mirroring puts the same table underneath
+        assert_eq!(view[240 * NAMETABLE_WIDTH + 256], 0x01);
+    }
+
+    #[test]
+    fn scroll_position_comes_from_t() {
+        let mut ppu = setup();
+        ppu.cpu_write(0x2000, 0x01);
+        ppu.cpu_write(0x2005, 13);
+        ppu.cpu_write(0x2005, 100);
+        assert_eq!(ppu.scroll_position(), (256 + 13, 100));
+    }
+
+    #[test]
+    fn sprites_are_decoded_and_drawn() {
+        let mut ppu = setup();
+        ppu.oam[4..8].copy_from_slice(&[0x20, 0x02, 0x41, 0x30]);
+        let sprite = ppu.sprites()[1];
+        assert_eq!((sprite.x, sprite.y, sprite.tile), (0x30, 0x20, 0x02));
+        assert_eq!(sprite.palette, 1);
+        assert!(sprite.flip_horizontal && !sprite.flip_vertical);
+        assert!(!sprite.behind_background);
+
+        // flipped, so the dot ends up in the top right, in palette 5
+        let view = ppu.sprite_view(&sprite);
+        assert_eq!(view.len(), 64);
+        assert_eq!(view[7], 0x17);
+        assert_eq!(view[0], 0x00);
+
+        // tall sprites take their table from bit 0 of the tile
+        ppu.cpu_write(0x2000, 0x20);
+        ppu.oam[1] = 0x01;
+        let sprite = ppu.sprites()[0];
+        assert_eq!(sprite.pattern_addr, 0x1000);
+        let view = ppu.sprite_view(&sprite);
+        assert_eq!(view.len(), 128);
+        // top half is tile 0, bottom half tile 1
+        assert_eq!(view[0], 0x00);
+        assert_eq!(view[8 * 8], 0x11);
+    }
+
+    #[test]
+    fn palette_ram_reads_back_mirrored() {
+        let ppu = setup();
+        let ram = ppu.palette_ram();
+        assert_eq!(ram[0x11], 0x11);
+        assert_eq!(ram[0x10], 0x00);
+    }
+}